@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The params of one AnkiConnect action, tied to that action so a request can't be
+/// sent with another action's params.
+pub trait ActionParams: Serialize {
+    const ACTION: Action;
+}
+
+/// A request envelope whose action is decided by the type of its params.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::action::ActionRequest;
+/// # use anki_direct::notes::FindNotesParams;
+/// let request = ActionRequest::new(6, FindNotesParams { query: "deck:Mining".into() });
+/// assert_eq!(
+///     serde_json::to_value(&request).unwrap(),
+///     serde_json::json!({ "action": "findNotes", "version": 6, "params": { "query": "deck:Mining" } })
+/// );
+/// ```
+#[derive(Serialize, Debug, Clone)]
+pub struct ActionRequest<P> {
+    action: Action,
+    version: u8,
+    params: P,
+}
+
+impl<P: ActionParams> ActionRequest<P> {
+    pub fn new(version: u8, params: P) -> Self {
+        Self {
+            action: P::ACTION,
+            version,
+            params,
+        }
+    }
+
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn params(&self) -> &P {
+        &self.params
+    }
+}
+
+macro_rules! actions {
+    ($($variant:ident => $name:literal $(since $min:literal)?,)*) => {
+        /// An AnkiConnect action, serialized as its name.
+        ///
+        /// Actions this crate doesn't know yet can be sent as `Action::Custom`;
+        /// converting a known name, e.g. `Action::from("findNotes")`, always gives the
+        /// named variant instead.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum Action {
+            $($variant,)*
+            Custom(String),
+        }
+
+        impl Action {
+            /// Every named action, without `Custom`.
+            pub const ALL: &'static [Action] = &[$(Action::$variant,)*];
+
+            /// The name AnkiConnect knows the action by.
+            pub fn name(&self) -> &str {
+                match self {
+                    $(Action::$variant => $name,)*
+                    Action::Custom(name) => name,
+                }
+            }
+
+            /// The oldest AnkiConnect API version the action is known to work with.
+            ///
+            /// `None` for actions that have been available since before version 5,
+            /// and for `Custom` actions; those are never checked.
+            pub fn min_version(&self) -> Option<u8> {
+                match self {
+                    $($(Action::$variant => Some($min),)?)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    AddNote => "addNote",
+    AddNotes => "addNotes",
+    AddTags => "addTags",
+    AnswerCards => "answerCards" since 6,
+    AreDue => "areDue",
+    AreSuspended => "areSuspended",
+    CardReviews => "cardReviews" since 6,
+    CardsInfo => "cardsInfo",
+    CardsModTime => "cardsModTime" since 6,
+    ChangeDeck => "changeDeck",
+    CreateModel => "createModel",
+    DeckNames => "deckNames",
+    DeckNamesAndIds => "deckNamesAndIds",
+    DeleteMediaFile => "deleteMediaFile",
+    DeleteNotes => "deleteNotes",
+    ExportPackage => "exportPackage" since 6,
+    FindCards => "findCards",
+    FindModelsByName => "findModelsByName",
+    FindNotes => "findNotes",
+    ForgetCards => "forgetCards",
+    GetActiveProfile => "getActiveProfile" since 6,
+    GetCollectionStatsHtml => "getCollectionStatsHTML" since 6,
+    GetDeckConfig => "getDeckConfig",
+    GetEaseFactors => "getEaseFactors",
+    GetLatestReviewId => "getLatestReviewID" since 6,
+    GetMediaDirPath => "getMediaDirPath" since 6,
+    GetMediaFilesNames => "getMediaFilesNames" since 6,
+    GetNumCardsReviewedByDay => "getNumCardsReviewedByDay" since 6,
+    GetNumCardsReviewedToday => "getNumCardsReviewedToday",
+    GetProfiles => "getProfiles",
+    GetTags => "getTags",
+    GuiAnswerCard => "guiAnswerCard",
+    GuiBrowse => "guiBrowse",
+    GuiCheckDatabase => "guiCheckDatabase" since 6,
+    GuiDeckBrowser => "guiDeckBrowser",
+    GuiDeckOverview => "guiDeckOverview",
+    GuiDeckReview => "guiDeckReview",
+    GuiEditNote => "guiEditNote",
+    GuiExitAnki => "guiExitAnki" since 6,
+    GuiShowAnswer => "guiShowAnswer",
+    GuiShowQuestion => "guiShowQuestion",
+    GuiStartCardTimer => "guiStartCardTimer",
+    InsertReviews => "insertReviews" since 6,
+    LoadProfile => "loadProfile" since 6,
+    ModelFieldAdd => "modelFieldAdd" since 6,
+    ModelFieldNames => "modelFieldNames",
+    ModelFieldRemove => "modelFieldRemove" since 6,
+    ModelFieldRename => "modelFieldRename" since 6,
+    ModelFieldReposition => "modelFieldReposition" since 6,
+    ModelNames => "modelNames",
+    ModelTemplateAdd => "modelTemplateAdd" since 6,
+    Multi => "multi",
+    NotesInfo => "notesInfo",
+    NotesModTime => "notesModTime" since 6,
+    RelearnCards => "relearnCards",
+    ReloadCollection => "reloadCollection" since 6,
+    RemoveTags => "removeTags",
+    RetrieveMediaFile => "retrieveMediaFile",
+    StoreMediaFile => "storeMediaFile",
+    Suspend => "suspend",
+    UpdateNote => "updateNote" since 6,
+    Sync => "sync",
+    Unsuspend => "unsuspend",
+    UpdateModelStyling => "updateModelStyling",
+    UpdateModelTemplates => "updateModelTemplates",
+    Version => "version",
+}
+
+impl From<&str> for Action {
+    fn from(name: &str) -> Self {
+        match Action::ALL.iter().find(|action| action.name() == name) {
+            Some(action) => action.clone(),
+            None => Action::Custom(name.to_string()),
+        }
+    }
+}
+
+impl From<String> for Action {
+    fn from(name: String) -> Self {
+        match Action::ALL.iter().find(|action| action.name() == name) {
+            Some(action) => action.clone(),
+            None => Action::Custom(name),
+        }
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Custom(name) => name,
+            action => action.name().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,287 @@
+use crate::action::Action;
+use crate::error::AnkiError;
+use crate::id::{CardId, NoteId};
+use crate::result::{CardInfo, GenericResult, NotesInfoData};
+use crate::{post_generic_request, AnkiClient};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Several actions sent to AnkiConnect in one `multi` request.
+///
+/// Each queued action returns a `BatchItem` handle; after `send`, the handles pull
+/// their typed results out of the `BatchResults`. One action failing doesn't fail the others.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut batch = client.batch();
+/// let new = batch.find_notes("is:new");
+/// let due = batch.find_cards("is:due");
+/// let mut results = batch.send().await?;
+/// let new_ids = results.take(new)?;
+/// let due_ids = results.take(due)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Batch<'a> {
+    anki_client: &'a AnkiClient,
+    actions: Vec<Value>,
+}
+
+/// A handle to the result of one action queued in a `Batch`.
+#[derive(Debug)]
+pub struct BatchItem<T> {
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+/// The raw results of a sent `Batch`, one per queued action.
+#[derive(Debug)]
+pub struct BatchResults {
+    results: Vec<Option<GenericResult<Value>>>,
+}
+
+/// The outcome of a bulk helper that keeps going when single items fail, so one bad
+/// row doesn't abort a whole import.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::batch::BatchOutcome;
+/// # use anki_direct::error::AnkiError;
+/// # use std::time::Duration;
+/// let outcome = BatchOutcome::from_results(
+///     vec![Ok("a.mp3"), Err(AnkiError::NoDataFound), Ok("c.mp3")],
+///     Duration::from_millis(40),
+/// );
+/// assert_eq!(outcome.succeeded, vec![(0, "a.mp3"), (2, "c.mp3")]);
+/// assert_eq!(outcome.failed[0].index, 1);
+/// ```
+#[derive(Debug)]
+pub struct BatchOutcome<T> {
+    /// The results of the items that succeeded, with their index in the input.
+    pub succeeded: Vec<(usize, T)>,
+    /// The items that failed, in input order.
+    pub failed: Vec<BatchFailure>,
+    /// How long the whole batch took.
+    pub elapsed: Duration,
+}
+
+/// An item of a bulk helper that failed.
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// The item's index in the input.
+    pub index: usize,
+    pub error: AnkiError,
+}
+
+impl<T> BatchOutcome<T> {
+    /// Sorts per-item results, given in input order, into successes and failures.
+    pub fn from_results(
+        results: impl IntoIterator<Item = Result<T, AnkiError>>,
+        elapsed: Duration,
+    ) -> Self {
+        let mut outcome = BatchOutcome {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+            elapsed,
+        };
+        for (index, res) in results.into_iter().enumerate() {
+            match res {
+                Ok(value) => outcome.succeeded.push((index, value)),
+                Err(error) => outcome.failed.push(BatchFailure { index, error }),
+            }
+        }
+        outcome
+    }
+
+    /// Whether every item succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Number of items, successful or not.
+    pub fn len(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The per-item results back in input order.
+    pub fn into_results(self) -> Vec<Result<T, AnkiError>> {
+        let mut results: Vec<(usize, Result<T, AnkiError>)> = self
+            .succeeded
+            .into_iter()
+            .map(|(index, value)| (index, Ok(value)))
+            .chain(self.failed.into_iter().map(|f| (f.index, Err(f.error))))
+            .collect();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+}
+
+impl AnkiClient {
+    /// Starts a batch of actions that will be sent in a single request.
+    pub fn batch(&self) -> Batch<'_> {
+        Batch {
+            anki_client: self,
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Batch<'a> {
+    /// Queues an action, given as an `Action` or by name. `params` is omitted from the
+    /// request when `None`.
+    pub fn action<T: DeserializeOwned>(
+        &mut self,
+        action: impl Into<Action>,
+        params: Option<Value>,
+    ) -> BatchItem<T> {
+        let mut payload = serde_json::json!({
+            "action": action.into(),
+            "version": self.anki_client.version,
+        });
+        if let Some(params) = params {
+            payload["params"] = params;
+        }
+        self.queue(payload)
+    }
+
+    /// Queues an already built action payload, such as an `ActionRequest`.
+    pub fn push<T: DeserializeOwned>(
+        &mut self,
+        payload: &impl Serialize,
+    ) -> Result<BatchItem<T>, AnkiError> {
+        match serde_json::to_value(payload) {
+            Ok(payload) => Ok(self.queue(payload)),
+            Err(e) => Err(AnkiError::ParseError(e.to_string())),
+        }
+    }
+
+    /// Queues a `findNotes` search.
+    pub fn find_notes(&mut self, query: &str) -> BatchItem<Vec<NoteId>> {
+        self.action(
+            Action::FindNotes,
+            Some(serde_json::json!({ "query": query })),
+        )
+    }
+
+    /// Queues a `notesInfo` lookup.
+    pub fn notes_info(&mut self, ids: &[NoteId]) -> BatchItem<Vec<NotesInfoData>> {
+        self.action(Action::NotesInfo, Some(serde_json::json!({ "notes": ids })))
+    }
+
+    /// Queues a `findCards` search.
+    pub fn find_cards(&mut self, query: &str) -> BatchItem<Vec<CardId>> {
+        self.action(
+            Action::FindCards,
+            Some(serde_json::json!({ "query": query })),
+        )
+    }
+
+    /// Queues a `cardsInfo` lookup.
+    pub fn cards_info(&mut self, ids: &[CardId]) -> BatchItem<Vec<CardInfo>> {
+        self.action(Action::CardsInfo, Some(serde_json::json!({ "cards": ids })))
+    }
+
+    /// Number of queued actions.
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Sends every queued action in one `multi` request.
+    pub async fn send(self) -> Result<BatchResults, AnkiError> {
+        if self.actions.is_empty() {
+            return Ok(BatchResults {
+                results: Vec::new(),
+            });
+        }
+
+        for action in &self.actions {
+            if let Some(name) = action["action"].as_str() {
+                self.anki_client.check_version(name).await?;
+            }
+        }
+
+        let expected = self.actions.len();
+        let payload = self.payload();
+        let results: Vec<GenericResult<Value>> = post_generic_request(&payload, self.anki_client)
+            .await?
+            .into_result()?;
+
+        match results.len() == expected {
+            true => Ok(BatchResults::new(results)),
+            false => Err(AnkiError::ParseError(format!(
+                "multi returned {} results for {} actions",
+                results.len(),
+                expected
+            ))),
+        }
+    }
+
+    pub(crate) fn payload(&self) -> Value {
+        serde_json::json!({
+            "action": Action::Multi,
+            "version": self.anki_client.version,
+            "params": { "actions": self.actions },
+        })
+    }
+
+    fn queue<T>(&mut self, payload: Value) -> BatchItem<T> {
+        self.actions.push(payload);
+        BatchItem {
+            index: self.actions.len() - 1,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl BatchResults {
+    pub(crate) fn new(results: Vec<GenericResult<Value>>) -> Self {
+        Self {
+            results: results.into_iter().map(Some).collect(),
+        }
+    }
+
+    /// Takes the result of one queued action.
+    ///
+    /// Errors the same way the single-action call would; taking the same item twice
+    /// returns `NoDataFound`.
+    pub fn take<T: DeserializeOwned>(&mut self, item: BatchItem<T>) -> Result<T, AnkiError> {
+        let Some(res) = self.results.get_mut(item.index).and_then(Option::take) else {
+            return Err(AnkiError::NoDataFound);
+        };
+        let value = res.into_result()?;
+        match serde_json::from_value(value) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(AnkiError::ParseError(e.to_string())),
+        }
+    }
+
+    /// Takes the result of a queued action that returns `null` on success, like `addTags`.
+    pub fn take_unit(&mut self, item: BatchItem<()>) -> Result<(), AnkiError> {
+        match self.results.get_mut(item.index).and_then(Option::take) {
+            Some(res) => res.into_unit(),
+            None => Err(AnkiError::NoDataFound),
+        }
+    }
+
+    /// Number of results not yet taken.
+    pub fn remaining(&self) -> usize {
+        self.results.iter().filter(|r| r.is_some()).count()
+    }
+}
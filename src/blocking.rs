@@ -0,0 +1,141 @@
+use crate::error::AnkiError;
+use crate::models::{
+    CardTemplate, CreateModelParams, MigrationPlan, ModelAction, ModelSpec, TemplateSides,
+};
+use crate::notes::{NewNote, NoteAction};
+use crate::result::{FullModelDetails, NoteModTime, NotesInfoBuffer, NotesInfoData};
+use crate::template::TemplateIssue;
+use crate::{AnkiClient, NoteId};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use tokio::runtime::{Builder, Runtime};
+
+/// Generates a blocking proxy that forwards each listed method to the async action of
+/// the same name, so a signature that drifts from the async one fails to compile.
+macro_rules! blocking_proxy {
+    (
+        $(#[$doc:meta])*
+        $proxy:ident => $action:ident {
+            $(fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*
+        }
+    ) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $proxy<'a>(&'a BlockingClient);
+
+        impl $proxy<'_> {
+            $(
+                #[doc = concat!("Blocking `", stringify!($action), "::", stringify!($name), "`.")]
+                pub fn $name(&self, $($arg: $ty),*) -> Result<$ret, AnkiError> {
+                    self.0.call(move |c| $action::$name(c, $($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+/// A synchronous front for `AnkiClient`, for programs that don't run an async runtime.
+///
+/// The note and model actions are mirrored by `notes()` and `models()`, generated
+/// from the async signatures; `call` runs any other async action to completion on
+/// the same private runtime. It must not be used from inside an async runtime,
+/// where blocking would panic.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::blocking::BlockingClient;
+/// # use anki_direct::notes::NoteAction;
+/// # fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = BlockingClient::new()?;
+/// let ids = client.notes().find_note_ids("is:new")?;
+/// let infos = client.call(|c| NoteAction::get_notes_infos(c, ids))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BlockingClient {
+    anki_client: AnkiClient,
+    runtime: Runtime,
+}
+
+impl BlockingClient {
+    /// Creates a blocking client with `AnkiClient::default()` settings.
+    pub fn new() -> Result<Self, AnkiError> {
+        BlockingClient::from_client(AnkiClient::default())
+    }
+
+    /// Wraps an already configured `AnkiClient`.
+    pub fn from_client(anki_client: AnkiClient) -> Result<Self, AnkiError> {
+        let runtime = match Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => return Err(AnkiError::RequestError(e.to_string())),
+        };
+        Ok(Self {
+            anki_client,
+            runtime,
+        })
+    }
+
+    /// The wrapped async client.
+    pub fn client(&self) -> &AnkiClient {
+        &self.anki_client
+    }
+
+    /// The blocking note actions.
+    pub fn notes(&self) -> BlockingNotes<'_> {
+        BlockingNotes(self)
+    }
+
+    /// The blocking model actions.
+    pub fn models(&self) -> BlockingModels<'_> {
+        BlockingModels(self)
+    }
+
+    /// Runs an async action to completion and returns its result.
+    pub fn call<'a, F, Fut, T>(&'a self, action: F) -> T
+    where
+        F: FnOnce(&'a AnkiClient) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.runtime.block_on(action(&self.anki_client))
+    }
+}
+
+blocking_proxy! {
+    /// `NoteAction` for a `BlockingClient`.
+    BlockingNotes => NoteAction {
+        fn find_note_ids(query: &str) -> Vec<NoteId>;
+        fn get_notes_infos(ids: Vec<NoteId>) -> Vec<NotesInfoData>;
+        fn notes_infos_borrowed(ids: Vec<NoteId>) -> NotesInfoBuffer;
+        fn notes_mod_time(ids: Vec<NoteId>) -> Vec<NoteModTime>;
+        fn add_note(note: NewNote) -> NoteId;
+        fn add_notes(notes: Vec<NewNote>) -> Vec<Option<NoteId>>;
+        fn get_tags() -> Vec<String>;
+        fn gui_edit_note(id: NoteId) -> ();
+    }
+}
+
+blocking_proxy! {
+    /// `ModelAction` for a `BlockingClient`.
+    BlockingModels => ModelAction {
+        fn model_names() -> Vec<String>;
+        fn find_by_name(model: &str) -> FullModelDetails;
+        fn find_by_names(models: Vec<String>) -> Vec<FullModelDetails>;
+        fn create_model(params: CreateModelParams) -> FullModelDetails;
+        fn clone_model(source: &str, new_name: &str) -> FullModelDetails;
+        fn export_json(model: &str) -> Value;
+        fn import_json(value: Value) -> MigrationPlan;
+        fn validate_templates(model: &str) -> Vec<TemplateIssue>;
+        fn field_names(model: &str) -> Vec<String>;
+        fn field_add(model: &str, field: &str, index: Option<usize>) -> ();
+        fn field_remove(model: &str, field: &str) -> ();
+        fn field_rename(model: &str, old_name: &str, new_name: &str) -> ();
+        fn field_reposition(model: &str, field: &str, index: usize) -> ();
+        fn update_templates(model: &str, templates: HashMap<String, TemplateSides>) -> ();
+        fn update_styling(model: &str, css: &str) -> ();
+        fn template_add(model: &str, template: CardTemplate) -> ();
+        fn ensure_model(spec: &ModelSpec, dry_run: bool) -> MigrationPlan;
+    }
+}
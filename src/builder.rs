@@ -0,0 +1,248 @@
+use crate::chunk::ChunkPolicy;
+use crate::error::AnkiError;
+use crate::limit::RateLimit;
+use crate::middleware::Middleware;
+use crate::payload::{Compression, DEFAULT_MAX_PAYLOAD_BYTES};
+use crate::query::QueryPresets;
+use crate::{parse_endpoint, AnkiClient};
+use reqwest::Client;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{Certificate, Proxy};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builds an `AnkiClient` with custom HTTP settings, for networks where the
+/// defaults of `AnkiClient::default()` don't work.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::AnkiClient;
+/// # use std::time::Duration;
+/// let client = AnkiClient::builder()
+///     .endpoint("https://anki.my-lan:8765")
+///     .connect_timeout(Duration::from_secs(2))
+///     .timeout(Duration::from_secs(30))
+///     .user_agent("my-tool/1.0")
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct AnkiClientBuilder {
+    endpoint: Option<String>,
+    version: Option<u8>,
+    api_key: Option<String>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    proxy: Option<String>,
+    no_proxy: bool,
+    user_agent: Option<String>,
+    accept_invalid_certs: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    root_certificates: Vec<Certificate>,
+    client: Option<Client>,
+    rate_limit: Option<RateLimit>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    compression: Compression,
+    max_payload_bytes: Option<Option<usize>>,
+    chunking: Option<Option<ChunkPolicy>>,
+}
+
+impl AnkiClient {
+    /// Starts building a client with custom settings.
+    pub fn builder() -> AnkiClientBuilder {
+        AnkiClientBuilder::default()
+    }
+}
+
+impl AnkiClientBuilder {
+    /// The AnkiConnect URL. Defaults to `http://localhost:8765`.
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    /// The AnkiConnect API version sent with requests. Defaults to `6`.
+    pub fn version(mut self, version: u8) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// The `apiKey` configured in AnkiConnect.
+    pub fn api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    /// How long to wait for the connection to AnkiConnect to open.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// How long a whole request may take, including reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sends every request through a proxy, e.g. `http://proxy.corp:3128`.
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Ignores proxies configured in the environment, such as `HTTP_PROXY`.
+    pub fn no_proxy(mut self) -> Self {
+        self.no_proxy = true;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Trusts an extra root certificate, e.g. a private CA in front of AnkiConnect.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Accepts any TLS certificate, including self-signed and expired ones.
+    /// Only meant for testing against a local reverse proxy.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Uses a preconfigured `reqwest` client instead of building one.
+    ///
+    /// Can't be combined with the other HTTP settings of this builder.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Limits how many requests run at once and how closely they follow each other.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Adds a middleware run around every request. See `Middleware`.
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Encodes request bodies with `compression`. See `AnkiClient::with_compression`.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Splits `addNotes` requests larger than `limit` bytes. See
+    /// `AnkiClient::with_max_payload_bytes`.
+    pub fn max_payload_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_payload_bytes = Some(limit);
+        self
+    }
+
+    /// Splits actions taking long id lists into several requests. See
+    /// `AnkiClient::with_chunking`.
+    pub fn chunking(mut self, policy: Option<ChunkPolicy>) -> Self {
+        self.chunking = Some(policy);
+        self
+    }
+
+    pub fn build(self) -> Result<AnkiClient, AnkiError> {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => parse_endpoint(endpoint)?.to_string(),
+            None => AnkiClient::default().endpoint,
+        };
+        let version = self.version.unwrap_or(AnkiClient::default().version);
+
+        let customized = self.connect_timeout.is_some()
+            || self.timeout.is_some()
+            || self.proxy.is_some()
+            || self.no_proxy
+            || self.user_agent.is_some()
+            || self.accept_invalid_certs;
+        #[cfg(not(target_arch = "wasm32"))]
+        let customized = customized || !self.root_certificates.is_empty();
+
+        let client = match self.client {
+            Some(_) if customized => {
+                return Err(AnkiError::ValidationError(
+                    "HTTP settings can't be combined with a custom reqwest client".to_string(),
+                ))
+            }
+            Some(client) => client,
+            // The browser's fetch decides timeouts, proxies and TLS.
+            #[cfg(target_arch = "wasm32")]
+            None if customized => {
+                return Err(AnkiError::ValidationError(
+                    "HTTP settings aren't supported on wasm32".to_string(),
+                ))
+            }
+            #[cfg(target_arch = "wasm32")]
+            None => Client::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            None => {
+                let mut builder = Client::builder();
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(proxy) = &self.proxy {
+                    builder = match Proxy::all(proxy) {
+                        Ok(proxy) => builder.proxy(proxy),
+                        Err(e) => return Err(AnkiError::ValidationError(e.to_string())),
+                    };
+                }
+                if self.no_proxy {
+                    builder = builder.no_proxy();
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                for certificate in self.root_certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+                builder = builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+
+                match builder.build() {
+                    Ok(client) => client,
+                    Err(e) => return Err(AnkiError::RequestError(e.to_string())),
+                }
+            }
+        };
+
+        let client = AnkiClient {
+            endpoint,
+            client,
+            version,
+            api_key: self.api_key,
+            detected_version: Arc::default(),
+            limiter: None,
+            middleware: self.middleware,
+            metrics: None,
+            transport: None,
+            dry_run: None,
+            queries: QueryPresets::default(),
+            compression: self.compression,
+            max_payload_bytes: self
+                .max_payload_bytes
+                .unwrap_or(Some(DEFAULT_MAX_PAYLOAD_BYTES)),
+            chunking: self.chunking.unwrap_or(Some(ChunkPolicy::default())),
+        };
+        Ok(match self.rate_limit {
+            Some(limit) => client.with_rate_limit(limit),
+            None => client,
+        })
+    }
+}
@@ -0,0 +1,1551 @@
+use crate::action::{Action, ActionRequest};
+use crate::cards::CardAction;
+use crate::decks::{parse_deck_config, DeckAction, GetDeckConfigParams};
+use crate::error::AnkiError;
+use crate::id::{CardId, DeckId, NoteId};
+use crate::middleware::Middleware;
+use crate::models::ModelAction;
+use crate::notes::NoteAction;
+use crate::query::QueryPresets;
+use crate::result::{CardInfo, DeckConfig, FullModelDetails, NotesInfoData};
+use crate::{AnkiClient, Instant};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::Display;
+use std::future::Future;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// A part of the `Cache` that is hydrated from Anki on its own.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CacheSection {
+    Models,
+    Decks,
+    Tags,
+}
+
+impl CacheSection {
+    pub const ALL: [CacheSection; 3] = [
+        CacheSection::Models,
+        CacheSection::Decks,
+        CacheSection::Tags,
+    ];
+}
+
+impl Display for CacheSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheSection::Models => write!(f, "models"),
+            CacheSection::Decks => write!(f, "decks"),
+            CacheSection::Tags => write!(f, "tags"),
+        }
+    }
+}
+
+/// How a `Cache` is written to disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheFormat {
+    #[default]
+    Json,
+    /// Indented JSON, for caches meant to be read or diffed by people.
+    PrettyJson,
+    /// CBOR, a compact binary format that is faster to write and read than JSON.
+    #[cfg(feature = "cache-cbor")]
+    Cbor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheError {
+    /// The section was read before it was hydrated.
+    Dehydrated(CacheSection),
+}
+
+impl Error for CacheError {}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::Dehydrated(section) => write!(
+                f,
+                "the {} cache is empty; hydrate it or call `Cache::hydrate_all(..)` first",
+                section
+            ),
+        }
+    }
+}
+
+/// The cache sections an action can change, judged by its name alone.
+pub fn sections_changed_by(action: &str) -> &'static [CacheSection] {
+    match action {
+        "createDeck" | "deleteDecks" | "saveDeckConfig" | "setDeckConfigId"
+        | "cloneDeckConfigId" | "removeDeckConfigId" => &[CacheSection::Decks],
+        // These create the deck they name if it doesn't exist.
+        "changeDeck" | "cardReviews" | "getLatestReviewID" => &[CacheSection::Decks],
+        "importPackage" => &CacheSection::ALL,
+        "createModel"
+        | "updateModelTemplates"
+        | "updateModelStyling"
+        | "findAndReplaceInModels"
+        | "modelFieldAdd"
+        | "modelFieldRemove"
+        | "modelFieldRename"
+        | "modelFieldReposition"
+        | "modelFieldSetFont"
+        | "modelFieldSetFontSize"
+        | "modelFieldSetDescription"
+        | "modelTemplateAdd"
+        | "modelTemplateRemove"
+        | "modelTemplateRename"
+        | "modelTemplateReposition" => &[CacheSection::Models],
+        "addTags"
+        | "removeTags"
+        | "replaceTags"
+        | "replaceTagsInAllNotes"
+        | "clearUnusedTags"
+        | "updateNoteTags"
+        | "updateNote"
+        | "addNote"
+        | "addNotes" => &[CacheSection::Tags],
+        _ => &[],
+    }
+}
+
+/// Marks `Cache` sections as expired when a client sends an action that changes them,
+/// like `createDeck`, `modelFieldRename` or `addTags`.
+///
+/// Get one from `Cache::invalidation` and pass it to `AnkiClient::with_cache_invalidation`.
+/// Sections are marked when the request is sent, whether or not it succeeds, so
+/// `Cache::refresh_expired` refetches them afterwards. Every section is marked once a
+/// `loadProfile` request completes.
+#[derive(Debug, Clone, Default)]
+pub struct CacheInvalidation {
+    changed: Arc<Mutex<BTreeMap<CacheSection, DateTime<Utc>>>>,
+}
+
+impl CacheInvalidation {
+    pub fn invalidate(&self, section: CacheSection) {
+        let now = Utc::now();
+        match self.changed.lock() {
+            Ok(mut changed) => changed.insert(section, now),
+            Err(poisoned) => poisoned.into_inner().insert(section, now),
+        };
+    }
+
+    /// When an action last changed `section`, if one did.
+    pub fn changed_at(&self, section: CacheSection) -> Option<DateTime<Utc>> {
+        match self.changed.lock() {
+            Ok(changed) => changed.get(&section).copied(),
+            Err(poisoned) => poisoned.into_inner().get(&section).copied(),
+        }
+    }
+}
+
+impl Middleware for CacheInvalidation {
+    fn before(&self, action: &str, payload: &mut Value) {
+        let inner = payload["params"]["actions"].as_array();
+        let actions = match (action, inner) {
+            ("multi", Some(inner)) => inner.iter().filter_map(|a| a["action"].as_str()).collect(),
+            _ => vec![action],
+        };
+        for action in actions {
+            for section in sections_changed_by(action) {
+                self.invalidate(*section);
+            }
+        }
+    }
+
+    // Another profile is another collection, so nothing cached before the switch
+    // completes still holds.
+    fn after(&self, action: &str, _response: Result<&Value, &AnkiError>, _elapsed: Duration) {
+        if action == "loadProfile" {
+            for section in CacheSection::ALL {
+                self.invalidate(section);
+            }
+        }
+    }
+}
+
+impl AnkiClient {
+    /// Expires the sections of a `Cache` whenever this client changes what they hold.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::cache::Cache;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let mut cache = Cache::new();
+    /// let client = AnkiClient::default().with_cache_invalidation(cache.invalidation());
+    /// cache.hydrate_all(&client, true).await?;
+    /// // ... actions that add tags or change models ...
+    /// cache.refresh_expired(&client).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_cache_invalidation(self, invalidation: CacheInvalidation) -> Self {
+        self.with_middleware(invalidation)
+    }
+}
+
+/// Full model definitions, keyed by model name unless hydrated with `hydrate_by`.
+///
+/// # Example
+///
+/// Keying by id keeps entries stable when a model is renamed in Anki:
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::cache::ModelCache;
+/// # use anki_direct::ModelId;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut models = ModelCache::<ModelId>::default();
+/// models.hydrate_by(&client, |model| model.id).await?;
+/// let mapped = models.get(&ModelId(1_700_000_000_000)).unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelCache<K: Ord = String> {
+    models: BTreeMap<K, FullModelDetails>,
+    hydrated_at: Option<DateTime<Utc>>,
+}
+
+impl<K: Ord> Default for ModelCache<K> {
+    fn default() -> Self {
+        Self {
+            models: BTreeMap::new(),
+            hydrated_at: None,
+        }
+    }
+}
+
+impl ModelCache {
+    /// Replaces the cached models with every model in the collection, keyed by name.
+    pub async fn hydrate(&mut self, anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        self.hydrate_by(anki_client, |model| model.name.clone())
+            .await
+    }
+}
+
+impl<K: Ord> ModelCache<K> {
+    /// Replaces the cached models with every model in the collection, keyed by `key`.
+    pub async fn hydrate_by(
+        &mut self,
+        anki_client: &AnkiClient,
+        key: impl Fn(&FullModelDetails) -> K,
+    ) -> Result<(), AnkiError> {
+        let started = Utc::now();
+        let names = ModelAction::model_names(anki_client).await?;
+        let models = ModelAction::find_by_names(anki_client, names).await?;
+        self.models = models.into_iter().map(|m| (key(&m), m)).collect();
+        self.hydrated_at = Some(started);
+        Ok(())
+    }
+
+    /// The cached model under `key`, or `None` if the collection has no such model.
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<&FullModelDetails>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.check()?;
+        Ok(self.models.get(key))
+    }
+
+    pub fn keys(&self) -> Result<impl Iterator<Item = &K>, CacheError> {
+        self.check()?;
+        Ok(self.models.keys())
+    }
+
+    pub fn names(&self) -> Result<impl Iterator<Item = &str>, CacheError> {
+        self.check()?;
+        Ok(self.models.values().map(|m| m.name.as_str()))
+    }
+
+    /// When the models were last fetched, or `None` if they never were.
+    pub fn hydrated_at(&self) -> Option<DateTime<Utc>> {
+        self.hydrated_at
+    }
+
+    fn check(&self) -> Result<(), CacheError> {
+        match self.hydrated_at {
+            Some(_) => Ok(()),
+            None => Err(CacheError::Dehydrated(CacheSection::Models)),
+        }
+    }
+}
+
+/// A deck in a `DeckCache`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeckEntry {
+    pub name: String,
+    pub id: DeckId,
+    /// The deck's options group, if it was fetched with `hydrate_full`.
+    pub config: Option<DeckConfig>,
+}
+
+/// Decks, each with its options group once fetched, keyed by deck name unless
+/// hydrated with `hydrate_names_by` or `hydrate_full_by`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeckCache<K: Ord = String> {
+    decks: BTreeMap<K, DeckEntry>,
+    hydrated_at: Option<DateTime<Utc>>,
+    /// Whether the decks were last hydrated with their configs.
+    #[serde(default)]
+    with_configs: bool,
+}
+
+impl<K: Ord> Default for DeckCache<K> {
+    fn default() -> Self {
+        Self {
+            decks: BTreeMap::new(),
+            hydrated_at: None,
+            with_configs: false,
+        }
+    }
+}
+
+impl DeckCache {
+    /// Replaces the cached decks with every deck in the collection, keyed by name,
+    /// without their configs.
+    pub async fn hydrate_names(&mut self, anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        self.hydrate_names_by(anki_client, |deck| deck.name.clone())
+            .await
+    }
+
+    /// Replaces the cached decks with every deck in the collection and its options
+    /// group, keyed by name.
+    pub async fn hydrate_full(&mut self, anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        self.hydrate_full_by(anki_client, |deck| deck.name.clone())
+            .await
+    }
+}
+
+impl<K: Ord> DeckCache<K> {
+    /// Replaces the cached decks with every deck in the collection, keyed by `key`,
+    /// without their configs.
+    pub async fn hydrate_names_by(
+        &mut self,
+        anki_client: &AnkiClient,
+        key: impl Fn(&DeckEntry) -> K,
+    ) -> Result<(), AnkiError> {
+        let started = Utc::now();
+        let decks = fetch_decks(anki_client).await?;
+        self.decks = decks.into_iter().map(|d| (key(&d), d)).collect();
+        self.hydrated_at = Some(started);
+        self.with_configs = false;
+        Ok(())
+    }
+
+    /// Replaces the cached decks with every deck in the collection and its options
+    /// group, keyed by `key`. The configs are fetched in a single `multi` request.
+    pub async fn hydrate_full_by(
+        &mut self,
+        anki_client: &AnkiClient,
+        key: impl Fn(&DeckEntry) -> K,
+    ) -> Result<(), AnkiError> {
+        let started = Utc::now();
+        let mut decks = fetch_decks(anki_client).await?;
+        let mut batch = anki_client.batch();
+        let mut items = Vec::with_capacity(decks.len());
+        for deck in &decks {
+            let params = GetDeckConfigParams {
+                deck: deck.name.clone(),
+            };
+            let request = ActionRequest::new(anki_client.version, params);
+            items.push(batch.push::<Value>(&request)?);
+        }
+        let mut results = batch.send().await?;
+        for (deck, item) in decks.iter_mut().zip(items) {
+            deck.config = Some(parse_deck_config(results.take(item)?)?);
+        }
+
+        self.decks = decks.into_iter().map(|d| (key(&d), d)).collect();
+        self.hydrated_at = Some(started);
+        self.with_configs = true;
+        Ok(())
+    }
+
+    /// Whether the decks were hydrated with their configs, as by `hydrate_full`.
+    pub fn has_configs(&self) -> bool {
+        self.with_configs || self.decks.values().any(|d| d.config.is_some())
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<&DeckEntry>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.check()?;
+        Ok(self.decks.get(key))
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> Result<bool, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.check()?;
+        Ok(self.decks.contains_key(key))
+    }
+
+    /// The options group of the deck, or `None` if the deck or its config isn't cached.
+    pub fn config<Q>(&self, key: &Q) -> Result<Option<&DeckConfig>, CacheError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.check()?;
+        Ok(self.decks.get(key).and_then(|deck| deck.config.as_ref()))
+    }
+
+    pub fn keys(&self) -> Result<impl Iterator<Item = &K>, CacheError> {
+        self.check()?;
+        Ok(self.decks.keys())
+    }
+
+    pub fn names(&self) -> Result<impl Iterator<Item = &str>, CacheError> {
+        self.check()?;
+        Ok(self.decks.values().map(|d| d.name.as_str()))
+    }
+
+    /// When the decks were last fetched, or `None` if they never were.
+    pub fn hydrated_at(&self) -> Option<DateTime<Utc>> {
+        self.hydrated_at
+    }
+
+    fn check(&self) -> Result<(), CacheError> {
+        match self.hydrated_at {
+            Some(_) => Ok(()),
+            None => Err(CacheError::Dehydrated(CacheSection::Decks)),
+        }
+    }
+}
+
+async fn fetch_decks(anki_client: &AnkiClient) -> Result<Vec<DeckEntry>, AnkiError> {
+    let decks = DeckAction::deck_names_and_ids(anki_client).await?;
+    Ok(decks
+        .into_iter()
+        .map(|(name, id)| DeckEntry {
+            name,
+            id,
+            config: None,
+        })
+        .collect())
+}
+
+/// The collection's tags, for checking and completing tags without asking Anki.
+///
+/// Like in Anki, tags are compared case-insensitively.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TagCache {
+    /// Each tag keyed by its lowercase form.
+    tags: BTreeMap<String, String>,
+    hydrated_at: Option<DateTime<Utc>>,
+}
+
+impl TagCache {
+    /// Replaces the cached tags with every tag in the collection.
+    pub async fn hydrate(&mut self, anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        let started = Utc::now();
+        let tags = NoteAction::get_tags(anki_client).await?;
+        self.tags = tags.into_iter().map(|t| (t.to_lowercase(), t)).collect();
+        self.hydrated_at = Some(started);
+        Ok(())
+    }
+
+    pub fn contains(&self, tag: &str) -> Result<bool, CacheError> {
+        self.check()?;
+        Ok(self.tags.contains_key(&tag.to_lowercase()))
+    }
+
+    /// The tag as spelled in the collection, e.g. `Mining::Anime` for `mining::anime`,
+    /// or `None` if no such tag exists yet.
+    pub fn normalize(&self, tag: &str) -> Result<Option<&str>, CacheError> {
+        self.check()?;
+        Ok(self.tags.get(&tag.to_lowercase()).map(String::as_str))
+    }
+
+    /// Tags starting with `prefix`, in alphabetical order, for completion.
+    pub fn with_prefix(&self, prefix: &str) -> Result<Vec<&str>, CacheError> {
+        self.check()?;
+        let prefix = prefix.to_lowercase();
+        Ok(self
+            .tags
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, tag)| tag.as_str())
+            .collect())
+    }
+
+    pub fn iter(&self) -> Result<impl Iterator<Item = &str>, CacheError> {
+        self.check()?;
+        Ok(self.tags.values().map(String::as_str))
+    }
+
+    /// When the tags were last fetched, or `None` if they never were.
+    pub fn hydrated_at(&self) -> Option<DateTime<Utc>> {
+        self.hydrated_at
+    }
+
+    fn check(&self) -> Result<(), CacheError> {
+        match self.hydrated_at {
+            Some(_) => Ok(()),
+            None => Err(CacheError::Dehydrated(CacheSection::Tags)),
+        }
+    }
+}
+
+/// A size limit for a `NoteCache` or `CardCache`. Once it is exceeded, the least
+/// recently used entries are evicted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheCapacity {
+    Entries(usize),
+    /// Approximate bytes, counting the text of each entry plus a fixed overhead.
+    Bytes(usize),
+}
+
+/// Rough in-memory size of a cached entry, for `CacheCapacity::Bytes`.
+trait ApproxSize {
+    fn approx_size(&self) -> usize;
+}
+
+impl ApproxSize for NotesInfoData {
+    fn approx_size(&self) -> usize {
+        let fields: usize = self
+            .fields
+            .iter()
+            .map(|(k, v)| k.len() + v.value.len())
+            .sum();
+        let tags: usize = self.tags.iter().map(String::len).sum();
+        64 + self.modelName.len() + tags + fields
+    }
+}
+
+impl ApproxSize for CardInfo {
+    fn approx_size(&self) -> usize {
+        let fields: usize = self
+            .fields
+            .iter()
+            .map(|(k, v)| k.len() + v.value.len())
+            .sum();
+        128 + self.deckName.len()
+            + self.modelName.len()
+            + self.question.len()
+            + self.answer.len()
+            + self.css.len()
+            + fields
+    }
+}
+
+/// When each entry of a bounded cache was last used. Not persisted: entries loaded
+/// from disk count as least recently used, oldest id first.
+#[derive(Debug, Default)]
+struct Recency<K>(Mutex<RecencyState<K>>);
+
+#[derive(Debug, Default, Clone)]
+struct RecencyState<K> {
+    tick: u64,
+    used: HashMap<K, u64>,
+}
+
+impl<K: Copy + Ord + Hash> Clone for Recency<K> {
+    fn clone(&self) -> Self {
+        Recency(Mutex::new(self.state().clone()))
+    }
+}
+
+impl<K: Copy + Ord + Hash> Recency<K> {
+    fn state(&self) -> MutexGuard<'_, RecencyState<K>> {
+        match self.0.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn touch(&self, id: K) {
+        let mut state = self.state();
+        state.tick += 1;
+        let tick = state.tick;
+        state.used.insert(id, tick);
+    }
+
+    fn forget(&self, id: K) {
+        self.state().used.remove(&id);
+    }
+
+    /// Removes entries from `entries`, least recently used first, until it fits in
+    /// `capacity`, and returns the evicted ids.
+    fn evict<V: ApproxSize>(
+        &self,
+        entries: &mut BTreeMap<K, V>,
+        capacity: Option<CacheCapacity>,
+    ) -> Vec<K> {
+        let Some(capacity) = capacity else {
+            return Vec::new();
+        };
+        let mut state = self.state();
+        let mut by_use: Vec<(u64, K)> = entries
+            .keys()
+            .map(|id| (state.used.get(id).copied().unwrap_or(0), *id))
+            .collect();
+        by_use.sort_unstable();
+
+        let keep = match capacity {
+            CacheCapacity::Entries(max) => max.min(by_use.len()),
+            CacheCapacity::Bytes(max) => {
+                let mut total = 0;
+                by_use
+                    .iter()
+                    .rev()
+                    .take_while(|(_, id)| {
+                        total += entries[id].approx_size();
+                        total <= max
+                    })
+                    .count()
+            }
+        };
+        let evicted: Vec<K> = by_use[..by_use.len() - keep]
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        for id in &evicted {
+            entries.remove(id);
+            state.used.remove(id);
+        }
+        evicted
+    }
+}
+
+/// Notes that changed in a `NoteCache::refresh`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteChanges {
+    /// Notes that are new to the cache or were edited since they were cached.
+    pub updated: Vec<NoteId>,
+    /// Notes that were deleted or no longer match the query.
+    pub removed: Vec<NoteId>,
+}
+
+impl NoteChanges {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A local copy of the notes matching a search, keyed by note id.
+///
+/// `hydrate` fetches every matching note once. `refresh` then only downloads notes
+/// edited since the last refresh (found with an `edited:` search and confirmed with
+/// `notesModTime`), plus the ids of all matches to notice deletions.
+///
+/// With a `CacheCapacity`, the least recently used notes are evicted once the cache is
+/// full. Evicted notes are no longer refreshed; `get_or_fetch` loads them again.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::cache::NoteCache;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut notes = NoteCache::new("deck:Mining");
+/// notes.hydrate(&client).await?;
+/// loop {
+///     let changes = notes.refresh(&client).await?;
+///     for id in changes.updated {
+///         println!("{:?}", notes.get(id));
+///     }
+///     # break;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteCache {
+    query: String,
+    notes: BTreeMap<NoteId, NotesInfoData>,
+    /// Modification times in seconds, as reported by `notesModTime`.
+    modified: BTreeMap<NoteId, u64>,
+    /// Every note that matched the query at the last refresh, cached or not.
+    #[serde(default)]
+    matching: BTreeSet<NoteId>,
+    #[serde(default)]
+    capacity: Option<CacheCapacity>,
+    #[serde(skip)]
+    recency: Recency<NoteId>,
+    hydrated_at: Option<DateTime<Utc>>,
+}
+
+impl NoteCache {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            notes: BTreeMap::new(),
+            modified: BTreeMap::new(),
+            matching: BTreeSet::new(),
+            capacity: None,
+            recency: Recency::default(),
+            hydrated_at: None,
+        }
+    }
+
+    /// Limits how much the cache holds, evicting the least recently used notes.
+    pub fn with_capacity(mut self, capacity: CacheCapacity) -> Self {
+        self.capacity = Some(capacity);
+        self.evict();
+        self
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Replaces the cached notes with every note matching the query.
+    pub async fn hydrate(&mut self, anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        let started = Utc::now();
+        let ids = find_ids(anki_client, &self.query).await?;
+        self.notes.clear();
+        self.modified.clear();
+        self.matching = ids.iter().copied().collect();
+        self.fetch(anki_client, ids).await?;
+        self.hydrated_at = Some(started);
+        Ok(())
+    }
+
+    /// Brings the cache up to date, hydrating it first if it never was.
+    pub async fn refresh(&mut self, anki_client: &AnkiClient) -> Result<NoteChanges, AnkiError> {
+        let Some(hydrated_at) = self.hydrated_at else {
+            self.hydrate(anki_client).await?;
+            return Ok(NoteChanges {
+                updated: self.notes.keys().copied().collect(),
+                removed: Vec::new(),
+            });
+        };
+        let started = Utc::now();
+
+        let matching: BTreeSet<NoteId> = find_ids(anki_client, &self.query)
+            .await?
+            .into_iter()
+            .collect();
+        let removed: Vec<NoteId> = self.matching.difference(&matching).copied().collect();
+        for id in &removed {
+            self.notes.remove(id);
+            self.modified.remove(id);
+            self.recency.forget(*id);
+        }
+
+        // `edited:n` counts whole days, so ask for one more than has passed and
+        // filter by modification time. Evicted notes are left out.
+        let days = (started - hydrated_at).num_days() + 1;
+        let query = format!("({}) edited:{}", self.query, days);
+        let mut candidates = find_ids(anki_client, &query).await?;
+        candidates.retain(|id| self.notes.contains_key(id));
+        candidates.extend(matching.difference(&self.matching));
+        self.matching = matching;
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut updated = Vec::new();
+        if !candidates.is_empty() {
+            for m in NoteAction::notes_mod_time(anki_client, candidates).await? {
+                if self.modified.get(&m.noteId) != Some(&m.modified) {
+                    updated.push(m.noteId);
+                }
+            }
+        }
+        self.fetch(anki_client, updated.clone()).await?;
+        self.hydrated_at = Some(started);
+        Ok(NoteChanges { updated, removed })
+    }
+
+    pub fn get(&self, id: NoteId) -> Option<&NotesInfoData> {
+        let note = self.notes.get(&id)?;
+        self.recency.touch(id);
+        Some(note)
+    }
+
+    /// The cached note, fetching it first if it was evicted or never cached.
+    pub async fn get_or_fetch(
+        &mut self,
+        anki_client: &AnkiClient,
+        id: NoteId,
+    ) -> Result<&NotesInfoData, AnkiError> {
+        if !self.notes.contains_key(&id) {
+            self.fetch(anki_client, vec![id]).await?;
+        }
+        self.recency.touch(id);
+        match self.notes.get(&id) {
+            Some(note) => Ok(note),
+            None => Err(AnkiError::NoDataFound),
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = NoteId> + '_ {
+        self.notes.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// When the notes were last hydrated or refreshed, or `None` if they never were.
+    pub fn hydrated_at(&self) -> Option<DateTime<Utc>> {
+        self.hydrated_at
+    }
+
+    async fn fetch(&mut self, anki_client: &AnkiClient, ids: Vec<NoteId>) -> Result<(), AnkiError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let times = NoteAction::notes_mod_time(anki_client, ids.clone()).await?;
+        let notes = NoteAction::get_notes_infos(anki_client, ids).await?;
+        self.modified
+            .extend(times.into_iter().map(|m| (m.noteId, m.modified)));
+        for note in notes {
+            self.recency.touch(note.noteId);
+            self.notes.insert(note.noteId, note);
+        }
+        self.evict();
+        Ok(())
+    }
+
+    fn evict(&mut self) {
+        for id in self.recency.evict(&mut self.notes, self.capacity) {
+            self.modified.remove(&id);
+        }
+    }
+}
+
+/// A local copy of `cardsInfo` for the cards matching a search, keyed by card id.
+///
+/// Each cached `CardInfo` is a snapshot of the card's scheduling at hydration time.
+/// `stale` compares them against `cardsModTime`, so tools that inspect the same
+/// cards repeatedly only refetch the ones that were reviewed or edited.
+///
+/// With a `CacheCapacity`, the least recently used cards are evicted once the cache is
+/// full; `get_or_fetch` loads them again.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::cache::CardCache;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut cards = CardCache::new();
+/// cards.hydrate(&client, "deck:Mining tag:leech").await?;
+/// let changed = cards.refresh_stale(&client).await?;
+/// for id in changed {
+///     if let Some(card) = cards.get(id) {
+///         println!("{} lapses: {}", id, card.lapses);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CardCache {
+    query: Option<String>,
+    cards: BTreeMap<CardId, CardInfo>,
+    #[serde(default)]
+    capacity: Option<CacheCapacity>,
+    #[serde(skip)]
+    recency: Recency<CardId>,
+    hydrated_at: Option<DateTime<Utc>>,
+}
+
+impl CardCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits how much the cache holds, evicting the least recently used cards.
+    pub fn with_capacity(mut self, capacity: CacheCapacity) -> Self {
+        self.capacity = Some(capacity);
+        self.recency.evict(&mut self.cards, self.capacity);
+        self
+    }
+
+    /// The search the cache was last hydrated from.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// Replaces the cached cards with every card matching `query`.
+    pub async fn hydrate(
+        &mut self,
+        anki_client: &AnkiClient,
+        query: &str,
+    ) -> Result<(), AnkiError> {
+        let started = Utc::now();
+        let ids = match CardAction::find_card_ids(anki_client, query).await {
+            Ok(ids) => ids,
+            Err(AnkiError::NoDataFound) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let cards = match ids.is_empty() {
+            true => Vec::new(),
+            false => CardAction::get_cards_infos(anki_client, ids).await?,
+        };
+        self.cards.clear();
+        self.insert(cards);
+        self.query = Some(query.to_string());
+        self.hydrated_at = Some(started);
+        Ok(())
+    }
+
+    /// The cached cards that were modified or deleted in Anki since they were fetched.
+    pub async fn stale(&self, anki_client: &AnkiClient) -> Result<Vec<CardId>, AnkiError> {
+        let times = self.mod_times(anki_client).await?;
+        Ok(self.stale_in(&times))
+    }
+
+    /// Refetches the stale cards, drops the deleted ones, and returns the ids of both.
+    pub async fn refresh_stale(
+        &mut self,
+        anki_client: &AnkiClient,
+    ) -> Result<Vec<CardId>, AnkiError> {
+        let times = self.mod_times(anki_client).await?;
+        let stale = self.stale_in(&times);
+        let modified: Vec<CardId> = stale
+            .iter()
+            .filter(|id| times.contains_key(id))
+            .copied()
+            .collect();
+        let fresh = match modified.is_empty() {
+            true => Vec::new(),
+            false => CardAction::get_cards_infos(anki_client, modified).await?,
+        };
+        for id in &stale {
+            self.cards.remove(id);
+            self.recency.forget(*id);
+        }
+        self.insert(fresh);
+        Ok(stale)
+    }
+
+    fn insert(&mut self, cards: Vec<CardInfo>) {
+        for card in cards {
+            self.recency.touch(card.cardId);
+            self.cards.insert(card.cardId, card);
+        }
+        self.recency.evict(&mut self.cards, self.capacity);
+    }
+
+    async fn mod_times(
+        &self,
+        anki_client: &AnkiClient,
+    ) -> Result<BTreeMap<CardId, u64>, AnkiError> {
+        if self.cards.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+        let ids = self.cards.keys().copied().collect();
+        let times = CardAction::cards_mod_time(anki_client, ids).await?;
+        Ok(times.into_iter().map(|m| (m.cardId, m.modified)).collect())
+    }
+
+    fn stale_in(&self, times: &BTreeMap<CardId, u64>) -> Vec<CardId> {
+        self.cards
+            .iter()
+            .filter(|(id, card)| times.get(id) != Some(&card.modified))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub fn get(&self, id: CardId) -> Option<&CardInfo> {
+        let card = self.cards.get(&id)?;
+        self.recency.touch(id);
+        Some(card)
+    }
+
+    /// The cached card, fetching it first if it was evicted or never cached.
+    pub async fn get_or_fetch(
+        &mut self,
+        anki_client: &AnkiClient,
+        id: CardId,
+    ) -> Result<&CardInfo, AnkiError> {
+        if !self.cards.contains_key(&id) {
+            let cards = CardAction::get_cards_infos(anki_client, vec![id]).await?;
+            self.insert(cards);
+        }
+        self.recency.touch(id);
+        match self.cards.get(&id) {
+            Some(card) => Ok(card),
+            None => Err(AnkiError::NoDataFound),
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = CardId> + '_ {
+        self.cards.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    /// When the cards were last hydrated, or `None` if they never were.
+    pub fn hydrated_at(&self) -> Option<DateTime<Utc>> {
+        self.hydrated_at
+    }
+}
+
+/// Whether `live` holds exactly the `cached` names, in any order.
+fn same_names<'a>(live: &[String], cached: impl Iterator<Item = &'a String>) -> bool {
+    let live: BTreeSet<&String> = live.iter().collect();
+    live.into_iter().eq(cached)
+}
+
+/// Note ids matching `query`, with no matches being an empty list rather than an error.
+async fn find_ids(anki_client: &AnkiClient, query: &str) -> Result<Vec<NoteId>, AnkiError> {
+    match NoteAction::find_note_ids(anki_client, query).await {
+        Ok(ids) => Ok(ids),
+        Err(AnkiError::NoDataFound) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// How long `Cache::warm_up` took, per section and overall.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmUpReport {
+    pub sections: BTreeMap<CacheSection, Duration>,
+    /// Wall time of the whole warm-up. Less than the sum of the sections, as they
+    /// are fetched at the same time.
+    pub total: Duration,
+}
+
+async fn timed(
+    hydrate: impl Future<Output = Result<(), AnkiError>>,
+) -> Result<Duration, AnkiError> {
+    let start = Instant::now();
+    hydrate.await?;
+    Ok(start.elapsed())
+}
+
+/// Names added, removed or changed in one section by a rehydration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SectionDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Entries present before and after whose contents differ. Always empty for tags.
+    pub changed: Vec<String>,
+}
+
+impl SectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// What `Cache::hydrate` changed compared to the previous cache contents.
+///
+/// Sections that weren't rehydrated are left empty. Everything counts as added when a
+/// section is hydrated for the first time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheDiff {
+    pub models: SectionDiff,
+    pub decks: SectionDiff,
+    pub tags: SectionDiff,
+    /// Fields that disappeared from models still in the collection, keyed by model name.
+    pub removed_fields: BTreeMap<String, Vec<String>>,
+}
+
+impl CacheDiff {
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+            && self.decks.is_empty()
+            && self.tags.is_empty()
+            && self.removed_fields.is_empty()
+    }
+
+    fn merge(&mut self, other: CacheDiff) {
+        let CacheDiff {
+            models,
+            decks,
+            tags,
+            removed_fields,
+        } = other;
+        for (mine, theirs) in [
+            (&mut self.models, models),
+            (&mut self.decks, decks),
+            (&mut self.tags, tags),
+        ] {
+            mine.added.extend(theirs.added);
+            mine.removed.extend(theirs.removed);
+            mine.changed.extend(theirs.changed);
+        }
+        self.removed_fields.extend(removed_fields);
+    }
+}
+
+fn diff_by_name<'a, T: 'a>(
+    old: impl Iterator<Item = (&'a str, &'a T)>,
+    new: impl Iterator<Item = (&'a str, &'a T)>,
+    same: impl Fn(&T, &T) -> bool,
+) -> SectionDiff {
+    let old: BTreeMap<&str, &T> = old.collect();
+    let new: BTreeMap<&str, &T> = new.collect();
+    let mut diff = SectionDiff::default();
+    for (name, entry) in &new {
+        match old.get(name) {
+            None => diff.added.push(name.to_string()),
+            Some(previous) if !same(previous, entry) => diff.changed.push(name.to_string()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+    diff
+}
+
+fn diff_models(old: &ModelCache, new: &ModelCache) -> CacheDiff {
+    let models = diff_by_name(
+        old.models.values().map(|m| (m.name.as_str(), m)),
+        new.models.values().map(|m| (m.name.as_str(), m)),
+        |a, b| serde_json::to_value(a).ok() == serde_json::to_value(b).ok(),
+    );
+    let mut removed_fields = BTreeMap::new();
+    for name in &models.changed {
+        let (Some(before), Some(after)) = (old.models.get(name), new.models.get(name)) else {
+            continue;
+        };
+        let gone: Vec<String> = before
+            .flds
+            .iter()
+            .filter(|f| !after.flds.iter().any(|g| g.name == f.name))
+            .map(|f| f.name.clone())
+            .collect();
+        if !gone.is_empty() {
+            removed_fields.insert(name.clone(), gone);
+        }
+    }
+    CacheDiff {
+        models,
+        removed_fields,
+        ..CacheDiff::default()
+    }
+}
+
+fn diff_decks(old: &DeckCache, new: &DeckCache) -> SectionDiff {
+    diff_by_name(
+        old.decks.values().map(|d| (d.name.as_str(), d)),
+        new.decks.values().map(|d| (d.name.as_str(), d)),
+        |a, b| {
+            a.id == b.id
+                && match (&a.config, &b.config) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => true,
+                }
+        },
+    )
+}
+
+fn diff_tags(old: &TagCache, new: &TagCache) -> SectionDiff {
+    diff_by_name(
+        old.tags.values().map(|t| (t.as_str(), &())),
+        new.tags.values().map(|t| (t.as_str(), &())),
+        |_, _| true,
+    )
+}
+
+/// A local copy of the collection's models and decks, for tools that would otherwise
+/// fetch them on every run.
+///
+/// Each section can get a time to live; `refresh_expired` only refetches sections that
+/// are older than theirs. The cache can be saved to disk and loaded again, or saved
+/// automatically when it is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::cache::{Cache, CacheFormat, CacheSection};
+/// # use std::time::Duration;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut cache = Cache::load_or_default("anki-cache.json", CacheFormat::Json)?
+///     .with_ttl(CacheSection::Models, Duration::from_secs(24 * 60 * 60))
+///     .with_ttl(CacheSection::Decks, Duration::from_secs(60 * 60))
+///     .auto_save("anki-cache.json", CacheFormat::Json);
+/// cache.refresh_expired(&client).await?;
+/// let basic = cache.models.get("Basic");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Cache {
+    pub models: ModelCache,
+    pub decks: DeckCache,
+    #[serde(default)]
+    pub tags: TagCache,
+    /// Named queries saved with the cache. Share them with a client through
+    /// `AnkiClient::with_queries(cache.queries.clone())`.
+    #[serde(default)]
+    pub queries: QueryPresets,
+    #[serde(default)]
+    ttl: BTreeMap<CacheSection, Duration>,
+    #[serde(skip)]
+    auto_save: Option<(PathBuf, CacheFormat)>,
+    #[serde(skip)]
+    invalidation: CacheInvalidation,
+}
+
+impl Cache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long `section` stays fresh after hydration. Sections without a TTL never
+    /// expire once hydrated.
+    pub fn with_ttl(mut self, section: CacheSection, ttl: Duration) -> Self {
+        self.ttl.insert(section, ttl);
+        self
+    }
+
+    /// Saves the cache to `path` when it is dropped. Errors while saving are ignored,
+    /// or logged with the `tracing` feature.
+    pub fn auto_save(mut self, path: impl AsRef<Path>, format: CacheFormat) -> Self {
+        self.auto_save = Some((path.as_ref().to_path_buf(), format));
+        self
+    }
+
+    /// When `section` was last hydrated, or `None` if it never was.
+    pub fn hydrated_at(&self, section: CacheSection) -> Option<DateTime<Utc>> {
+        match section {
+            CacheSection::Models => self.models.hydrated_at,
+            CacheSection::Decks => self.decks.hydrated_at,
+            CacheSection::Tags => self.tags.hydrated_at,
+        }
+    }
+
+    /// A handle that expires sections of this cache. See `CacheInvalidation`.
+    pub fn invalidation(&self) -> CacheInvalidation {
+        self.invalidation.clone()
+    }
+
+    /// Whether `section` was never hydrated, was changed since, or is older than its TTL.
+    pub fn is_expired(&self, section: CacheSection) -> bool {
+        let Some(hydrated_at) = self.hydrated_at(section) else {
+            return true;
+        };
+        if self
+            .invalidation
+            .changed_at(section)
+            .is_some_and(|changed_at| changed_at >= hydrated_at)
+        {
+            return true;
+        }
+        match self.ttl.get(&section) {
+            Some(ttl) => match chrono::Duration::from_std(*ttl) {
+                Ok(ttl) => hydrated_at
+                    .checked_add_signed(ttl)
+                    .is_some_and(|expiry| expiry <= Utc::now()),
+                Err(_) => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Checks the live collection for changes to the cached sections and returns the
+    /// sections that need rehydrating, including the ones never hydrated.
+    ///
+    /// Costs one `multi` request, plus a `notesModTime` lookup if notes were edited
+    /// on the days since the tags were cached:
+    /// - models and decks are stale if their names changed,
+    /// - tags are stale if any note was edited after they were fetched.
+    ///
+    /// Changes inside a model or a deck config that keep its name go unnoticed, as
+    /// AnkiConnect doesn't expose modification times for them.
+    pub async fn is_stale(&self, anki_client: &AnkiClient) -> Result<Vec<CacheSection>, AnkiError> {
+        let mut batch = anki_client.batch();
+        let models = batch.action::<Vec<String>>(Action::ModelNames, None);
+        let decks = batch.action::<Vec<String>>(Action::DeckNames, None);
+        let edited = self.tags.hydrated_at.map(|at| {
+            let days = (Utc::now() - at).num_days() + 1;
+            batch.find_notes(&format!("edited:{}", days))
+        });
+        let mut results = batch.send().await?;
+
+        let mut stale = Vec::new();
+        let models = results.take(models)?;
+        if self.models.hydrated_at.is_none()
+            || !same_names(&models, self.models.models.values().map(|m| &m.name))
+        {
+            stale.push(CacheSection::Models);
+        }
+        let decks = results.take(decks)?;
+        if self.decks.hydrated_at.is_none()
+            || !same_names(&decks, self.decks.decks.values().map(|d| &d.name))
+        {
+            stale.push(CacheSection::Decks);
+        }
+        match (self.tags.hydrated_at, edited) {
+            (Some(at), Some(edited)) => {
+                let ids = results.take(edited)?;
+                let edited_since = match ids.is_empty() {
+                    true => false,
+                    false => NoteAction::notes_mod_time(anki_client, ids)
+                        .await?
+                        .iter()
+                        .any(|m| m.modified as i64 >= at.timestamp()),
+                };
+                if edited_since {
+                    stale.push(CacheSection::Tags);
+                }
+            }
+            _ => stale.push(CacheSection::Tags),
+        }
+        Ok(stale)
+    }
+
+    /// Rehydrates one section and returns what changed in it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::cache::{Cache, CacheFormat, CacheSection};
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let mut cache = Cache::load_or_default("anki-cache.json", CacheFormat::Json)?;
+    /// let diff = cache.hydrate(&client, CacheSection::Models).await?;
+    /// for (model, fields) in &diff.removed_fields {
+    ///     eprintln!("{} no longer has the fields {:?}", model, fields);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn hydrate(
+        &mut self,
+        anki_client: &AnkiClient,
+        section: CacheSection,
+    ) -> Result<CacheDiff, AnkiError> {
+        match section {
+            CacheSection::Models => {
+                let mut models = ModelCache::default();
+                models.hydrate(anki_client).await?;
+                let diff = diff_models(&self.models, &models);
+                self.models = models;
+                Ok(diff)
+            }
+            CacheSection::Decks => {
+                // Keep the configs of a cache hydrated with them.
+                let mut decks = DeckCache::default();
+                match self.decks.has_configs() {
+                    true => decks.hydrate_full(anki_client).await?,
+                    false => decks.hydrate_names(anki_client).await?,
+                }
+                let diff = diff_decks(&self.decks, &decks);
+                self.decks = decks;
+                Ok(CacheDiff {
+                    decks: diff,
+                    ..CacheDiff::default()
+                })
+            }
+            CacheSection::Tags => {
+                let mut tags = TagCache::default();
+                tags.hydrate(anki_client).await?;
+                let diff = diff_tags(&self.tags, &tags);
+                self.tags = tags;
+                Ok(CacheDiff {
+                    tags: diff,
+                    ..CacheDiff::default()
+                })
+            }
+        }
+    }
+
+    /// Hydrates every section, with deck configs, in one call.
+    ///
+    /// With `concurrent`, the sections are fetched at the same time instead of one
+    /// after the other. Nothing is replaced unless every section succeeds.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::cache::{Cache, CacheFormat};
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let mut cache = Cache::load_or_default("anki-cache.json", CacheFormat::Json)?;
+    /// cache.hydrate_all(&client, true).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn hydrate_all(
+        &mut self,
+        anki_client: &AnkiClient,
+        concurrent: bool,
+    ) -> Result<(), AnkiError> {
+        if concurrent {
+            return self.warm_up(anki_client).await.map(|_| ());
+        }
+        let mut models = ModelCache::default();
+        let mut decks = DeckCache::default();
+        let mut tags = TagCache::default();
+        models.hydrate(anki_client).await?;
+        decks.hydrate_full(anki_client).await?;
+        tags.hydrate(anki_client).await?;
+        self.models = models;
+        self.decks = decks;
+        self.tags = tags;
+        Ok(())
+    }
+
+    /// Hydrates every section concurrently, with deck configs fetched through a single
+    /// `multi` request, and reports how long each section took.
+    ///
+    /// Nothing is replaced unless every section succeeds.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::cache::Cache;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let mut cache = Cache::new();
+    /// let report = cache.warm_up(&client).await?;
+    /// for (section, took) in &report.sections {
+    ///     println!("{}: {:?}", section, took);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn warm_up(&mut self, anki_client: &AnkiClient) -> Result<WarmUpReport, AnkiError> {
+        let start = Instant::now();
+        let mut models = ModelCache::default();
+        let mut decks = DeckCache::default();
+        let mut tags = TagCache::default();
+        let (models_took, decks_took, tags_took) = tokio::try_join!(
+            timed(models.hydrate(anki_client)),
+            timed(decks.hydrate_full(anki_client)),
+            timed(tags.hydrate(anki_client)),
+        )?;
+        self.models = models;
+        self.decks = decks;
+        self.tags = tags;
+        Ok(WarmUpReport {
+            sections: BTreeMap::from([
+                (CacheSection::Models, models_took),
+                (CacheSection::Decks, decks_took),
+                (CacheSection::Tags, tags_took),
+            ]),
+            total: start.elapsed(),
+        })
+    }
+
+    /// Rehydrates every section and returns what changed.
+    pub async fn update_all(&mut self, anki_client: &AnkiClient) -> Result<CacheDiff, AnkiError> {
+        let mut diff = CacheDiff::default();
+        for section in CacheSection::ALL {
+            diff.merge(self.hydrate(anki_client, section).await?);
+        }
+        Ok(diff)
+    }
+
+    /// Rehydrates the expired sections and returns which ones were refreshed.
+    pub async fn refresh_expired(
+        &mut self,
+        anki_client: &AnkiClient,
+    ) -> Result<Vec<CacheSection>, AnkiError> {
+        let mut refreshed = Vec::new();
+        for section in CacheSection::ALL {
+            if self.is_expired(section) {
+                self.hydrate(anki_client, section).await?;
+                refreshed.push(section);
+            }
+        }
+        Ok(refreshed)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, format: CacheFormat) -> Result<(), AnkiError> {
+        let path = path.as_ref();
+        let bytes = match format {
+            CacheFormat::Json => serde_json::to_vec(self).map_err(|e| e.to_string()),
+            CacheFormat::PrettyJson => serde_json::to_vec_pretty(self).map_err(|e| e.to_string()),
+            #[cfg(feature = "cache-cbor")]
+            CacheFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(self, &mut bytes)
+                    .map(|()| bytes)
+                    .map_err(|e| e.to_string())
+            }
+        };
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(AnkiError::ParseError(e)),
+        };
+        match std::fs::write(path, bytes) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(AnkiError::RequestError(format!(
+                "failed to write cache {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>, format: CacheFormat) -> Result<Self, AnkiError> {
+        let path = path.as_ref();
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return Err(AnkiError::RequestError(format!(
+                    "failed to read cache {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        let cache = match format {
+            CacheFormat::Json | CacheFormat::PrettyJson => {
+                serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "cache-cbor")]
+            CacheFormat::Cbor => ciborium::from_reader(&bytes[..]).map_err(|e| e.to_string()),
+        };
+        match cache {
+            Ok(cache) => Ok(cache),
+            Err(e) => Err(AnkiError::ParseError(e)),
+        }
+    }
+
+    /// Loads the cache at `path`, or starts an empty one if the file doesn't exist yet.
+    pub fn load_or_default(path: impl AsRef<Path>, format: CacheFormat) -> Result<Self, AnkiError> {
+        match path.as_ref().exists() {
+            true => Self::load(path, format),
+            false => Ok(Self::default()),
+        }
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        let Some((path, format)) = self.auto_save.take() else {
+            return;
+        };
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        if let Err(e) = self.save(&path, format) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(path = %path.display(), error = %e, "failed to auto-save cache");
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::error::AnkiError;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::task::JoinHandle;
+
+/// A handle for aborting long operations, such as a large export or media download,
+/// from elsewhere in the program, e.g. a GUI's cancel button or another thread.
+///
+/// Every action in this crate stops cleanly when its future is dropped; `run` drops the
+/// operation's future as soon as the token is cancelled.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::cancel::CancelToken;
+/// # use anki_direct::media::MediaAction;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let token = CancelToken::new();
+/// let button = token.clone(); // button.cancel() from the UI
+/// let report = token
+///     .run(MediaAction::download_all(&client, "backup".as_ref(), "*"))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<CancelState>,
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every operation run with this token or its clones.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Runs an operation until it finishes or the token is cancelled, whichever is first.
+    ///
+    /// On cancellation the operation is dropped and `AnkiError::Cancelled` is returned.
+    pub async fn run<T>(
+        &self,
+        operation: impl Future<Output = Result<T, AnkiError>>,
+    ) -> Result<T, AnkiError> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => Err(AnkiError::Cancelled),
+            res = operation => res,
+        }
+    }
+}
+
+/// Aborts spawned tasks when dropped, so dropping the future that spawned them
+/// doesn't leave them running.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct AbortOnDrop<T>(pub(crate) Vec<JoinHandle<T>>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+}
@@ -0,0 +1,124 @@
+#![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
+use crate::error::AnkiError;
+use crate::gui::Ease;
+use crate::id::CardId;
+use crate::result::{CardInfo, CardModTime, CardsInfoBuffer};
+use crate::{post_generic_request, post_raw_request, AnkiClient};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct FindCardsParams {
+    pub query: String,
+}
+
+impl ActionParams for FindCardsParams {
+    const ACTION: Action = Action::FindCards;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CardsInfoParams {
+    pub cards: Vec<CardId>,
+}
+
+impl ActionParams for CardsInfoParams {
+    const ACTION: Action = Action::CardsInfo;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CardsModTimeParams {
+    pub cards: Vec<CardId>,
+}
+
+impl ActionParams for CardsModTimeParams {
+    const ACTION: Action = Action::CardsModTime;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct CardAnswer {
+    pub cardId: CardId,
+    pub ease: Ease,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AnswerCardsParams {
+    pub answers: Vec<CardAnswer>,
+}
+
+impl ActionParams for AnswerCardsParams {
+    const ACTION: Action = Action::AnswerCards;
+}
+
+/// The card actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct CardAction;
+
+impl CardAction {
+    /// Returns the ids of the cards matching a search query.
+    pub async fn find_card_ids(
+        anki_client: &AnkiClient,
+        query: &str,
+    ) -> Result<Vec<CardId>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FindCardsParams {
+                query: query.to_string(),
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Returns the scheduling, content and rendered question/answer of each card.
+    pub async fn get_cards_infos(
+        anki_client: &AnkiClient,
+        ids: Vec<CardId>,
+    ) -> Result<Vec<CardInfo>, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, CardsInfoParams { cards: ids });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Like `get_cards_infos`, but keeps the response so the cards can be parsed with
+    /// their strings borrowed from it. See `NoteAction::notes_infos_borrowed`.
+    pub async fn cards_info_borrowed(
+        anki_client: &AnkiClient,
+        ids: Vec<CardId>,
+    ) -> Result<CardsInfoBuffer, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, CardsInfoParams { cards: ids });
+
+        post_raw_request(&payload, anki_client)
+            .await
+            .map(CardsInfoBuffer::new)
+    }
+
+    /// Returns when each card was last modified, a much smaller response than `cardsInfo`.
+    pub async fn cards_mod_time(
+        anki_client: &AnkiClient,
+        ids: Vec<CardId>,
+    ) -> Result<Vec<CardModTime>, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, CardsModTimeParams { cards: ids });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Answers cards as if they were reviewed, without the GUI.
+    ///
+    /// Returns, for each answer, whether the card was found and answered.
+    pub async fn answer_cards(
+        anki_client: &AnkiClient,
+        answers: Vec<CardAnswer>,
+    ) -> Result<Vec<bool>, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, AnswerCardsParams { answers });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+}
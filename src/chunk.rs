@@ -0,0 +1,197 @@
+use crate::action::Action;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cancel::AbortOnDrop;
+use crate::error::AnkiError;
+use crate::result::GenericResult;
+use crate::{send_request, AnkiClient};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::sync::Semaphore;
+
+/// How many ids an action is sent with at most by default.
+pub const DEFAULT_CHUNK_SIZE: usize = 5_000;
+
+/// How actions taking long id lists are split into several requests.
+///
+/// A single `notesInfo` with 50k ids is slow and can time out; chunked, the same call
+/// is sent as several requests whose results are joined in order. Applies to the
+/// actions in `CHUNKED_ACTIONS`.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::chunk::ChunkPolicy;
+/// let client = AnkiClient::default().with_chunking(Some(ChunkPolicy {
+///     size: 1_000,
+///     parallelism: 2,
+/// }));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkPolicy {
+    /// Most ids per request. At least 1.
+    pub size: usize,
+    /// Most chunks awaiting a response at once. At least 1. AnkiConnect runs every
+    /// action on Anki's main thread, so more than a few rarely helps. Chunks are
+    /// always sent one after another on wasm32.
+    pub parallelism: usize,
+}
+
+impl Default for ChunkPolicy {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_CHUNK_SIZE,
+            parallelism: 1,
+        }
+    }
+}
+
+/// How the results of a chunked action's requests are joined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkMerge {
+    /// The results are lists, concatenated in chunk order.
+    Concat,
+    /// The action answers `null`.
+    Null,
+    /// The action answers whether anything changed, `true` if any chunk did.
+    Any,
+}
+
+/// The actions chunked by a client: the action, the param holding the ids, and how
+/// their results are joined.
+pub const CHUNKED_ACTIONS: &[(Action, &str, ChunkMerge)] = &[
+    (Action::AddTags, "notes", ChunkMerge::Null),
+    (Action::AreDue, "cards", ChunkMerge::Concat),
+    (Action::AreSuspended, "cards", ChunkMerge::Concat),
+    (Action::CardsInfo, "cards", ChunkMerge::Concat),
+    (Action::CardsModTime, "cards", ChunkMerge::Concat),
+    (Action::ChangeDeck, "cards", ChunkMerge::Null),
+    (Action::DeleteNotes, "notes", ChunkMerge::Null),
+    (Action::ForgetCards, "cards", ChunkMerge::Null),
+    (Action::GetEaseFactors, "cards", ChunkMerge::Concat),
+    (Action::NotesInfo, "notes", ChunkMerge::Concat),
+    (Action::NotesModTime, "notes", ChunkMerge::Concat),
+    (Action::RelearnCards, "cards", ChunkMerge::Null),
+    (Action::RemoveTags, "notes", ChunkMerge::Null),
+    (Action::Suspend, "cards", ChunkMerge::Any),
+    (Action::Unsuspend, "cards", ChunkMerge::Any),
+];
+
+/// Splits `payload` into one payload per `size` ids if its action is chunked and it
+/// has more ids than that. Returns `None` when it should be sent whole.
+pub(crate) fn split_ids(payload: &Value, size: usize) -> Option<(Vec<Value>, ChunkMerge)> {
+    let action = Action::from(payload["action"].as_str()?);
+    let (_, key, merge) = CHUNKED_ACTIONS
+        .iter()
+        .find(|(chunked, ..)| *chunked == action)?;
+    let ids = payload["params"][key].as_array()?;
+    let size = size.max(1);
+    if ids.len() <= size {
+        return None;
+    }
+    let chunks = ids
+        .chunks(size)
+        .map(|ids| {
+            let mut chunk = payload.clone();
+            chunk["params"][key] = Value::Array(ids.to_vec());
+            chunk
+        })
+        .collect();
+    Some((chunks, *merge))
+}
+
+/// Sends the chunks of an id-taking action and joins their results.
+///
+/// The first chunk, in order, that AnkiConnect answers with an error decides the
+/// returned error. Chunks already in flight still finish, so a failed mutating action
+/// may have been applied to some of the ids.
+pub(crate) async fn post_chunks<T: DeserializeOwned>(
+    chunks: Vec<Value>,
+    merge: ChunkMerge,
+    parallelism: usize,
+    anki_client: &AnkiClient,
+) -> Result<GenericResult<T>, AnkiError> {
+    let responses = send_chunks(chunks, parallelism, anki_client).await?;
+
+    let mut items = Vec::new();
+    let mut changed = false;
+    let mut answered = false;
+    for res in responses {
+        if res.error.is_some() {
+            return Ok(GenericResult {
+                result: None,
+                error: res.error,
+            });
+        }
+        match res.result {
+            Some(Value::Array(chunk_items)) => items.extend(chunk_items),
+            Some(Value::Bool(chunk_changed)) => changed |= chunk_changed,
+            Some(Value::Null) | None => continue,
+            Some(other) => {
+                return Err(AnkiError::ParseError(format!(
+                    "unexpected result in a chunked request: {}",
+                    other
+                )))
+            }
+        }
+        answered = true;
+    }
+
+    let result = match (merge, answered) {
+        // Dry runs answer every chunk with `null`.
+        (_, false) | (ChunkMerge::Null, _) => Value::Null,
+        (ChunkMerge::Concat, true) => Value::Array(items),
+        (ChunkMerge::Any, true) => Value::Bool(changed),
+    };
+    match serde_json::from_value(result) {
+        Ok(result) => Ok(GenericResult {
+            result,
+            error: None,
+        }),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn send_chunks(
+    chunks: Vec<Value>,
+    parallelism: usize,
+    anki_client: &AnkiClient,
+) -> Result<Vec<GenericResult<Value>>, AnkiError> {
+    let limit = Arc::new(Semaphore::new(parallelism.max(1)));
+    // Dropping this future aborts the chunks that haven't been answered yet.
+    let mut handles = AbortOnDrop(Vec::with_capacity(chunks.len()));
+    for chunk in chunks {
+        let client = anki_client.clone();
+        let limit = limit.clone();
+        handles.0.push(tokio::spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            send_request::<Value>(chunk, &client).await
+        }));
+    }
+
+    let mut responses = Vec::with_capacity(handles.0.len());
+    for handle in handles.0.iter_mut() {
+        match handle.await {
+            Ok(res) => responses.push(res?),
+            Err(e) => return Err(AnkiError::RequestError(e.to_string())),
+        }
+    }
+    Ok(responses)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn send_chunks(
+    chunks: Vec<Value>,
+    _parallelism: usize,
+    anki_client: &AnkiClient,
+) -> Result<Vec<GenericResult<Value>>, AnkiError> {
+    let mut responses = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        responses.push(send_request(chunk, anki_client).await?);
+    }
+    Ok(responses)
+}
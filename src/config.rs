@@ -0,0 +1,140 @@
+use crate::error::AnkiError;
+use crate::{AnkiClient, AnkiClientBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Environment variable holding the AnkiConnect URL.
+pub const ENV_URL: &str = "ANKICONNECT_URL";
+/// Environment variable holding the AnkiConnect `apiKey`.
+pub const ENV_KEY: &str = "ANKICONNECT_KEY";
+/// Environment variable holding the AnkiConnect API version to send.
+pub const ENV_VERSION: &str = "ANKICONNECT_VERSION";
+/// Environment variable holding the request timeout, in seconds.
+pub const ENV_TIMEOUT: &str = "ANKICONNECT_TIMEOUT_SECS";
+/// Environment variable holding the connect timeout, in seconds.
+pub const ENV_CONNECT_TIMEOUT: &str = "ANKICONNECT_CONNECT_TIMEOUT_SECS";
+
+/// Client settings read by `AnkiClient::from_env` or `AnkiClient::from_config`.
+/// Unset values keep the `AnkiClient::default()` settings.
+///
+/// The TOML form uses the field names:
+///
+/// ```toml
+/// url = "http://192.168.1.20:8765"
+/// key = "secret"
+/// timeout_secs = 30
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AnkiConfig {
+    pub url: Option<String>,
+    pub key: Option<String>,
+    pub version: Option<u8>,
+    pub timeout_secs: Option<f64>,
+    pub connect_timeout_secs: Option<f64>,
+}
+
+impl AnkiConfig {
+    /// Reads the `ANKICONNECT_*` environment variables.
+    pub fn from_env() -> Result<Self, AnkiError> {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|v: &String| !v.trim().is_empty())
+        };
+        Ok(Self {
+            url: var(ENV_URL),
+            key: var(ENV_KEY),
+            version: var(ENV_VERSION)
+                .map(|v| parse_var(ENV_VERSION, &v))
+                .transpose()?,
+            timeout_secs: var(ENV_TIMEOUT)
+                .map(|v| parse_var(ENV_TIMEOUT, &v))
+                .transpose()?,
+            connect_timeout_secs: var(ENV_CONNECT_TIMEOUT)
+                .map(|v| parse_var(ENV_CONNECT_TIMEOUT, &v))
+                .transpose()?,
+        })
+    }
+
+    /// Parses settings from TOML.
+    pub fn from_toml(source: &str) -> Result<Self, AnkiError> {
+        match toml::from_str(source) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(AnkiError::ParseError(e.to_string())),
+        }
+    }
+
+    /// A builder with these settings applied, for adding more before building.
+    pub fn into_builder(self) -> Result<AnkiClientBuilder, AnkiError> {
+        let mut builder = AnkiClient::builder();
+        if let Some(url) = &self.url {
+            builder = builder.endpoint(url);
+        }
+        if let Some(key) = &self.key {
+            builder = builder.api_key(key);
+        }
+        if let Some(version) = self.version {
+            builder = builder.version(version);
+        }
+        if let Some(secs) = self.timeout_secs {
+            builder = builder.timeout(seconds(secs)?);
+        }
+        if let Some(secs) = self.connect_timeout_secs {
+            builder = builder.connect_timeout(seconds(secs)?);
+        }
+        Ok(builder)
+    }
+}
+
+impl AnkiClient {
+    /// Creates a client from the `ANKICONNECT_URL`, `ANKICONNECT_KEY`, `ANKICONNECT_VERSION`,
+    /// `ANKICONNECT_TIMEOUT_SECS` and `ANKICONNECT_CONNECT_TIMEOUT_SECS` environment variables.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// let client = AnkiClient::from_env().unwrap();
+    /// ```
+    pub fn from_env() -> Result<Self, AnkiError> {
+        AnkiConfig::from_env()?.into_builder()?.build()
+    }
+
+    /// Creates a client from a TOML file with the fields of `AnkiConfig`.
+    pub fn from_config(path: impl AsRef<Path>) -> Result<Self, AnkiError> {
+        let path = path.as_ref();
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                return Err(AnkiError::RequestError(format!(
+                    "failed to read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        AnkiConfig::from_toml(&source)?.into_builder()?.build()
+    }
+}
+
+fn parse_var<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, AnkiError> {
+    match value.trim().parse() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(AnkiError::ValidationError(format!(
+            "{} has an invalid value `{}`",
+            name, value
+        ))),
+    }
+}
+
+fn seconds(secs: f64) -> Result<Duration, AnkiError> {
+    match Duration::try_from_secs_f64(secs) {
+        Ok(duration) => Ok(duration),
+        Err(e) => Err(AnkiError::ValidationError(format!(
+            "{} seconds: {}",
+            secs, e
+        ))),
+    }
+}
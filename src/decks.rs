@@ -0,0 +1,66 @@
+use crate::action::{Action, ActionParams, ActionRequest};
+use crate::error::AnkiError;
+use crate::id::DeckId;
+use crate::result::DeckConfig;
+use crate::{post_generic_request, post_simple_action, AnkiClient};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize)]
+pub struct GetDeckConfigParams {
+    pub deck: String,
+}
+
+impl ActionParams for GetDeckConfigParams {
+    const ACTION: Action = Action::GetDeckConfig;
+}
+
+/// The deck actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct DeckAction;
+
+impl DeckAction {
+    /// Returns the names of every deck in the collection.
+    pub async fn deck_names(anki_client: &AnkiClient) -> Result<Vec<String>, AnkiError> {
+        post_simple_action(anki_client, Action::DeckNames).await
+    }
+
+    /// Returns every deck's name with its id.
+    pub async fn deck_names_and_ids(
+        anki_client: &AnkiClient,
+    ) -> Result<HashMap<String, DeckId>, AnkiError> {
+        post_simple_action(anki_client, Action::DeckNamesAndIds).await
+    }
+
+    /// Returns the options group used by `deck`.
+    ///
+    /// Fails with `AnkiError::NoDataFound` if the deck doesn't exist.
+    pub async fn get_deck_config(
+        anki_client: &AnkiClient,
+        deck: &str,
+    ) -> Result<DeckConfig, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            GetDeckConfigParams {
+                deck: deck.to_string(),
+            },
+        );
+
+        // AnkiConnect answers `false` for unknown decks.
+        let config: Value = post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()?;
+        parse_deck_config(config)
+    }
+}
+
+pub(crate) fn parse_deck_config(config: Value) -> Result<DeckConfig, AnkiError> {
+    if config == Value::Bool(false) {
+        return Err(AnkiError::NoDataFound);
+    }
+    match serde_json::from_value(config) {
+        Ok(config) => Ok(config),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// Strings longer than this are shortened in a planned action's params.
+const SUMMARY_STRING_LIMIT: usize = 120;
+
+/// Action name prefixes that change the collection, media folder or Anki's state.
+/// A prefix also matches the action named exactly like it, e.g. `sync`.
+const MUTATING_PREFIXES: &[&str] = &[
+    "add",
+    "answer",
+    "change",
+    "clear",
+    "create",
+    "delete",
+    "forget",
+    "import",
+    "insert",
+    "relearn",
+    "remove",
+    "replace",
+    "save",
+    "set",
+    "store",
+    "suspend",
+    "sync",
+    "unsuspend",
+    "update",
+];
+
+/// Mutating actions not caught by `MUTATING_PREFIXES`.
+const MUTATING_ACTIONS: &[&str] = &[
+    "modelFieldAdd",
+    "modelFieldRemove",
+    "modelFieldRename",
+    "modelFieldReposition",
+    "modelFieldSetDescription",
+    "modelFieldSetFont",
+    "modelFieldSetFontSize",
+    "modelTemplateAdd",
+    "modelTemplateRemove",
+    "modelTemplateRename",
+    "modelTemplateReposition",
+    "findAndReplaceInModels",
+    "reloadCollection",
+    "loadProfile",
+    "exportPackage",
+    "guiAddCards",
+    "guiAnswerCard",
+    "guiCheckDatabase",
+    "guiExitAnki",
+    "guiImportFile",
+    "guiUndo",
+];
+
+/// Whether `action` changes anything in Anki, as opposed to only reading.
+pub fn is_mutating(action: &str) -> bool {
+    MUTATING_ACTIONS.contains(&action)
+        || MUTATING_PREFIXES.iter().any(|prefix| {
+            action.strip_prefix(prefix).is_some_and(|rest| {
+                rest.is_empty() || rest.starts_with(|c: char| c.is_ascii_uppercase())
+            })
+        })
+}
+
+/// A mutating action intercepted by a dry-run client.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlannedAction {
+    pub action: String,
+    /// The action's params, with long strings such as base64 file contents shortened.
+    pub params: Value,
+}
+
+/// The mutating actions a dry-run client would have sent, in order.
+#[derive(Debug, Default)]
+pub(crate) struct DryRunPlan {
+    actions: Mutex<Vec<PlannedAction>>,
+}
+
+impl DryRunPlan {
+    pub(crate) fn record(&self, action: &str, params: &Value) {
+        let planned = PlannedAction {
+            action: action.to_string(),
+            params: summarize(params),
+        };
+        match self.actions.lock() {
+            Ok(mut actions) => actions.push(planned),
+            Err(poisoned) => poisoned.into_inner().push(planned),
+        }
+    }
+
+    pub(crate) fn actions(&self) -> Vec<PlannedAction> {
+        match self.actions.lock() {
+            Ok(actions) => actions.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Records the mutating actions of a request and returns the response to answer it
+    /// with, or `None` if it only reads and should be sent.
+    ///
+    /// A `multi` batch is intercepted as a whole if any of its actions mutates.
+    pub(crate) fn intercept(&self, payload: &Value) -> Option<Value> {
+        let action = payload["action"].as_str().unwrap_or_default();
+        if action != "multi" {
+            if !is_mutating(action) {
+                return None;
+            }
+            self.record(action, &payload["params"]);
+            return Some(serde_json::json!({ "result": null, "error": null }));
+        }
+
+        let inner = payload["params"]["actions"].as_array()?;
+        let mutating = |a: &Value| is_mutating(a["action"].as_str().unwrap_or_default());
+        if !inner.iter().any(mutating) {
+            return None;
+        }
+        for a in inner.iter().filter(|a| mutating(a)) {
+            self.record(a["action"].as_str().unwrap_or_default(), &a["params"]);
+        }
+        let results = vec![serde_json::json!({ "result": null, "error": null }); inner.len()];
+        Some(serde_json::json!({ "result": results, "error": null }))
+    }
+}
+
+fn summarize(value: &Value) -> Value {
+    match value {
+        Value::String(s) if s.chars().count() > SUMMARY_STRING_LIMIT => {
+            let head: String = s.chars().take(SUMMARY_STRING_LIMIT).collect();
+            Value::String(format!("{}... ({} chars)", head, s.chars().count()))
+        }
+        Value::Array(items) => Value::Array(items.iter().map(summarize).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), summarize(v)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(non_snake_case)]
+use crate::id::NoteId;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt::Display;
@@ -9,6 +10,122 @@ pub enum AnkiError {
     NoDataFound,
     RequestError(String),
     ParseError(String),
+    ValidationError(String),
+    ChecksumMismatch {
+        filename: String,
+        expected: String,
+        actual: String,
+    },
+    SyncError(SyncFailure),
+    /// The operation was stopped through a `CancelToken`.
+    Cancelled,
+    /// The running AnkiConnect is older than the action needs.
+    VersionTooOld {
+        action: String,
+        have: u8,
+        need: u8,
+    },
+    /// AnkiConnect has no deck with this name.
+    DeckNotFound(String),
+    /// AnkiConnect has no model (note type) with this name.
+    ModelNotFound(String),
+    /// The note would duplicate an existing one and duplicates aren't allowed.
+    DuplicateNote,
+    /// The note's first field is empty.
+    EmptyNote,
+    /// No profile is open in Anki, e.g. while it is starting or syncing.
+    CollectionUnavailable,
+    /// AnkiConnect rejected the request's `apiKey`, or the origin wasn't allowed.
+    PermissionDenied,
+    /// An `addNotes` request split by the payload limit failed part way. The notes of
+    /// the requests sent before the failing one are in the collection; `added` holds
+    /// their ids, in order, with `None` for notes Anki refused.
+    PartiallyAdded {
+        added: Vec<Option<NoteId>>,
+        error: Box<AnkiError>,
+    },
+}
+
+impl AnkiError {
+    /// Maps an error message returned by AnkiConnect to its variant. Messages without
+    /// a variant of their own are kept as `RequestError`.
+    pub fn from_message(message: String) -> Self {
+        let lower = message.to_lowercase();
+        let subject = || match message.split_once(": ") {
+            Some((_, subject)) => subject.to_string(),
+            None => String::new(),
+        };
+        if lower.starts_with("deck was not found") {
+            AnkiError::DeckNotFound(subject())
+        } else if lower.starts_with("model was not found") {
+            AnkiError::ModelNotFound(subject())
+        } else if lower.contains("it is a duplicate") {
+            AnkiError::DuplicateNote
+        } else if lower.contains("it is empty") {
+            AnkiError::EmptyNote
+        } else if lower.contains("collection is not available") {
+            AnkiError::CollectionUnavailable
+        } else if lower.contains("api key") || lower.contains("permission") {
+            AnkiError::PermissionDenied
+        } else {
+            AnkiError::RequestError(message)
+        }
+    }
+}
+
+/// Why a `sync` call failed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SyncFailure {
+    /// No AnkiWeb login is configured for the profile.
+    AuthRequired,
+    /// AnkiWeb asked for a one-way full sync, which has to be confirmed in Anki.
+    FullSyncRequired,
+    /// AnkiWeb couldn't be reached. Usually worth retrying later.
+    Network(String),
+    Other(String),
+}
+
+impl SyncFailure {
+    /// Maps the error message AnkiConnect returns for `sync` to a failure kind.
+    pub fn from_message(message: &str) -> Self {
+        let lower = message.to_lowercase();
+        if lower.contains("auth") || lower.contains("login") {
+            SyncFailure::AuthRequired
+        } else if lower.contains("full sync") || lower.contains("one-way") {
+            SyncFailure::FullSyncRequired
+        } else if ["network", "connection", "timed out", "timeout", "resolve"]
+            .iter()
+            .any(|s| lower.contains(s))
+        {
+            SyncFailure::Network(message.to_string())
+        } else {
+            SyncFailure::Other(message.to_string())
+        }
+    }
+
+    /// Whether the user has to act in Anki before syncing can succeed.
+    pub fn needs_user_attention(&self) -> bool {
+        matches!(
+            self,
+            SyncFailure::AuthRequired | SyncFailure::FullSyncRequired
+        )
+    }
+
+    /// Whether retrying the sync later might succeed on its own.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SyncFailure::Network(_))
+    }
+}
+
+impl Display for SyncFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncFailure::AuthRequired => write!(f, "AnkiWeb login required"),
+            SyncFailure::FullSyncRequired => write!(f, "full sync required"),
+            SyncFailure::Network(e) => write!(f, "network error: {}", e),
+            SyncFailure::Other(e) => write!(f, "{}", e),
+        }
+    }
 }
 
 impl Error for AnkiError {}
@@ -19,6 +136,35 @@ impl Display for AnkiError {
             AnkiError::NoDataFound => write!(f, "No data found for query."),
             AnkiError::RequestError(e) => write!(f, "Request error: {}", e),
             AnkiError::ParseError(e) => write!(f, "Parse error: {}", e),
+            AnkiError::ValidationError(e) => write!(f, "Validation error: {}", e),
+            AnkiError::ChecksumMismatch {
+                filename,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch for {}: expected sha256 {}, got {}",
+                filename, expected, actual
+            ),
+            AnkiError::SyncError(e) => write!(f, "Sync error: {}", e),
+            AnkiError::Cancelled => write!(f, "Operation cancelled."),
+            AnkiError::VersionTooOld { action, have, need } => write!(
+                f,
+                "`{}` needs AnkiConnect version {}, but version {} is running",
+                action, need, have
+            ),
+            AnkiError::DeckNotFound(deck) => write!(f, "Deck not found: {}", deck),
+            AnkiError::ModelNotFound(model) => write!(f, "Model not found: {}", model),
+            AnkiError::DuplicateNote => write!(f, "Note is a duplicate."),
+            AnkiError::EmptyNote => write!(f, "Note is empty."),
+            AnkiError::CollectionUnavailable => write!(f, "Collection is not available."),
+            AnkiError::PermissionDenied => write!(f, "Permission denied by AnkiConnect."),
+            AnkiError::PartiallyAdded { added, error } => write!(
+                f,
+                "Adding notes failed after {} notes were sent: {}",
+                added.len(),
+                error
+            ),
         }
     }
 }
@@ -0,0 +1,174 @@
+use crate::error::AnkiError;
+use crate::transport::{AnkiTransport, TransportError, TransportFuture};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// One recorded request and AnkiConnect's response to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Exchange {
+    pub request: Value,
+    pub response: Value,
+}
+
+/// The request as stored in a fixture: without the `key`, so fixtures don't leak it
+/// and replay works with any key.
+fn fixture_request(request: &Value) -> Value {
+    let mut request = request.clone();
+    if let Some(fields) = request.as_object_mut() {
+        fields.remove("key");
+    }
+    request
+}
+
+/// Passes requests to another transport and writes every request/response pair
+/// to a JSON fixture file, for `ReplayTransport` to answer from later.
+///
+/// The file is rewritten after each exchange, so it stays complete if the
+/// program stops early. Use `AnkiClient::record_to` to record a client's requests.
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Arc<dyn AnkiTransport>,
+    path: PathBuf,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl RecordingTransport {
+    pub fn new(path: impl AsRef<Path>, inner: impl AnkiTransport + 'static) -> Self {
+        Self::from_arc(path, Arc::new(inner))
+    }
+
+    pub(crate) fn from_arc(path: impl AsRef<Path>, inner: Arc<dyn AnkiTransport>) -> Self {
+        Self {
+            inner,
+            path: path.as_ref().to_path_buf(),
+            exchanges: Mutex::default(),
+        }
+    }
+
+    /// The exchanges recorded so far.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        match self.exchanges.lock() {
+            Ok(exchanges) => exchanges.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn record(&self, request: &Value, response: &Value) -> Result<(), AnkiError> {
+        let mut exchanges = match self.exchanges.lock() {
+            Ok(exchanges) => exchanges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        exchanges.push(Exchange {
+            request: fixture_request(request),
+            response: response.clone(),
+        });
+        let json = match serde_json::to_vec_pretty(&*exchanges) {
+            Ok(json) => json,
+            Err(e) => return Err(AnkiError::ParseError(e.to_string())),
+        };
+        match std::fs::write(&self.path, json) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(AnkiError::RequestError(format!(
+                "failed to write fixture {}: {}",
+                self.path.display(),
+                e
+            ))),
+        }
+    }
+}
+
+impl AnkiTransport for RecordingTransport {
+    fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let response = self.inner.call(request).await?;
+            self.record(request, &response)
+                .map_err(TransportError::Failed)?;
+            Ok(response)
+        })
+    }
+}
+
+/// Answers requests from a fixture written by `RecordingTransport`, without contacting Anki.
+///
+/// Each request gets the response of the first unused recorded exchange with the same
+/// action, version and params, so repeated requests replay in recorded order.
+/// A request missing from the fixture fails with `AnkiError::RequestError`.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::fixture::ReplayTransport;
+/// # use anki_direct::notes::NoteAction;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let replay = ReplayTransport::from_file("tests/fixtures/mining.json")?;
+/// let client = AnkiClient::default().with_transport(replay);
+/// let ids = NoteAction::find_note_ids(&client, "deck:Mining").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ReplayTransport {
+    exchanges: Mutex<Vec<Option<Exchange>>>,
+}
+
+impl ReplayTransport {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        Self {
+            exchanges: Mutex::new(exchanges.into_iter().map(Some).collect()),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AnkiError> {
+        let path = path.as_ref();
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) => {
+                return Err(AnkiError::RequestError(format!(
+                    "failed to read fixture {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        match serde_json::from_slice(&json) {
+            Ok(exchanges) => Ok(Self::new(exchanges)),
+            Err(e) => Err(AnkiError::ParseError(e.to_string())),
+        }
+    }
+
+    /// How many recorded exchanges haven't been replayed yet.
+    pub fn remaining(&self) -> usize {
+        match self.exchanges.lock() {
+            Ok(exchanges) => exchanges.iter().flatten().count(),
+            Err(poisoned) => poisoned.into_inner().iter().flatten().count(),
+        }
+    }
+
+    fn answer(&self, request: &Value) -> Result<Value, AnkiError> {
+        let request = fixture_request(request);
+        let mut exchanges = match self.exchanges.lock() {
+            Ok(exchanges) => exchanges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let slot = exchanges
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(e) if e.request == request));
+        match slot.and_then(Option::take) {
+            Some(exchange) => Ok(exchange.response),
+            None => Err(AnkiError::RequestError(format!(
+                "no recorded response for action {}",
+                request["action"]
+            ))),
+        }
+    }
+}
+
+impl AnkiTransport for ReplayTransport {
+    fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a> {
+        let res = self.answer(request).map_err(TransportError::Failed);
+        Box::pin(async move { res })
+    }
+}
@@ -0,0 +1,339 @@
+use crate::action::ActionRequest;
+use crate::batch::{BatchItem, BatchOutcome};
+use crate::error::AnkiError;
+use crate::id::NoteId;
+use crate::media::{
+    ensure_flat_filename, extract_media_refs, is_local_endpoint, MediaAction, MediaBuilder,
+    MediaSource, SniffPolicy, StoreMode, DEFAULT_MEDIA_CONCURRENCY,
+};
+use crate::notes::{AddNoteParams, NewNote};
+use crate::AnkiClient;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// How a media column is referenced from its field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    /// `[sound:name]`
+    Audio,
+    /// `<img src="name">`
+    Image,
+}
+
+/// Imports a subs2srs/mpvacious-style export: a TSV file with one note per row, and a
+/// folder holding the audio clips and screenshots its rows name.
+///
+/// A media column may hold a bare filename or Anki's own reference to it, like
+/// `[sound:clip.mp3]`. Every file is uploaded once, concurrently, before the notes are
+/// added; each row is then added on its own, so a duplicate or a missing clip only
+/// fails its own row.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::folder_import::FolderImport;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// // subs2srs: tag, sequence, audio, image, sentence, translation
+/// let outcome = FolderImport::new("bleach_01.tsv", "bleach_01.media", "Mining", "Sentence")
+///     .tag_column(0)
+///     .audio(2, "Audio")
+///     .image(3, "Picture")
+///     .text(4, "Sentence")
+///     .text(5, "Meaning")
+///     .import(&client)
+///     .await?;
+/// for failure in &outcome.failed {
+///     eprintln!("row {}: {}", failure.index, failure.error);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FolderImport {
+    pub tsv: PathBuf,
+    pub media_folder: PathBuf,
+    pub deck: String,
+    pub model: String,
+    pub has_header: bool,
+    pub text: Vec<(usize, String)>,
+    pub media: Vec<(usize, String, MediaKind)>,
+    pub tags: Vec<String>,
+    pub tag_column: Option<usize>,
+    pub mode: StoreMode,
+    pub concurrency: usize,
+}
+
+/// A parsed row, before its media is stored.
+struct Row {
+    fields: HashMap<String, String>,
+    /// The field, kind and filenames of each media column.
+    media: Vec<(String, MediaKind, Vec<String>)>,
+    tags: Vec<String>,
+}
+
+impl FolderImport {
+    pub fn new(
+        tsv: impl AsRef<Path>,
+        media_folder: impl AsRef<Path>,
+        deck: &str,
+        model: &str,
+    ) -> Self {
+        Self {
+            tsv: tsv.as_ref().to_path_buf(),
+            media_folder: media_folder.as_ref().to_path_buf(),
+            deck: deck.to_string(),
+            model: model.to_string(),
+            has_header: false,
+            text: Vec::new(),
+            media: Vec::new(),
+            tags: Vec::new(),
+            tag_column: None,
+            mode: StoreMode::default(),
+            concurrency: DEFAULT_MEDIA_CONCURRENCY,
+        }
+    }
+
+    /// Skips the first line of the TSV.
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// Fills `field` with the text of `column`, counted from 0.
+    pub fn text(mut self, column: usize, field: &str) -> Self {
+        self.text.push((column, field.to_string()));
+        self
+    }
+
+    /// Stores the audio file named in `column` and references it from `field`.
+    pub fn audio(mut self, column: usize, field: &str) -> Self {
+        self.media
+            .push((column, field.to_string(), MediaKind::Audio));
+        self
+    }
+
+    /// Stores the image named in `column` and references it from `field`.
+    pub fn image(mut self, column: usize, field: &str) -> Self {
+        self.media
+            .push((column, field.to_string(), MediaKind::Image));
+        self
+    }
+
+    /// A tag added to every note.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Tags each note with the space-separated tags in `column`, like subs2srs' first column.
+    pub fn tag_column(mut self, column: usize) -> Self {
+        self.tag_column = Some(column);
+        self
+    }
+
+    /// How the media files are stored. With `StoreMode::DirectCopy`, the collection is
+    /// reloaded after storing so the copied files show up.
+    pub fn mode(mut self, mode: StoreMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Most media files stored at once. Defaults to `DEFAULT_MEDIA_CONCURRENCY`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Stores the media and adds a note per row, returning the new note ids by row.
+    ///
+    /// Rows are numbered from 0, counting neither the header nor blank lines. The
+    /// outer error is the TSV not being readable, the collection reload failing, or
+    /// the request adding the notes failing as a whole.
+    pub async fn import(
+        &self,
+        anki_client: &AnkiClient,
+    ) -> Result<BatchOutcome<NoteId>, AnkiError> {
+        let start = Instant::now();
+        let tsv = match tokio::fs::read_to_string(&self.tsv).await {
+            Ok(tsv) => tsv,
+            Err(e) => {
+                return Err(AnkiError::RequestError(format!(
+                    "failed to read {}: {}",
+                    self.tsv.display(),
+                    e
+                )))
+            }
+        };
+        let rows = self.parse_rows(&tsv);
+        let stored = self.store_media(anki_client, &rows).await?;
+
+        let mut results: Vec<Option<Result<NoteId, AnkiError>>> = Vec::new();
+        let mut batch = anki_client.batch();
+        let mut pending: Vec<(usize, BatchItem<NoteId>)> = Vec::new();
+        for (index, row) in rows.into_iter().enumerate() {
+            match row.and_then(|row| self.build_note(row, &stored)) {
+                Ok(note) => {
+                    let request = ActionRequest::new(anki_client.version, AddNoteParams { note });
+                    pending.push((index, batch.push(&request)?));
+                    results.push(None);
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+
+        let mut added = batch.send().await?;
+        for (index, item) in pending {
+            results[index] = Some(added.take(item));
+        }
+        Ok(BatchOutcome::from_results(
+            results.into_iter().flatten(),
+            start.elapsed(),
+        ))
+    }
+
+    /// Splits the TSV into rows, reading the mapped columns of each.
+    fn parse_rows(&self, tsv: &str) -> Vec<Result<Row, AnkiError>> {
+        let skip = usize::from(self.has_header);
+        tsv.lines()
+            .skip(skip)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let cells: Vec<&str> = line.split('\t').collect();
+                let cell = |column: usize| match cells.get(column) {
+                    Some(cell) => Ok(cell.trim()),
+                    None => Err(AnkiError::ValidationError(format!(
+                        "the row has {} columns, but column {} is mapped",
+                        cells.len(),
+                        column
+                    ))),
+                };
+
+                let mut fields = HashMap::new();
+                for (column, field) in &self.text {
+                    fields.insert(field.clone(), cell(*column)?.to_string());
+                }
+                let mut media = Vec::new();
+                for (column, field, kind) in &self.media {
+                    let names = media_names(cell(*column)?)?;
+                    media.push((field.clone(), *kind, names));
+                }
+                let mut tags = self.tags.clone();
+                if let Some(column) = self.tag_column {
+                    for tag in cell(column)?.split_whitespace() {
+                        if !tags.iter().any(|t| t == tag) {
+                            tags.push(tag.to_string());
+                        }
+                    }
+                }
+                Ok(Row {
+                    fields,
+                    media,
+                    tags,
+                })
+            })
+            .collect()
+    }
+
+    /// Stores every file named by a readable row once, returning the name each was
+    /// stored under, or why it failed.
+    async fn store_media(
+        &self,
+        anki_client: &AnkiClient,
+        rows: &[Result<Row, AnkiError>],
+    ) -> Result<HashMap<String, Result<String, String>>, AnkiError> {
+        let mut names: Vec<String> = Vec::new();
+        for row in rows.iter().flatten() {
+            for name in row.media.iter().flat_map(|(_, _, names)| names) {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        // A local Anki reads the files itself; a remote one gets their contents, which
+        // sniffing makes the builder read.
+        let local = is_local_endpoint(&anki_client.endpoint);
+        let mut stored = HashMap::new();
+        let mut queued = Vec::new();
+        let mut items = Vec::new();
+        for name in names {
+            match MediaSource::path(self.media_folder.join(&name)) {
+                Ok(source) => {
+                    let mut builder = MediaBuilder::new(source).filename(&name).mode(self.mode);
+                    if !local {
+                        builder = builder.sniff(SniffPolicy::Warn);
+                    }
+                    items.push(builder);
+                    queued.push(name);
+                }
+                Err(e) => {
+                    stored.insert(name, Err(e.to_string()));
+                }
+            }
+        }
+
+        let outcome = match self.mode {
+            StoreMode::DirectCopy => {
+                MediaAction::store_many_and_reload(anki_client, items, self.concurrency).await?
+            }
+            StoreMode::Upload => {
+                MediaAction::store_many(anki_client, items, self.concurrency).await
+            }
+        };
+        for (name, res) in queued.into_iter().zip(outcome.into_results()) {
+            stored.insert(name, res.map_err(|e| e.to_string()));
+        }
+        Ok(stored)
+    }
+
+    fn build_note(
+        &self,
+        mut row: Row,
+        stored: &HashMap<String, Result<String, String>>,
+    ) -> Result<NewNote, AnkiError> {
+        for (field, kind, names) in row.media {
+            let value = row.fields.entry(field).or_default();
+            for name in names {
+                let stored = match stored.get(&name) {
+                    Some(Ok(stored)) => stored,
+                    Some(Err(e)) => {
+                        return Err(AnkiError::RequestError(format!(
+                            "media file `{}` couldn't be stored: {}",
+                            name, e
+                        )))
+                    }
+                    None => return Err(AnkiError::NoDataFound),
+                };
+                match kind {
+                    MediaKind::Audio => value.push_str(&format!("[sound:{}]", stored)),
+                    MediaKind::Image => value.push_str(&format!("<img src=\"{}\">", stored)),
+                }
+            }
+        }
+        Ok(NewNote {
+            deckName: self.deck.clone(),
+            modelName: self.model.clone(),
+            fields: row.fields,
+            tags: row.tags,
+            ..NewNote::default()
+        })
+    }
+}
+
+/// The filenames in a media cell: Anki references if it has any, otherwise the cell
+/// itself. An empty cell names no file.
+fn media_names(cell: &str) -> Result<Vec<String>, AnkiError> {
+    if cell.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut names = extract_media_refs(cell);
+    if names.is_empty() {
+        names.push(cell.to_string());
+    }
+    for name in &names {
+        ensure_flat_filename(name)?;
+    }
+    Ok(names)
+}
@@ -0,0 +1,261 @@
+#![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
+use crate::error::AnkiError;
+use crate::id::CardId;
+use crate::{post_generic_request, post_simple_action, AnkiClient};
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+#[cfg(not(target_arch = "wasm32"))]
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Sorts the Browse window by a column, e.g. `noteCrt` or `cardDue`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReorderCards {
+    pub order: SortOrder,
+    pub columnId: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuiBrowseParams {
+    pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reorderCards: Option<ReorderCards>,
+}
+
+impl ActionParams for GuiBrowseParams {
+    const ACTION: Action = Action::GuiBrowse;
+}
+
+/// The answer buttons of Anki's reviewer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(from = "u8", into = "u8")]
+pub enum Ease {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl From<u8> for Ease {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Ease::Again,
+            2 => Ease::Hard,
+            4 => Ease::Easy,
+            _ => Ease::Good,
+        }
+    }
+}
+
+impl From<Ease> for u8 {
+    fn from(value: Ease) -> Self {
+        match value {
+            Ease::Again => 1,
+            Ease::Hard => 2,
+            Ease::Good => 3,
+            Ease::Easy => 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuiAnswerCardParams {
+    pub ease: Ease,
+}
+
+impl ActionParams for GuiAnswerCardParams {
+    const ACTION: Action = Action::GuiAnswerCard;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuiDeckOverviewParams {
+    pub name: String,
+}
+
+impl ActionParams for GuiDeckOverviewParams {
+    const ACTION: Action = Action::GuiDeckOverview;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuiDeckReviewParams {
+    pub name: String,
+}
+
+impl ActionParams for GuiDeckReviewParams {
+    const ACTION: Action = Action::GuiDeckReview;
+}
+
+/// The GUI actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct GuiAction;
+
+impl GuiAction {
+    /// Opens the Browse window on `query`, optionally sorted by a column,
+    /// and returns the ids of the matching cards.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::gui::GuiAction;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let cards = GuiAction::browse(&client, "added:1", None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn browse(
+        anki_client: &AnkiClient,
+        query: &str,
+        reorder: Option<ReorderCards>,
+    ) -> Result<Vec<CardId>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            GuiBrowseParams {
+                query: query.to_string(),
+                reorderCards: reorder,
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Shows the question of the current card in the reviewer.
+    ///
+    /// Returns `false` if the reviewer isn't open.
+    pub async fn show_question(anki_client: &AnkiClient) -> Result<bool, AnkiError> {
+        post_simple_action::<bool>(anki_client, Action::GuiShowQuestion).await
+    }
+
+    /// Reveals the answer of the current card in the reviewer.
+    ///
+    /// Returns `false` if the reviewer isn't open.
+    pub async fn show_answer(anki_client: &AnkiClient) -> Result<bool, AnkiError> {
+        post_simple_action::<bool>(anki_client, Action::GuiShowAnswer).await
+    }
+
+    /// Answers the current card. The answer must be showing first.
+    ///
+    /// Returns `false` if there is no card to answer.
+    pub async fn answer_card(anki_client: &AnkiClient, ease: Ease) -> Result<bool, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, GuiAnswerCardParams { ease });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Restarts the answer timer of the current card, e.g. after a pause.
+    pub async fn start_card_timer(anki_client: &AnkiClient) -> Result<bool, AnkiError> {
+        post_simple_action::<bool>(anki_client, Action::GuiStartCardTimer).await
+    }
+
+    /// Runs Anki's Check Database, e.g. after bulk deletes or model migrations.
+    ///
+    /// Blocks until the check has finished and returns whether it completed.
+    pub async fn check_database(anki_client: &AnkiClient) -> Result<bool, AnkiError> {
+        post_simple_action::<bool>(anki_client, Action::GuiCheckDatabase).await
+    }
+
+    /// Asks Anki to close. Anki saves the collection and exits shortly after answering.
+    pub async fn exit_anki(anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        let payload = serde_json::json!({
+            "action": Action::GuiExitAnki,
+            "version": anki_client.version,
+        });
+
+        post_generic_request::<serde_json::Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Asks Anki to close and waits until AnkiConnect stops accepting connections,
+    /// so the profile folder can safely be backed up afterwards.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn exit_anki_and_wait(
+        anki_client: &AnkiClient,
+        timeout: Duration,
+    ) -> Result<(), AnkiError> {
+        GuiAction::exit_anki(anki_client).await?;
+
+        let deadline = Instant::now().checked_add(timeout);
+        let probe =
+            serde_json::json!({ "action": Action::Version, "version": anki_client.version });
+        loop {
+            let res = anki_client
+                .client
+                .post(&anki_client.endpoint)
+                .json(&probe)
+                .timeout(EXIT_POLL_INTERVAL)
+                .send()
+                .await;
+            if let Err(e) = res {
+                if e.is_connect() {
+                    return Ok(());
+                }
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(AnkiError::RequestError(format!(
+                    "Anki was still running {:?} after guiExitAnki",
+                    timeout
+                )));
+            }
+            tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Opens the overview screen of a deck.
+    ///
+    /// Returns `false` if the deck doesn't exist.
+    pub async fn deck_overview(anki_client: &AnkiClient, name: &str) -> Result<bool, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            GuiDeckOverviewParams {
+                name: name.to_string(),
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Opens the deck browser, Anki's main deck list.
+    pub async fn deck_browser(anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        let payload = serde_json::json!({
+            "action": Action::GuiDeckBrowser,
+            "version": anki_client.version,
+        });
+
+        post_generic_request::<serde_json::Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Starts reviewing a deck.
+    ///
+    /// Returns `false` if the deck doesn't exist.
+    pub async fn deck_review(anki_client: &AnkiClient, name: &str) -> Result<bool, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            GuiDeckReviewParams {
+                name: name.to_string(),
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+}
@@ -0,0 +1,116 @@
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::num::{ParseIntError, TryFromIntError};
+use std::str::FromStr;
+
+macro_rules! ids {
+    ($($(#[$doc:meta])* $name:ident;)*) => {
+        $(
+            $(#[$doc])*
+            #[derive(
+                Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+            )]
+            #[serde(transparent)]
+            pub struct $name(pub u64);
+
+            impl Display for $name {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    self.0.fmt(f)
+                }
+            }
+
+            impl FromStr for $name {
+                type Err = ParseIntError;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    s.parse().map($name)
+                }
+            }
+
+            impl From<u64> for $name {
+                fn from(id: u64) -> Self {
+                    $name(id)
+                }
+            }
+
+            impl From<u32> for $name {
+                fn from(id: u32) -> Self {
+                    $name(id.into())
+                }
+            }
+
+            impl TryFrom<i64> for $name {
+                type Error = TryFromIntError;
+
+                fn try_from(id: i64) -> Result<Self, Self::Error> {
+                    u64::try_from(id).map($name)
+                }
+            }
+
+            impl TryFrom<u128> for $name {
+                type Error = TryFromIntError;
+
+                fn try_from(id: u128) -> Result<Self, Self::Error> {
+                    u64::try_from(id).map($name)
+                }
+            }
+
+            impl TryFrom<usize> for $name {
+                type Error = TryFromIntError;
+
+                fn try_from(id: usize) -> Result<Self, Self::Error> {
+                    u64::try_from(id).map($name)
+                }
+            }
+
+            impl From<$name> for u64 {
+                fn from(id: $name) -> Self {
+                    id.0
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! created_at {
+    ($($name:ident),*) => {
+        $(
+            #[cfg(feature = "chrono")]
+            impl $name {
+                /// When the object was created, which Anki encodes in its id, or `None`
+                /// if the id isn't a valid timestamp.
+                pub fn created_at(self) -> Option<DateTime<Utc>> {
+                    from_millis(self.0)
+                }
+            }
+        )*
+    };
+}
+
+ids! {
+    /// A note's id, the millisecond timestamp of its creation.
+    NoteId;
+    /// A card's id, the millisecond timestamp of its creation.
+    CardId;
+    /// A deck's id. `1` is the Default deck.
+    DeckId;
+    /// A model's (note type's) id, the millisecond timestamp of its creation.
+    ModelId;
+}
+
+// The Default deck has id 1, so deck ids aren't reliably timestamps.
+created_at!(NoteId, CardId, ModelId);
+
+/// A time in milliseconds since the epoch, as in Anki's ids.
+#[cfg(feature = "chrono")]
+fn from_millis(millis: u64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(i64::try_from(millis).ok()?)
+}
+
+/// A time in seconds since the epoch, as in Anki's `mod` fields.
+#[cfg(feature = "chrono")]
+pub(crate) fn from_secs(secs: u64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(i64::try_from(secs).ok()?, 0)
+}
@@ -1,9 +1,73 @@
+pub mod action;
+pub mod batch;
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
+pub mod builder;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod cancel;
+pub mod cards;
+pub mod chunk;
+pub mod config;
+pub mod decks;
+pub mod dry_run;
 pub mod error;
+pub mod fixture;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod folder_import;
+pub mod gui;
+pub mod id;
+pub mod limit;
+pub mod media;
+pub mod metrics;
+pub mod middleware;
+pub mod misc;
+pub mod mock;
+pub mod models;
 pub mod notes;
+pub mod occlusion;
+pub mod payload;
+pub mod query;
 pub mod result;
+pub mod review;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub mod sqlite;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod template;
 mod test;
+pub mod transport;
+pub mod version;
+#[cfg(all(feature = "stats", not(target_arch = "wasm32")))]
+pub mod watcher;
+pub mod yomitan;
 
+pub use crate::action::Action;
+pub use crate::builder::AnkiClientBuilder;
+pub use crate::id::{CardId, DeckId, ModelId, NoteId};
+
+use crate::action::ActionParams;
+use crate::chunk::ChunkPolicy;
+use crate::dry_run::{DryRunPlan, PlannedAction};
+use crate::error::AnkiError;
+use crate::fixture::RecordingTransport;
+use crate::limit::{Limiter, RateLimit};
+use crate::metrics::{MetricsRecorder, MetricsSnapshot};
+use crate::middleware::Middleware;
+use crate::payload::{Compression, DEFAULT_MAX_PAYLOAD_BYTES};
+use crate::query::QueryPresets;
+use crate::result::GenericResult;
+use crate::transport::{AnkiTransport, TransportError};
+use base64::Engine;
 use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
 /// `AnkiClient` is a struct that allows you to communicate with the AnkiConnect API.
 ///
@@ -11,22 +75,47 @@ use reqwest::Client;
 /// - `endpoint`: The endpoint where AnkiConnect is running. Defaults to `http://localhost:8765`.
 /// - `client`: The HTTP client used to send requests.
 /// - `version`: The version of the AnkiConnect plugin. Defaults to `6`.
+/// - `api_key`: The `apiKey` configured in AnkiConnect, sent with every request if set.
+///
+/// Clones share the AnkiConnect version detected by `anki_connect_version`.
 #[derive(Clone, Debug)]
 pub struct AnkiClient {
     pub endpoint: String,
     pub client: Client,
     pub version: u8,
+    pub api_key: Option<String>,
+    /// The version reported by AnkiConnect, or `0` until it has been asked.
+    detected_version: Arc<AtomicU8>,
+    limiter: Option<Arc<Limiter>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    metrics: Option<Arc<MetricsRecorder>>,
+    /// Replaces the `reqwest` calls to `endpoint` when set.
+    transport: Option<Arc<dyn AnkiTransport>>,
+    dry_run: Option<Arc<DryRunPlan>>,
+    queries: QueryPresets,
+    compression: Compression,
+    /// The largest `addNotes` body sent in one request, or `None` for no limit.
+    max_payload_bytes: Option<usize>,
+    /// How long id lists are split, or `None` to send them whole.
+    chunking: Option<ChunkPolicy>,
 }
 
 impl Default for AnkiClient {
     /// Creates a new `AnkiClient` with default values.
     /// * `port`: The port where AnkiConnect is running. Defaults to `8765`.
     /// * `version`: The version of the AnkiConnect plugin. Defaults to `6`.
+    ///
     /// To change these defaults, use `Ankiclient::new()` instead;
     ///
+    /// # Panics
+    ///
+    /// If the TLS backend can't be initialized. `AnkiClient::builder().build()` returns
+    /// an error instead.
+    ///
     /// # Example
     ///
     /// ```
+    /// # use anki_direct::AnkiClient;
     /// let client = AnkiClient::default();
     /// ```
     fn default() -> Self {
@@ -34,6 +123,17 @@ impl Default for AnkiClient {
             endpoint: "http://localhost:8765".to_string(),
             client: Client::new(),
             version: 6,
+            api_key: None,
+            detected_version: Arc::default(),
+            limiter: None,
+            middleware: Vec::new(),
+            metrics: None,
+            transport: None,
+            dry_run: None,
+            queries: QueryPresets::default(),
+            compression: Compression::None,
+            max_payload_bytes: Some(DEFAULT_MAX_PAYLOAD_BYTES),
+            chunking: Some(ChunkPolicy::default()),
         }
     }
 }
@@ -46,20 +146,59 @@ impl AnkiClient {
     /// * `port`: The port where AnkiConnect is running.
     /// * `version`: The version of the AnkiConnect plugin.
     ///
+    /// # Panics
+    ///
+    /// If the TLS backend can't be initialized. `AnkiClient::builder().build()` returns
+    /// an error instead.
+    ///
     /// # Example
     ///
     /// ```
+    /// # use anki_direct::AnkiClient;
     /// let client = AnkiClient::new("8765", 6);
     /// ```
     pub fn new(port: &str, version: u8) -> Self {
         Self {
-            endpoint: format!("http://{}", port),
+            endpoint: format!("http://localhost:{}", port),
             client: Client::new(),
             version,
+            api_key: None,
+            detected_version: Arc::default(),
+            limiter: None,
+            middleware: Vec::new(),
+            metrics: None,
+            transport: None,
+            dry_run: None,
+            queries: QueryPresets::default(),
+            compression: Compression::None,
+            max_payload_bytes: Some(DEFAULT_MAX_PAYLOAD_BYTES),
+            chunking: Some(ChunkPolicy::default()),
         }
     }
 
-    /// Formats the URL from the provided port.
+    /// Creates a new `AnkiClient` for AnkiConnect at a full URL, e.g. on another
+    /// machine, in Docker, or behind a TLS reverse proxy.
+    ///
+    /// # Parameters
+    ///
+    /// * `endpoint`: An `http` or `https` URL, such as `https://anki.my-lan:8765`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use anki_direct::AnkiClient;
+    /// let client = AnkiClient::new_endpoint("https://anki.my-lan:8765").unwrap();
+    /// ```
+    pub fn new_endpoint(endpoint: &str) -> Result<Self, AnkiError> {
+        let url = parse_endpoint(endpoint)?;
+        Ok(Self {
+            endpoint: url.to_string(),
+            ..Self::default()
+        })
+    }
+
+    /// Formats the URL from the provided port, keeping the scheme and host of the
+    /// client's endpoint.
     ///
     /// # Parameters
     ///
@@ -68,9 +207,598 @@ impl AnkiClient {
     /// # Example
     ///
     /// ```
+    /// # use anki_direct::AnkiClient;
+    /// # let client = AnkiClient::default();
     /// let url = client.format_url("8765");
     /// ```
     pub fn format_url(&self, port: &str) -> String {
-        format!("http://localhost:{}", port)
+        match reqwest::Url::parse(&self.endpoint) {
+            Ok(url) => format!(
+                "{}://{}:{}",
+                url.scheme(),
+                url.host_str().unwrap_or("localhost"),
+                port
+            ),
+            Err(_) => format!("http://localhost:{}", port),
+        }
+    }
+
+    /// Limits the requests sent by this client and its clones.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::limit::RateLimit;
+    /// # use std::time::Duration;
+    /// let client = AnkiClient::default().with_rate_limit(RateLimit {
+    ///     max_in_flight: 2,
+    ///     min_interval: Duration::from_millis(20),
+    /// });
+    /// ```
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.limiter = Some(Arc::new(Limiter::new(limit)));
+        self
+    }
+
+    /// A handle sending requests with another AnkiConnect API `version`, for single
+    /// calls that need different semantics than the rest. It shares the connection,
+    /// limits, middleware and metrics of this client.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::notes::NoteAction;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::new("8765", 5);
+    /// let ids = NoteAction::find_note_ids(&client, "deck:Mining").await?;
+    /// let notes = NoteAction::get_notes_infos(&client.at_version(6), ids).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn at_version(&self, version: u8) -> AnkiClient {
+        AnkiClient {
+            version,
+            ..self.clone()
+        }
+    }
+
+    /// Sends any action, e.g. one provided by an add-on or too new for this crate,
+    /// and returns its result as JSON. `params` is omitted from the request when `null`.
+    ///
+    /// The request goes through the same middleware, limits, version checks and
+    /// chunking as the wrapped actions.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let due = client
+    ///     .raw("areDue", serde_json::json!({ "cards": [1483959291685u64] }))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn raw(
+        &self,
+        action: impl Into<Action>,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, AnkiError> {
+        self.raw_typed(action, params).await
+    }
+
+    /// Like `raw`, but parses the result into `T`.
+    ///
+    /// Actions that answer `null` on success can be read as `()`.
+    pub async fn raw_typed<T: DeserializeOwned>(
+        &self,
+        action: impl Into<Action>,
+        params: serde_json::Value,
+    ) -> Result<T, AnkiError> {
+        let mut payload = serde_json::json!({ "action": action.into(), "version": self.version });
+        if !params.is_null() {
+            payload["params"] = params;
+        }
+        let res: GenericResult<T> = post_generic_request(&payload, self).await?;
+        match (res.error, res.result) {
+            (Some(e), _) => Err(AnkiError::from_message(e)),
+            (None, Some(result)) => Ok(result),
+            (None, None) => match serde_json::from_value(serde_json::Value::Null) {
+                Ok(result) => Ok(result),
+                Err(_) => Err(AnkiError::NoDataFound),
+            },
+        }
+    }
+
+    /// The named queries of this client, shared with its clones.
+    pub fn queries(&self) -> &QueryPresets {
+        &self.queries
+    }
+
+    /// Uses `queries` as this client's presets, e.g. the ones persisted in a cache.
+    pub fn with_queries(mut self, queries: QueryPresets) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    /// Encodes request bodies with `compression`. Only applies to the default transport.
+    ///
+    /// AnkiConnect doesn't decode compressed bodies itself; use this for endpoints behind
+    /// a reverse proxy that does.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Splits `addNotes` requests larger than `limit` bytes, e.g. with lots of inline
+    /// base64 media, into several smaller ones. `None` sends every request whole.
+    ///
+    /// Defaults to `payload::DEFAULT_MAX_PAYLOAD_BYTES`.
+    pub fn with_max_payload_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_payload_bytes = limit;
+        self
+    }
+
+    /// Splits actions taking long id lists, like `notesInfo` or `deleteNotes`, into
+    /// several requests. `None` sends every id in one request.
+    ///
+    /// Defaults to `ChunkPolicy::default()`.
+    pub fn with_chunking(mut self, policy: Option<ChunkPolicy>) -> Self {
+        self.chunking = policy;
+        self
+    }
+
+    /// Adds a middleware run around every request of this client and its later clones.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sends requests through a custom transport instead of `reqwest`.
+    /// Middleware, limits and version checks still apply.
+    pub fn with_transport(mut self, transport: impl AnkiTransport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Records every request and response of this client to a JSON fixture at `path`,
+    /// wrapping the current transport. Replay it with `fixture::ReplayTransport`.
+    pub fn record_to(mut self, path: impl AsRef<std::path::Path>) -> Self {
+        let inner: Arc<dyn AnkiTransport> = match self.transport.take() {
+            Some(transport) => transport,
+            None => Arc::new(transport::ReqwestTransport {
+                endpoint: self.endpoint.clone(),
+                client: self.client.clone(),
+                compression: self.compression,
+            }),
+        };
+        self.transport = Some(Arc::new(RecordingTransport::from_arc(path, inner)));
+        self
+    }
+
+    /// Turns on dry-run mode: mutating actions (add, update, delete, store, ...) are not
+    /// sent but recorded, and answered with a `null` result. Read actions still go to Anki.
+    ///
+    /// Intercepted actions that normally return a value, like `addNote`, therefore fail
+    /// with `AnkiError::NoDataFound` when read with `into_result`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::misc::MiscAction;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default().with_dry_run();
+    /// MiscAction::reload_collection(&client).await?;
+    /// for planned in client.dry_run_plan().unwrap_or_default() {
+    ///     println!("would send {} with {}", planned.action, planned.params);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_dry_run(mut self) -> Self {
+        if self.dry_run.is_none() {
+            self.dry_run = Some(Arc::default());
+        }
+        self
+    }
+
+    /// The actions intercepted since `with_dry_run`, or `None` outside dry-run mode.
+    pub fn dry_run_plan(&self) -> Option<Vec<PlannedAction>> {
+        self.dry_run.as_ref().map(|plan| plan.actions())
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    /// Starts collecting per-action request metrics, shared with later clones.
+    /// Does nothing if metrics are already being collected.
+    pub fn with_metrics(mut self) -> Self {
+        if self.metrics.is_none() {
+            let recorder = Arc::new(MetricsRecorder::default());
+            self.middleware.push(recorder.clone());
+            self.metrics = Some(recorder);
+        }
+        self
+    }
+
+    /// A snapshot of the metrics collected since `with_metrics`, if enabled.
+    pub fn metrics(&self) -> Option<MetricsSnapshot> {
+        self.metrics.as_ref().map(|m| m.snapshot())
+    }
+
+    /// The limit set with `with_rate_limit`, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.limiter.as_ref().map(|l| l.limit())
+    }
+
+    /// Asks AnkiConnect for its API version and remembers it for version checks.
+    ///
+    /// Actions listed in `version::ACTION_MIN_VERSIONS` fail with
+    /// `AnkiError::VersionTooOld` when the running AnkiConnect is older than they need.
+    /// The version is detected on first use of such an action if this hasn't been called.
+    ///
+    /// Reads the `apiVersion` AnkiConnect reports to a plain `GET` first, and falls back
+    /// to the `version` action when that is unavailable or unreadable.
+    pub async fn anki_connect_version(&self) -> Result<u8, AnkiError> {
+        let version = match self.get_api_version().await {
+            Some(version) => version,
+            None => {
+                let payload =
+                    serde_json::json!({ "action": Action::Version, "version": self.version });
+                send_request(payload, self).await?.into_result()?
+            }
+        };
+        self.detected_version.store(version, Ordering::Relaxed);
+        Ok(version)
+    }
+
+    /// The version from a `GET` of the endpoint, if the default transport is in use
+    /// and the answer contains one.
+    async fn get_api_version(&self) -> Option<u8> {
+        if self.transport.is_some() {
+            return None;
+        }
+        let res = self.client.get(&self.endpoint).send().await.ok()?;
+        let info: version::ApiVersionInfo = res.json().await.ok()?;
+        info.version()
+    }
+
+    /// Checks that AnkiConnect is reachable and returns how long it took to answer.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # async fn run() {
+    /// let client = AnkiClient::default();
+    /// let anki_is_up = client.ping().await.is_ok();
+    /// # }
+    /// ```
+    pub async fn ping(&self) -> Result<std::time::Duration, AnkiError> {
+        let start = Instant::now();
+        self.probe_version().await?;
+        Ok(start.elapsed())
+    }
+
+    /// Asks AnkiConnect for its version once, without middleware, limits or reconnecting.
+    async fn probe_version(&self) -> Result<u8, AnkiError> {
+        let mut payload = serde_json::json!({ "action": Action::Version, "version": self.version });
+        if let Some(key) = &self.api_key {
+            payload["key"] = key.clone().into();
+        }
+        let body = post_json(&payload, self).await?;
+        match serde_json::from_value::<GenericResult<u8>>(body) {
+            Ok(res) => res.into_result(),
+            Err(e) => Err(AnkiError::ParseError(e.to_string())),
+        }
     }
+
+    /// Re-probes the endpoint after a connection error, e.g. because Anki restarted,
+    /// and re-detects the version, which may have changed with an add-on update.
+    async fn reconnect(&self) -> Result<(), AnkiError> {
+        let version = self.probe_version().await?;
+        self.detected_version.store(version, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether responses can be parsed straight into their result type: nothing
+    /// replaces the HTTP call, and no middleware needs to see the raw envelope.
+    fn parses_directly(&self) -> bool {
+        self.transport.is_none() && self.dry_run.is_none() && self.middleware.is_empty()
+    }
+
+    /// Fails with `VersionTooOld` if `action` needs a newer AnkiConnect than the one running.
+    pub(crate) async fn check_version(&self, action: &str) -> Result<(), AnkiError> {
+        let Some(need) = version::min_version(action) else {
+            return Ok(());
+        };
+        let have = match self.detected_version.load(Ordering::Relaxed) {
+            0 => self.anki_connect_version().await?,
+            have => have,
+        };
+        match have >= need {
+            true => Ok(()),
+            false => Err(AnkiError::VersionTooOld {
+                action: action.to_string(),
+                have,
+                need,
+            }),
+        }
+    }
+}
+
+/// Parses an AnkiConnect endpoint URL, accepting only `http` and `https`.
+pub(crate) fn parse_endpoint(endpoint: &str) -> Result<reqwest::Url, AnkiError> {
+    let url = match reqwest::Url::parse(endpoint) {
+        Ok(url) => url,
+        Err(e) => return Err(AnkiError::ValidationError(format!("{}: {}", endpoint, e))),
+    };
+    match (url.scheme(), url.has_host()) {
+        ("http" | "https", true) => Ok(url),
+        _ => Err(AnkiError::ValidationError(format!(
+            "{} is not an http or https URL",
+            endpoint
+        ))),
+    }
+}
+
+/// Sends any serializable action payload to AnkiConnect and parses the response envelope.
+pub(crate) async fn post_generic_request<T: DeserializeOwned>(
+    payload: &impl Serialize,
+    anki_client: &AnkiClient,
+) -> Result<GenericResult<T>, AnkiError> {
+    let payload = match serde_json::to_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => return Err(AnkiError::ParseError(e.to_string())),
+    };
+    if let Some(action) = payload["action"].as_str() {
+        anki_client.check_version(action).await?;
+    }
+    if let Some(limit) = anki_client.max_payload_bytes {
+        if payload["action"] == Action::AddNotes.name() {
+            return payload::post_add_notes(payload, limit, anki_client).await;
+        }
+    }
+    if let Some(policy) = anki_client.chunking {
+        if let Some((chunks, merge)) = chunk::split_ids(&payload, policy.size) {
+            return chunk::post_chunks(chunks, merge, policy.parallelism, anki_client).await;
+        }
+    }
+
+    send_request(payload, anki_client).await
+}
+
+/// Like `post_generic_request`, but keeps each result as raw JSON for parsers that
+/// borrow from it. Chunked actions give one result per chunk, sent one after another.
+pub(crate) async fn post_raw_request(
+    payload: &impl Serialize,
+    anki_client: &AnkiClient,
+) -> Result<Vec<Box<serde_json::value::RawValue>>, AnkiError> {
+    let payload = match serde_json::to_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => return Err(AnkiError::ParseError(e.to_string())),
+    };
+    if let Some(action) = payload["action"].as_str() {
+        anki_client.check_version(action).await?;
+    }
+    let chunks = match anki_client.chunking {
+        Some(policy) => chunk::split_ids(&payload, policy.size).map(|(chunks, _)| chunks),
+        None => None,
+    };
+
+    let mut results = Vec::new();
+    for chunk in chunks.unwrap_or_else(|| vec![payload]) {
+        results.push(send_request(chunk, anki_client).await?.into_result()?);
+    }
+    Ok(results)
+}
+
+pub(crate) async fn send_request<T: DeserializeOwned>(
+    mut payload: serde_json::Value,
+    anki_client: &AnkiClient,
+) -> Result<GenericResult<T>, AnkiError> {
+    if let (Some(key), Some(fields)) = (&anki_client.api_key, payload.as_object_mut()) {
+        fields.insert("key".to_string(), key.clone().into());
+    }
+    let action = payload["action"].as_str().unwrap_or_default().to_string();
+    for middleware in &anki_client.middleware {
+        middleware.before(&action, &mut payload);
+    }
+    let _permit = match &anki_client.limiter {
+        Some(limiter) => limiter.acquire().await,
+        None => None,
+    };
+
+    let start = Instant::now();
+    if anki_client.parses_directly() {
+        let res = send_typed(&payload, anki_client).await;
+        #[cfg(feature = "tracing")]
+        trace_request(
+            &action,
+            payload.to_string().len(),
+            res.as_ref().map(|res| res.error.as_deref()),
+            start.elapsed(),
+        );
+        return res;
+    }
+    let planned = anki_client
+        .dry_run
+        .as_ref()
+        .and_then(|plan| plan.intercept(&payload));
+    let body = match planned {
+        Some(body) => Ok(body),
+        None => send_json(&payload, anki_client).await,
+    };
+    for middleware in anki_client.middleware.iter().rev() {
+        middleware.after(&action, body.as_ref(), start.elapsed());
+    }
+    #[cfg(feature = "tracing")]
+    trace_request(
+        &action,
+        payload.to_string().len(),
+        body.as_ref().map(|body| body["error"].as_str()),
+        start.elapsed(),
+    );
+
+    match serde_json::from_value(body?) {
+        Ok(res) => Ok(res),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+/// Sends an action with a large binary param, e.g. a media file, base64 encoding
+/// `data` straight into the request body as `params[field]`, so only the file and its
+/// encoded body are in memory at once instead of several copies of the base64 string.
+///
+/// Clients whose middleware, transport or dry run needs the payload as a `Value` get
+/// the usual path instead.
+pub(crate) async fn post_base64_request<T: DeserializeOwned, P: ActionParams>(
+    params: &P,
+    field: &str,
+    data: &[u8],
+    anki_client: &AnkiClient,
+) -> Result<GenericResult<T>, AnkiError> {
+    anki_client.check_version(P::ACTION.name()).await?;
+    let mut envelope = serde_json::json!({ "action": P::ACTION, "version": anki_client.version });
+    if let Some(key) = &anki_client.api_key {
+        envelope["key"] = key.clone().into();
+    }
+    let params = match serde_json::to_value(params) {
+        Ok(params) => params,
+        Err(e) => return Err(AnkiError::ParseError(e.to_string())),
+    };
+    if !anki_client.parses_directly() {
+        let mut payload = envelope;
+        payload["params"] = params;
+        payload["params"][field] = base64::engine::general_purpose::STANDARD
+            .encode(data)
+            .into();
+        return send_request(payload, anki_client).await;
+    }
+
+    let body = payload::write_base64_body(&envelope, &params, field, data)?;
+    let (body, encoding) = payload::compress(body, anki_client.compression)?;
+    let _permit = match &anki_client.limiter {
+        Some(limiter) => limiter.acquire().await,
+        None => None,
+    };
+    #[cfg(feature = "tracing")]
+    let (start, payload_bytes) = (Instant::now(), body.len());
+
+    let req = transport::post_body(&anki_client.client, &anki_client.endpoint, body, encoding);
+    let retry = req.try_clone();
+    let res = match transport::send_typed(req).await {
+        Ok(res) => Ok(res),
+        Err(TransportError::Unreachable(e)) => match retry {
+            Some(retry) if anki_client.reconnect().await.is_ok() => {
+                transport::send_typed(retry).await.map_err(AnkiError::from)
+            }
+            _ => Err(AnkiError::RequestError(e)),
+        },
+        Err(e) => Err(e.into()),
+    };
+    #[cfg(feature = "tracing")]
+    trace_request(
+        P::ACTION.name(),
+        payload_bytes,
+        res.as_ref().map(|res| res.error.as_deref()),
+        start.elapsed(),
+    );
+    res
+}
+
+#[cfg(feature = "tracing")]
+fn trace_request(
+    action: &str,
+    payload_bytes: usize,
+    outcome: Result<Option<&str>, &AnkiError>,
+    elapsed: std::time::Duration,
+) {
+    match outcome {
+        Ok(Some(error)) => {
+            tracing::warn!(action, ?elapsed, payload_bytes, error, "AnkiConnect error")
+        }
+        Ok(None) => tracing::debug!(action, ?elapsed, payload_bytes, "AnkiConnect request"),
+        Err(error) => {
+            tracing::warn!(action, ?elapsed, payload_bytes, %error, "AnkiConnect request failed")
+        }
+    }
+}
+
+/// Sends a payload, retrying once if the connection was refused but AnkiConnect
+/// answers again, so a client survives Anki restarting mid-session.
+///
+/// Only connection failures are retried, since the request never reached Anki.
+async fn send_json(
+    payload: &serde_json::Value,
+    anki_client: &AnkiClient,
+) -> Result<serde_json::Value, AnkiError> {
+    match post_json(payload, anki_client).await {
+        Ok(body) => Ok(body),
+        Err(TransportError::Unreachable(_)) if anki_client.reconnect().await.is_ok() => {
+            Ok(post_json(payload, anki_client).await?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `send_json` for clients without anything that needs the raw response envelope,
+/// parsing the response in a single pass.
+async fn send_typed<T: DeserializeOwned>(
+    payload: &serde_json::Value,
+    anki_client: &AnkiClient,
+) -> Result<GenericResult<T>, AnkiError> {
+    let send = || {
+        transport::reqwest_call_typed(
+            &anki_client.client,
+            &anki_client.endpoint,
+            payload,
+            anki_client.compression,
+        )
+    };
+    match send().await {
+        Ok(res) => Ok(res),
+        Err(TransportError::Unreachable(_)) if anki_client.reconnect().await.is_ok() => {
+            Ok(send().await?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn post_json(
+    payload: &serde_json::Value,
+    anki_client: &AnkiClient,
+) -> Result<serde_json::Value, TransportError> {
+    match &anki_client.transport {
+        Some(transport) => transport.call(payload).await,
+        None => {
+            transport::reqwest_call(
+                &anki_client.client,
+                &anki_client.endpoint,
+                payload,
+                anki_client.compression,
+            )
+            .await
+        }
+    }
+}
+
+/// Sends a parameterless action and parses its result.
+pub(crate) async fn post_simple_action<T: DeserializeOwned>(
+    anki_client: &AnkiClient,
+    action: Action,
+) -> Result<T, AnkiError> {
+    let payload = serde_json::json!({
+        "action": action,
+        "version": anki_client.version,
+    });
+
+    post_generic_request(&payload, anki_client)
+        .await?
+        .into_result()
 }
@@ -0,0 +1,73 @@
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::{sync::Mutex, time::Instant};
+
+/// Limits how hard a client drives AnkiConnect.
+///
+/// AnkiConnect runs every action on Anki's main thread, so a flood of requests
+/// from a bulk tool freezes the GUI. Every request sent through a limited client,
+/// including those made by the bulk helpers, waits for its turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Most requests awaiting a response at once. At least 1.
+    pub max_in_flight: usize,
+    /// Least time between the starts of two requests. Not enforced on wasm32,
+    /// which has no timer to wait on.
+    pub min_interval: Duration,
+}
+
+impl Default for RateLimit {
+    /// Unlimited: as many requests as the caller sends, back to back.
+    fn default() -> Self {
+        Self {
+            max_in_flight: Semaphore::MAX_PERMITS,
+            min_interval: Duration::ZERO,
+        }
+    }
+}
+
+/// The shared state enforcing a `RateLimit` across clones of a client.
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    limit: RateLimit,
+    in_flight: Semaphore,
+    #[cfg(not(target_arch = "wasm32"))]
+    next_start: Mutex<Instant>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        let max_in_flight = limit.max_in_flight.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            limit,
+            in_flight: Semaphore::new(max_in_flight),
+            #[cfg(not(target_arch = "wasm32"))]
+            next_start: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Waits until a request may start. The request counts as in flight until
+    /// the returned permit is dropped.
+    ///
+    /// The semaphore is never closed, so the permit is always `Some`.
+    pub(crate) async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let permit = self.in_flight.acquire().await.ok();
+        #[cfg(not(target_arch = "wasm32"))]
+        if !self.limit.min_interval.is_zero() {
+            let mut next_start = self.next_start.lock().await;
+            tokio::time::sleep_until(*next_start).await;
+            let now = Instant::now();
+            *next_start = match now.checked_add(self.limit.min_interval) {
+                Some(next) => next,
+                // An interval too long to represent; wait a year instead.
+                None => now + Duration::from_secs(365 * 24 * 60 * 60),
+            };
+        }
+        permit
+    }
+}
@@ -0,0 +1,1510 @@
+#![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::batch::BatchOutcome;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cancel::AbortOnDrop;
+use crate::error::AnkiError;
+use crate::id::NoteId;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::misc::MiscAction;
+use crate::notes::NoteAction;
+use crate::{post_base64_request, post_generic_request, AnkiClient};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Debug;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::sync::Semaphore;
+
+/// Where the contents of a media file come from.
+pub enum MediaSource {
+    /// Raw file contents, sent base64 encoded.
+    Bytes(Vec<u8>),
+    /// A path on the machine running Anki, read by AnkiConnect.
+    Path(PathBuf),
+    /// A URL downloaded by AnkiConnect.
+    Url(String),
+    /// A `data:` URL, e.g. `data:image/png;base64,iVBOR...`, decoded by the crate.
+    DataUrl(String),
+    /// Contents streamed from an in-memory pipeline (TTS output, screenshots), read to the end.
+    Reader(Box<dyn Read + Send>),
+}
+
+impl Debug for MediaSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaSource::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            MediaSource::Path(path) => write!(f, "Path({:?})", path),
+            MediaSource::Url(url) => write!(f, "Url({:?})", url),
+            MediaSource::DataUrl(url) => write!(f, "DataUrl({} chars)", url.len()),
+            MediaSource::Reader(_) => f.write_str("Reader"),
+        }
+    }
+}
+
+/// A `MediaSource` reduced to the forms `storeMediaFile` accepts.
+pub(crate) enum LoadedMedia {
+    Bytes(Vec<u8>),
+    Path(PathBuf),
+    Url(String),
+}
+
+/// How `MediaSource::guess` treats strings that aren't URLs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GuessPolicy {
+    /// Anything without an `http(s):` or `data:` scheme must be an existing local file.
+    #[default]
+    Strict,
+    /// Anything without a scheme is taken as a path, even if it doesn't exist yet.
+    Lenient,
+}
+
+impl MediaSource {
+    /// An `http` or `https` URL.
+    pub fn url(url: &str) -> Result<Self, AnkiError> {
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {
+                Ok(MediaSource::Url(url.to_string()))
+            }
+            _ => Err(AnkiError::ValidationError(format!(
+                "`{}` is not an http(s) url",
+                url
+            ))),
+        }
+    }
+
+    /// An existing local file, made absolute so AnkiConnect doesn't resolve
+    /// it against its own working directory.
+    ///
+    /// For paths on a remote Anki machine, construct `MediaSource::Path` directly.
+    pub fn path(path: impl AsRef<Path>) -> Result<Self, AnkiError> {
+        let path = path.as_ref();
+        match std::fs::canonicalize(path) {
+            Ok(abs) if abs.is_file() => Ok(MediaSource::Path(abs)),
+            Ok(_) => Err(AnkiError::ValidationError(format!(
+                "`{}` is not a file",
+                path.display()
+            ))),
+            Err(e) => Err(AnkiError::ValidationError(format!(
+                "`{}` is not a readable file: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    /// Non-empty file contents.
+    pub fn bytes(bytes: impl Into<Vec<u8>>) -> Result<Self, AnkiError> {
+        let bytes = bytes.into();
+        match bytes.is_empty() {
+            true => Err(AnkiError::ValidationError(
+                "media contents are empty".to_string(),
+            )),
+            false => Ok(MediaSource::Bytes(bytes)),
+        }
+    }
+
+    /// A `data:` URL, decoded eagerly so malformed input fails here.
+    pub fn data_url(url: &str) -> Result<Self, AnkiError> {
+        parse_data_url(url)?;
+        Ok(MediaSource::DataUrl(url.to_string()))
+    }
+
+    /// Picks a constructor from the string's shape: `http(s):` URLs, `data:` URLs,
+    /// and otherwise paths, validated according to `policy`.
+    pub fn guess(value: &str, policy: GuessPolicy) -> Result<Self, AnkiError> {
+        let lower = value.get(..8).unwrap_or(value).to_ascii_lowercase();
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            return MediaSource::url(value);
+        }
+        if lower.starts_with("data:") {
+            return MediaSource::data_url(value);
+        }
+
+        match policy {
+            GuessPolicy::Strict => MediaSource::path(value),
+            GuessPolicy::Lenient => Ok(MediaSource::Path(PathBuf::from(value))),
+        }
+    }
+
+    /// Decodes `DataUrl` and drains `Reader` sources into bytes.
+    pub(crate) fn load(self) -> Result<LoadedMedia, AnkiError> {
+        match self {
+            MediaSource::Bytes(bytes) => Ok(LoadedMedia::Bytes(bytes)),
+            MediaSource::Path(path) => Ok(LoadedMedia::Path(path)),
+            MediaSource::Url(url) => Ok(LoadedMedia::Url(url)),
+            MediaSource::DataUrl(url) => Ok(LoadedMedia::Bytes(parse_data_url(&url)?.1)),
+            MediaSource::Reader(mut reader) => {
+                let mut bytes = Vec::new();
+                match reader.read_to_end(&mut bytes) {
+                    Ok(_) => Ok(LoadedMedia::Bytes(bytes)),
+                    Err(e) => Err(AnkiError::RequestError(format!(
+                        "failed to read media: {}",
+                        e
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for MediaSource {
+    type Error = AnkiError;
+
+    /// Same as `MediaSource::guess` with `GuessPolicy::Strict`.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        MediaSource::guess(value, GuessPolicy::Strict)
+    }
+}
+
+/// Splits a `data:` URL into its media type and decoded contents.
+pub fn parse_data_url(url: &str) -> Result<(String, Vec<u8>), AnkiError> {
+    let invalid = || AnkiError::ValidationError("invalid data url".to_string());
+    let (header, data) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .ok_or_else(invalid)?;
+
+    let (mime, is_base64) = match header.strip_suffix(";base64") {
+        Some(mime) => (mime, true),
+        None => (header, false),
+    };
+    let mime = mime.split(';').next().unwrap_or_default();
+    let mime = match mime.is_empty() {
+        true => "text/plain".to_string(),
+        false => mime.to_ascii_lowercase(),
+    };
+
+    let bytes = match is_base64 {
+        true => STANDARD.decode(data.trim()).map_err(|_| invalid())?,
+        false => percent_decode(data).into_bytes(),
+    };
+    Ok((mime, bytes))
+}
+
+/// The usual file extension for the media types Anki can play or display.
+pub fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    match mime {
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/svg+xml" => Some("svg"),
+        "audio/mpeg" => Some("mp3"),
+        "audio/ogg" => Some("ogg"),
+        "audio/wav" | "audio/x-wav" => Some("wav"),
+        "audio/mp4" => Some("m4a"),
+        "video/mp4" => Some("mp4"),
+        "video/webm" => Some("webm"),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StoreMediaFileParams {
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub deleteExisting: bool,
+}
+
+impl StoreMediaFileParams {
+    pub fn new(filename: &str, source: MediaSource) -> Result<Self, AnkiError> {
+        Ok(Self::from_loaded(filename, source.load()?))
+    }
+
+    pub(crate) fn from_loaded(filename: &str, source: LoadedMedia) -> Self {
+        let mut params = Self {
+            filename: filename.to_string(),
+            data: None,
+            path: None,
+            url: None,
+            deleteExisting: true,
+        };
+        match source {
+            LoadedMedia::Bytes(bytes) => params.data = Some(STANDARD.encode(bytes)),
+            LoadedMedia::Path(path) => params.path = Some(path.to_string_lossy().into_owned()),
+            LoadedMedia::Url(url) => params.url = Some(url),
+        }
+        params
+    }
+}
+
+impl ActionParams for StoreMediaFileParams {
+    const ACTION: Action = Action::StoreMediaFile;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RetrieveMediaFileParams {
+    pub filename: String,
+}
+
+impl ActionParams for RetrieveMediaFileParams {
+    const ACTION: Action = Action::RetrieveMediaFile;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteMediaFileParams {
+    pub filename: String,
+}
+
+impl ActionParams for DeleteMediaFileParams {
+    const ACTION: Action = Action::DeleteMediaFile;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetMediaFilesNamesParams {
+    pub pattern: String,
+}
+
+impl ActionParams for GetMediaFilesNamesParams {
+    const ACTION: Action = Action::GetMediaFilesNames;
+}
+
+/// The media actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct MediaAction;
+
+impl MediaAction {
+    /// Stores a file in Anki's media folder, replacing any file with the same name.
+    ///
+    /// Returns the filename Anki stored the file under.
+    pub async fn store_media_file(
+        anki_client: &AnkiClient,
+        params: StoreMediaFileParams,
+    ) -> Result<String, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, params);
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Stores raw file contents in Anki's media folder, base64 encoding them straight
+    /// into the request body. Storing a 200 MB video this way holds the file and one
+    /// encoded body in memory, instead of several copies of its base64 string.
+    ///
+    /// Returns the filename Anki stored the file under.
+    pub async fn store_media_bytes(
+        anki_client: &AnkiClient,
+        filename: &str,
+        bytes: &[u8],
+        delete_existing: bool,
+    ) -> Result<String, AnkiError> {
+        let params = StoreMediaFileParams {
+            filename: filename.to_string(),
+            data: None,
+            path: None,
+            url: None,
+            deleteExisting: delete_existing,
+        };
+
+        post_base64_request(&params, "data", bytes, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Retrieves the contents of a file in the media folder.
+    ///
+    /// Returns `AnkiError::NoDataFound` if the file doesn't exist.
+    pub async fn retrieve_media_file(
+        anki_client: &AnkiClient,
+        filename: &str,
+    ) -> Result<Vec<u8>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            RetrieveMediaFileParams {
+                filename: filename.to_string(),
+            },
+        );
+
+        // AnkiConnect answers `false` instead of an error for missing files.
+        let res: Value = post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()?;
+
+        match res {
+            Value::String(data) => match STANDARD.decode(data) {
+                Ok(bytes) => Ok(bytes),
+                Err(e) => Err(AnkiError::ParseError(e.to_string())),
+            },
+            _ => Err(AnkiError::NoDataFound),
+        }
+    }
+
+    /// Lists the names of media files matching a glob `pattern`, e.g. `*.mp3`.
+    pub async fn get_media_files_names(
+        anki_client: &AnkiClient,
+        pattern: &str,
+    ) -> Result<Vec<String>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            GetMediaFilesNamesParams {
+                pattern: pattern.to_string(),
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Checks whether a file with exactly this name is in the media folder.
+    pub async fn media_file_exists(
+        anki_client: &AnkiClient,
+        filename: &str,
+    ) -> Result<bool, AnkiError> {
+        let names = MediaAction::get_media_files_names(anki_client, &escape_glob(filename)).await?;
+        Ok(names.iter().any(|n| n == filename))
+    }
+
+    /// Moves a file from the media folder to Anki's trash.
+    pub async fn delete_media_file(
+        anki_client: &AnkiClient,
+        filename: &str,
+    ) -> Result<(), AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            DeleteMediaFileParams {
+                filename: filename.to_string(),
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Returns the full path to the current profile's `collection.media` folder.
+    pub async fn get_media_dir_path(anki_client: &AnkiClient) -> Result<PathBuf, AnkiError> {
+        let payload = serde_json::json!({
+            "action": Action::GetMediaDirPath,
+            "version": anki_client.version,
+        });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+}
+
+/// How `MediaBuilder::store` gets a file into Anki's media folder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StoreMode {
+    /// Send the file through `storeMediaFile`.
+    #[default]
+    Upload,
+    /// Write the file straight into the media folder, skipping base64 and JSON.
+    ///
+    /// Only used when AnkiConnect runs on this machine; otherwise, and for
+    /// `MediaSource::Url`, it falls back to `Upload`.
+    DirectCopy,
+}
+
+/// What happens when a file with the same name is already in the media folder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the existing file (`deleteExisting: true`).
+    #[default]
+    Overwrite,
+    /// Keep the existing file; Anki stores the new one under a free name, which `store` returns.
+    KeepExisting,
+    /// Fail with `AnkiError::ValidationError`. Checked before storing, so a file
+    /// added concurrently by someone else can still be overwritten.
+    ErrorIfExists,
+}
+
+/// Retry settings for URLs the crate downloads itself, set with `MediaBuilder::download`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadPolicy {
+    /// How many times to retry after the first failed attempt.
+    pub retries: u32,
+    /// Delay before the first retry, doubled after every further failure.
+    pub initial_backoff: Duration,
+    /// Timeout for each individual attempt.
+    pub timeout: Duration,
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Called with `(filename, bytes_done, bytes_total)` as a file is downloaded and stored.
+///
+/// `bytes_total` is `None` when the size isn't known up front, e.g. a download
+/// without a `Content-Length` header.
+#[derive(Clone)]
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub struct ProgressHook(Arc<ProgressFn>);
+
+type ProgressFn = dyn Fn(&str, u64, Option<u64>) + Send + Sync;
+
+impl ProgressHook {
+    pub fn new(hook: impl Fn(&str, u64, Option<u64>) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn report(&self, filename: &str, bytes_done: u64, bytes_total: Option<u64>) {
+        (self.0)(filename, bytes_done, bytes_total)
+    }
+}
+
+impl Debug for ProgressHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
+
+/// Rewrites a file's contents after it is resolved and before it is stored,
+/// e.g. to downscale screenshots or trim audio.
+///
+/// Receives the bytes and the type detected by `sniff_extension`. Implemented
+/// for any matching closure.
+pub trait MediaTransform: Send + Sync {
+    fn transform(&self, bytes: Vec<u8>, detected: Option<&str>) -> Result<Vec<u8>, AnkiError>;
+}
+
+impl<F> MediaTransform for F
+where
+    F: Fn(Vec<u8>, Option<&str>) -> Result<Vec<u8>, AnkiError> + Send + Sync,
+{
+    fn transform(&self, bytes: Vec<u8>, detected: Option<&str>) -> Result<Vec<u8>, AnkiError> {
+        self(bytes, detected)
+    }
+}
+
+impl Debug for dyn MediaTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MediaTransform")
+    }
+}
+
+/// What happened to a file passed to `MediaBuilder::store_or_skip`.
+#[derive(Debug)]
+pub enum MediaOutcome {
+    Stored(String),
+    /// The file couldn't be resolved, e.g. its URL kept failing, and was left out.
+    Skipped {
+        filename: Option<String>,
+        reason: AnkiError,
+    },
+}
+
+/// Builds and stores a single media file.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::media::{MediaBuilder, MediaSource};
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let stored = MediaBuilder::new(MediaSource::Path("sentence.mp3".into()))
+///     .filename("sentence.mp3")
+///     .direct_copy()
+///     .store(&client)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MediaBuilder {
+    pub source: MediaSource,
+    pub filename: Option<String>,
+    pub mode: StoreMode,
+    pub media_dir: Option<PathBuf>,
+    pub download: Option<DownloadPolicy>,
+    pub progress: Option<ProgressHook>,
+    pub unique_by_hash: bool,
+    pub sniff: Option<SniffPolicy>,
+    pub transform: Option<Arc<dyn MediaTransform>>,
+    pub sha256: Option<String>,
+    pub overwrite: OverwritePolicy,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MediaBuilder {
+    pub fn new(source: MediaSource) -> Self {
+        Self {
+            source,
+            filename: None,
+            mode: StoreMode::default(),
+            media_dir: None,
+            download: None,
+            progress: None,
+            unique_by_hash: false,
+            sniff: None,
+            transform: None,
+            sha256: None,
+            overwrite: OverwritePolicy::default(),
+        }
+    }
+
+    pub fn filename(mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_string());
+        self
+    }
+
+    pub fn mode(mut self, mode: StoreMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn direct_copy(self) -> Self {
+        self.mode(StoreMode::DirectCopy)
+    }
+
+    /// Uses an already resolved media folder for `StoreMode::DirectCopy`,
+    /// saving a `getMediaDirPath` request per file when storing many files.
+    pub fn media_dir(mut self, dir: PathBuf) -> Self {
+        self.media_dir = Some(dir);
+        self
+    }
+
+    /// Downloads `MediaSource::Url` sources in the crate, retrying transient failures
+    /// with exponential backoff, instead of leaving a single attempt to AnkiConnect.
+    pub fn download(mut self, policy: DownloadPolicy) -> Self {
+        self.download = Some(policy);
+        self
+    }
+
+    /// Reports transfer progress as `(filename, bytes_done, bytes_total)`.
+    pub fn on_media_progress(
+        mut self,
+        hook: impl Fn(&str, u64, Option<u64>) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(ProgressHook::new(hook));
+        self
+    }
+
+    /// Appends a short hash of the file's contents to its filename, e.g. `line.mp3`
+    /// becomes `line-1a2b3c4d.mp3`, so different files sharing a name don't overwrite each other.
+    ///
+    /// The contents are needed up front, so paths are read and URLs are downloaded
+    /// by the crate (using the `download` policy, or the default one).
+    pub fn unique_by_hash(mut self) -> Self {
+        self.unique_by_hash = true;
+        self
+    }
+
+    /// Checks that the file's contents match its extension before storing it,
+    /// e.g. that `word.mp3` isn't the HTML error page of a dead URL.
+    ///
+    /// Like `unique_by_hash`, this needs the contents up front.
+    pub fn sniff(mut self, policy: SniffPolicy) -> Self {
+        self.sniff = Some(policy);
+        self
+    }
+
+    /// Runs `transform` on the contents before they are checked and stored.
+    ///
+    /// Runs on a blocking thread, so it may do CPU-heavy work. Combine with
+    /// `SniffPolicy::FixExtension` if it changes the file's format.
+    pub fn transform(mut self, transform: impl MediaTransform + 'static) -> Self {
+        self.transform = Some(Arc::new(transform));
+        self
+    }
+
+    /// Verifies the downloaded contents against an expected hex SHA-256 digest,
+    /// failing with `AnkiError::ChecksumMismatch` if they differ.
+    ///
+    /// Checked before any `transform` runs. Like `unique_by_hash`, this makes the
+    /// crate download URL sources itself.
+    pub fn sha256(mut self, hex_digest: &str) -> Self {
+        self.sha256 = Some(hex_digest.to_ascii_lowercase());
+        self
+    }
+
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// Stores the file and returns the filename it was stored under.
+    pub async fn store(self, anki_client: &AnkiClient) -> Result<String, AnkiError> {
+        self.prepare(anki_client).await?.send(anki_client).await
+    }
+
+    /// Like `store`, but a file whose source can't be resolved is reported as
+    /// `MediaOutcome::Skipped` rather than failing, so one dead URL doesn't
+    /// abort everything else being imported.
+    pub async fn store_or_skip(self, anki_client: &AnkiClient) -> Result<MediaOutcome, AnkiError> {
+        let filename = self.filename.clone();
+        match self.prepare(anki_client).await {
+            Ok(prepared) => prepared.send(anki_client).await.map(MediaOutcome::Stored),
+            Err(reason) => Ok(MediaOutcome::Skipped { filename, reason }),
+        }
+    }
+
+    /// Settles the final filename and resolves the source into what will be sent to Anki.
+    async fn prepare(self, anki_client: &AnkiClient) -> Result<PreparedMedia, AnkiError> {
+        let mut filename = match self.filename {
+            Some(filename) => filename,
+            None => infer_filename(&self.source)?,
+        };
+        let progress = self.progress.as_ref();
+
+        let source = match self.source {
+            // Draining a reader may block, so keep it off the async workers.
+            MediaSource::Reader(reader) => {
+                match tokio::task::spawn_blocking(|| MediaSource::Reader(reader).load()).await {
+                    Ok(source) => source?,
+                    Err(e) => return Err(AnkiError::RequestError(e.to_string())),
+                }
+            }
+            source => source.load()?,
+        };
+        let needs_bytes = self.unique_by_hash
+            || self.sniff.is_some()
+            || self.transform.is_some()
+            || self.sha256.is_some();
+        let download = match needs_bytes {
+            true => Some(self.download.unwrap_or_default()),
+            false => self.download,
+        };
+        let mut source = match (source, download) {
+            (LoadedMedia::Url(url), Some(policy)) => {
+                let progress = progress.map(|hook| (filename.as_str(), hook));
+                let bytes =
+                    download_with_retries(&anki_client.client, &url, policy, progress).await?;
+                LoadedMedia::Bytes(bytes)
+            }
+            (source, _) => source,
+        };
+
+        if needs_bytes {
+            let mut bytes = read_source_bytes(source).await?;
+            if let Some(expected) = self.sha256 {
+                let actual = sha256_hex(&bytes);
+                if actual != expected {
+                    return Err(AnkiError::ChecksumMismatch {
+                        filename,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+            if let Some(transform) = self.transform {
+                let detected = sniff_extension(&bytes);
+                let run = move || transform.transform(bytes, detected);
+                bytes = match tokio::task::spawn_blocking(run).await {
+                    Ok(res) => res?,
+                    Err(e) => return Err(AnkiError::RequestError(e.to_string())),
+                };
+            }
+            if let Some(policy) = self.sniff {
+                filename = check_extension(&filename, &bytes, policy)?;
+            }
+            if self.unique_by_hash {
+                filename = with_hash_suffix(&filename, &bytes);
+            }
+            source = LoadedMedia::Bytes(bytes);
+        }
+
+        Ok(PreparedMedia {
+            filename,
+            source,
+            mode: self.mode,
+            media_dir: self.media_dir,
+            progress: self.progress,
+            overwrite: self.overwrite,
+        })
+    }
+}
+
+/// A `MediaBuilder` whose filename and contents are final.
+#[cfg(not(target_arch = "wasm32"))]
+struct PreparedMedia {
+    filename: String,
+    source: LoadedMedia,
+    mode: StoreMode,
+    media_dir: Option<PathBuf>,
+    progress: Option<ProgressHook>,
+    overwrite: OverwritePolicy,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PreparedMedia {
+    async fn send(self, anki_client: &AnkiClient) -> Result<String, AnkiError> {
+        let PreparedMedia {
+            filename,
+            source,
+            progress,
+            overwrite,
+            ..
+        } = self;
+        let total = source_len(&source).await;
+        let report = |done: u64| {
+            if let Some(hook) = &progress {
+                hook.report(&filename, done, total);
+            }
+        };
+        let already_exists =
+            || AnkiError::ValidationError(format!("media file `{}` already exists", filename));
+        report(0);
+
+        let mut direct_dir = None;
+        // In dry-run mode, uploading instead lets the client record the store.
+        if self.mode == StoreMode::DirectCopy
+            && !anki_client.is_dry_run()
+            && is_local_endpoint(&anki_client.endpoint)
+            && !matches!(source, LoadedMedia::Url(_))
+        {
+            let dir = match self.media_dir {
+                Some(dir) => dir,
+                None => MediaAction::get_media_dir_path(anki_client).await?,
+            };
+            ensure_flat_filename(&filename)?;
+            let exists = tokio::fs::try_exists(dir.join(&filename))
+                .await
+                .unwrap_or(false);
+            match (overwrite, exists) {
+                (OverwritePolicy::ErrorIfExists, true) => return Err(already_exists()),
+                // Let Anki pick a free name for the new file.
+                (OverwritePolicy::KeepExisting, true) => {}
+                _ => direct_dir = Some(dir),
+            }
+        } else if overwrite == OverwritePolicy::ErrorIfExists
+            && MediaAction::media_file_exists(anki_client, &filename).await?
+        {
+            return Err(already_exists());
+        }
+
+        let Some(dir) = direct_dir else {
+            let delete_existing = overwrite != OverwritePolicy::KeepExisting;
+            let stored = match source {
+                LoadedMedia::Bytes(bytes) => {
+                    MediaAction::store_media_bytes(anki_client, &filename, &bytes, delete_existing)
+                        .await?
+                }
+                source => {
+                    let mut params = StoreMediaFileParams::from_loaded(&filename, source);
+                    params.deleteExisting = delete_existing;
+                    MediaAction::store_media_file(anki_client, params).await?
+                }
+            };
+            report(total.unwrap_or_default());
+            return Ok(stored);
+        };
+
+        copy_into_media_dir(&dir, &filename, source).await?;
+        report(total.unwrap_or_default());
+        Ok(filename)
+    }
+}
+
+/// Loads a local source's contents into memory. URLs must have been downloaded already.
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_source_bytes(source: LoadedMedia) -> Result<Vec<u8>, AnkiError> {
+    match source {
+        LoadedMedia::Bytes(bytes) => Ok(bytes),
+        LoadedMedia::Path(path) => match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(AnkiError::RequestError(format!(
+                "failed to read {}: {}",
+                path.display(),
+                e
+            ))),
+        },
+        LoadedMedia::Url(url) => Err(AnkiError::ValidationError(format!(
+            "`{}` has not been downloaded",
+            url
+        ))),
+    }
+}
+
+/// Guesses a filename from the last segment of a path or URL.
+pub fn infer_filename(source: &MediaSource) -> Result<String, AnkiError> {
+    let name = match source {
+        MediaSource::Path(path) => path.file_name().map(|n| n.to_string_lossy().into_owned()),
+        MediaSource::Url(url) => reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.path_segments()?.next_back().map(percent_decode))
+            .filter(|n| !n.is_empty()),
+        MediaSource::DataUrl(url) => {
+            let (mime, bytes) = parse_data_url(url)?;
+            let name = with_hash_suffix("paste", &bytes);
+            extension_for_mime(&mime).map(|ext| format!("{}.{}", name, ext))
+        }
+        MediaSource::Bytes(_) | MediaSource::Reader(_) => None,
+    };
+
+    match name {
+        Some(name) => Ok(name),
+        None => Err(AnkiError::ValidationError(
+            "media filename is required and can't be inferred from the source".to_string(),
+        )),
+    }
+}
+
+/// What to do when a file's contents don't match its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniffPolicy {
+    /// Fail with `AnkiError::ValidationError`.
+    Reject,
+    /// Store the file anyway, logging a warning with the `tracing` feature.
+    Warn,
+    /// Replace the extension with the detected one. Unrecognized contents are rejected.
+    FixExtension,
+}
+
+/// Detects common audio and image formats from their leading magic bytes.
+pub fn sniff_extension(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        // An MPEG audio frame sync without an ID3 tag.
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("mp3"),
+        _ => None,
+    }
+}
+
+/// Validates `filename`'s extension against the sniffed contents and returns the
+/// filename to store under. Extensions `sniff_extension` doesn't know are not checked.
+pub fn check_extension(
+    filename: &str,
+    bytes: &[u8],
+    policy: SniffPolicy,
+) -> Result<String, AnkiError> {
+    let (stem, declared) = match filename.rsplit_once('.') {
+        Some((stem, ext)) => (stem, ext.to_ascii_lowercase()),
+        None => (filename, String::new()),
+    };
+    let declared = match declared.as_str() {
+        "jpeg" => "jpg",
+        ext => ext,
+    };
+    let checked = ["mp3", "ogg", "jpg", "png", "webp", "gif", "wav"];
+    let detected = sniff_extension(bytes);
+    if !checked.contains(&declared) || detected == Some(declared) {
+        return Ok(filename.to_string());
+    }
+
+    let mismatch = format!(
+        "`{}` looks like {} rather than .{}",
+        filename,
+        detected.map_or("unrecognized data".to_string(), |ext| format!(".{}", ext)),
+        declared
+    );
+    match (policy, detected) {
+        (SniffPolicy::Warn, _) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(filename, "{}", mismatch);
+            Ok(filename.to_string())
+        }
+        (SniffPolicy::FixExtension, Some(ext)) => Ok(format!("{}.{}", stem, ext)),
+        _ => Err(AnkiError::ValidationError(mismatch)),
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Inserts the first 8 hex digits of the SHA-256 of `bytes` before the extension.
+pub fn with_hash_suffix(filename: &str, bytes: &[u8]) -> String {
+    let hash = &sha256_hex(bytes)[..8];
+    match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{}-{}.{}", stem, hash, ext),
+        _ => format!("{}-{}", filename, hash),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn download_with_retries(
+    client: &Client,
+    url: &str,
+    policy: DownloadPolicy,
+    progress: Option<(&str, &ProgressHook)>,
+) -> Result<Vec<u8>, AnkiError> {
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 0;
+
+    loop {
+        let res = client.get(url).timeout(policy.timeout).send().await;
+        let err = match res.and_then(|r| r.error_for_status()) {
+            Ok(response) => match read_body(response, progress).await {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) => e,
+            },
+            Err(e) => e,
+        };
+
+        // Client errors other than timeouts and rate limiting won't fix themselves.
+        let retryable = match err.status() {
+            Some(status) => status.is_server_error() || matches!(status.as_u16(), 408 | 429),
+            None => true,
+        };
+        if !retryable || attempt >= policy.retries {
+            return Err(AnkiError::RequestError(format!(
+                "download of {} failed after {} attempt(s): {}",
+                url,
+                attempt + 1,
+                err
+            )));
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// Most bytes reserved up front for a download, whatever size the server announces.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+/// Reads a response chunk by chunk so progress can be reported while it downloads.
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_body(
+    mut response: Response,
+    progress: Option<(&str, &ProgressHook)>,
+) -> Result<Vec<u8>, reqwest::Error> {
+    let total = response.content_length();
+    // Content-Length comes from the server, so don't trust it for more than a hint.
+    let capacity = total.unwrap_or_default().min(MAX_PREALLOCATION);
+    let mut body = Vec::with_capacity(capacity as usize);
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if let Some((filename, hook)) = progress {
+            hook.report(filename, body.len() as u64, total);
+        }
+    }
+    Ok(body)
+}
+
+/// The size of a resolved source, if it can be known without reading it.
+#[cfg(not(target_arch = "wasm32"))]
+async fn source_len(source: &LoadedMedia) -> Option<u64> {
+    match source {
+        LoadedMedia::Bytes(bytes) => Some(bytes.len() as u64),
+        LoadedMedia::Path(path) => tokio::fs::metadata(path).await.ok().map(|m| m.len()),
+        LoadedMedia::Url(_) => None,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn copy_into_media_dir(
+    dir: &Path,
+    filename: &str,
+    source: LoadedMedia,
+) -> Result<(), AnkiError> {
+    ensure_flat_filename(filename)?;
+    let target = dir.join(filename);
+    let res = match source {
+        LoadedMedia::Bytes(bytes) => tokio::fs::write(&target, bytes).await,
+        LoadedMedia::Path(path) => tokio::fs::copy(&path, &target).await.map(|_| ()),
+        LoadedMedia::Url(url) => {
+            return Err(AnkiError::ValidationError(format!(
+                "cannot copy `{}` directly, url sources must be uploaded",
+                url
+            )))
+        }
+    };
+
+    match res {
+        Ok(()) => Ok(()),
+        Err(e) => Err(AnkiError::RequestError(format!(
+            "failed to write {}: {}",
+            target.display(),
+            e
+        ))),
+    }
+}
+
+/// Escapes glob metacharacters so `getMediaFilesNames` matches the name literally.
+fn escape_glob(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                out.push('[');
+                out.push(c);
+                out.push(']');
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Media filenames are flat; refuses anything that would escape the folder it is written to.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn ensure_flat_filename(filename: &str) -> Result<(), AnkiError> {
+    match filename.contains(['/', '\\']) || filename == ".." || filename.is_empty() {
+        true => Err(AnkiError::ValidationError(format!(
+            "invalid media filename `{}`",
+            filename
+        ))),
+        false => Ok(()),
+    }
+}
+
+/// Whether the endpoint points at this machine, so its media folder is on our filesystem.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn is_local_endpoint(endpoint: &str) -> bool {
+    match reqwest::Url::parse(endpoint) {
+        Ok(url) => matches!(
+            url.host_str(),
+            Some("localhost") | Some("127.0.0.1") | Some("[::1]")
+        ),
+        Err(_) => false,
+    }
+}
+
+/// How many files `MediaAction::store_many` sends at once by default.
+pub const DEFAULT_MEDIA_CONCURRENCY: usize = 4;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MediaAction {
+    /// Stores a batch of files concurrently, at most `concurrency` at a time,
+    /// so importers can push all media first and reference it by filename in notes.
+    ///
+    /// Returns the stored filenames and the failed items by input index; a failed file
+    /// doesn't stop the others. The media folder is resolved once for all
+    /// `StoreMode::DirectCopy` items.
+    pub async fn store_many(
+        anki_client: &AnkiClient,
+        items: Vec<MediaBuilder>,
+        concurrency: usize,
+    ) -> BatchOutcome<String> {
+        let start = Instant::now();
+        let needs_dir = items
+            .iter()
+            .any(|b| b.mode == StoreMode::DirectCopy && b.media_dir.is_none());
+        let media_dir = match needs_dir && is_local_endpoint(&anki_client.endpoint) {
+            true => MediaAction::get_media_dir_path(anki_client).await.ok(),
+            false => None,
+        };
+
+        let limit = Arc::new(Semaphore::new(concurrency.max(1)));
+        // Dropping this future aborts the uploads that haven't finished yet.
+        let mut handles = AbortOnDrop(Vec::with_capacity(items.len()));
+        for mut builder in items {
+            if builder.media_dir.is_none() {
+                builder.media_dir = media_dir.clone();
+            }
+            let client = anki_client.clone();
+            let limit = limit.clone();
+            handles.0.push(tokio::spawn(async move {
+                let _permit = limit.acquire_owned().await;
+                builder.store(&client).await
+            }));
+        }
+
+        let mut results = Vec::with_capacity(handles.0.len());
+        for handle in handles.0.iter_mut() {
+            results.push(match handle.await {
+                Ok(res) => res,
+                Err(e) => Err(AnkiError::RequestError(e.to_string())),
+            });
+        }
+        BatchOutcome::from_results(results, start.elapsed())
+    }
+
+    /// Like `store_many`, then asks Anki to reload the collection if any file was stored,
+    /// so files copied with `StoreMode::DirectCopy` show up without restarting Anki.
+    ///
+    /// The outer error is the reload failing; the outcome is as in `store_many`.
+    pub async fn store_many_and_reload(
+        anki_client: &AnkiClient,
+        items: Vec<MediaBuilder>,
+        concurrency: usize,
+    ) -> Result<BatchOutcome<String>, AnkiError> {
+        let outcome = MediaAction::store_many(anki_client, items, concurrency).await;
+        if !outcome.succeeded.is_empty() {
+            MiscAction::reload_collection(anki_client).await?;
+        }
+        Ok(outcome)
+    }
+}
+
+/// Leading-character classes the listing is partitioned by. Letters are paired by
+/// case so each name lands in exactly one class on case-insensitive file systems too.
+const LIST_PARTITIONS: &[&str] = &[
+    "[0-9]",
+    "[aA]",
+    "[bB]",
+    "[cC]",
+    "[dD]",
+    "[eE]",
+    "[fF]",
+    "[gG]",
+    "[hH]",
+    "[iI]",
+    "[jJ]",
+    "[kK]",
+    "[lL]",
+    "[mM]",
+    "[nN]",
+    "[oO]",
+    "[pP]",
+    "[qQ]",
+    "[rR]",
+    "[sS]",
+    "[tT]",
+    "[uU]",
+    "[vV]",
+    "[wW]",
+    "[xX]",
+    "[yY]",
+    "[zZ]",
+    "[!0-9a-zA-Z]",
+];
+
+/// Filenames from `MediaAction::list`, fetched and yielded in batches.
+///
+/// Patterns starting with `*` or `?` are split by the first character of the name,
+/// so only one partition of the listing is held in memory at a time.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::media::MediaAction;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut list = MediaAction::list(&client, "*.mp3", 500);
+/// while let Some(batch) = list.next_batch().await? {
+///     println!("{} files", batch.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MediaList<'a> {
+    anki_client: &'a AnkiClient,
+    /// The pattern with its leading `*` dropped, fetched before the partitions.
+    bare: Option<String>,
+    /// Patterns still to be fetched, one per partition.
+    patterns: VecDeque<String>,
+    /// The fetched partition being handed out.
+    names: std::vec::IntoIter<String>,
+    /// Names listed for `bare`, which the partitions may return again.
+    seen: HashSet<String>,
+    batch_size: usize,
+}
+
+impl<'a> MediaList<'a> {
+    /// The next batch of up to `batch_size` filenames, or `None` once every
+    /// partition has been listed.
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<String>>, AnkiError> {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size {
+            if let Some(name) = self.names.next() {
+                batch.push(name);
+                continue;
+            }
+            if let Some(bare) = self.bare.take() {
+                let names = MediaAction::get_media_files_names(self.anki_client, &bare).await?;
+                // A leading `*` never matches hidden files, but `bare` may start with a dot.
+                let names: Vec<String> =
+                    names.into_iter().filter(|n| !n.starts_with('.')).collect();
+                self.seen = names.iter().cloned().collect();
+                self.names = names.into_iter();
+                continue;
+            }
+            let Some(pattern) = self.patterns.pop_front() else {
+                break;
+            };
+            let names = MediaAction::get_media_files_names(self.anki_client, &pattern).await?;
+            self.names = names
+                .into_iter()
+                .filter(|name| !self.seen.contains(name))
+                .collect::<Vec<_>>()
+                .into_iter();
+        }
+        match batch.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(batch)),
+        }
+    }
+}
+
+impl MediaAction {
+    /// Lists media filenames matching a glob `pattern` (`*`, `?`, `[abc]`), in
+    /// batches of `batch_size`.
+    ///
+    /// AnkiConnect has no paging, so a pattern starting with `*` or `?` is sent once
+    /// per leading character; other patterns already narrow the listing and are sent
+    /// as is. Nothing is fetched until the first `next_batch`.
+    pub fn list<'a>(
+        anki_client: &'a AnkiClient,
+        pattern: &str,
+        batch_size: usize,
+    ) -> MediaList<'a> {
+        let mut list = MediaList {
+            anki_client,
+            bare: None,
+            patterns: VecDeque::new(),
+            names: Vec::new().into_iter(),
+            seen: HashSet::new(),
+            batch_size: batch_size.max(1),
+        };
+        if let Some(rest) = pattern.strip_prefix('?') {
+            list.patterns = LIST_PARTITIONS
+                .iter()
+                .map(|p| format!("{p}{rest}"))
+                .collect();
+        } else if pattern.starts_with('*') {
+            let pattern = format!("*{}", pattern.trim_start_matches('*'));
+            list.patterns = LIST_PARTITIONS
+                .iter()
+                .map(|p| format!("{p}{pattern}"))
+                .collect();
+            // The partitions need the `*` to match at least one character, so names
+            // it matches empty are listed on their own first.
+            if pattern.len() > 1 {
+                list.bare = Some(pattern[1..].to_string());
+            }
+        } else {
+            list.patterns.push_back(pattern.to_string());
+        }
+        list
+    }
+}
+
+/// The result of `MediaAction::download_all`.
+#[derive(Debug, Default)]
+pub struct MirrorReport {
+    pub downloaded: Vec<String>,
+    /// Files that already existed in the target folder.
+    pub skipped: Vec<String>,
+    pub failed: Vec<(String, AnkiError)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MediaAction {
+    /// Mirrors the media files matching `pattern` (e.g. `*`) into a local folder.
+    ///
+    /// Files already present in `dir` are skipped, and each file is written to a
+    /// `.part` file before being renamed, so an interrupted run can simply be restarted.
+    pub async fn download_all(
+        anki_client: &AnkiClient,
+        dir: &Path,
+        pattern: &str,
+    ) -> Result<MirrorReport, AnkiError> {
+        if let Err(e) = tokio::fs::create_dir_all(dir).await {
+            return Err(AnkiError::RequestError(format!(
+                "failed to create {}: {}",
+                dir.display(),
+                e
+            )));
+        }
+
+        let mut report = MirrorReport::default();
+        for filename in MediaAction::get_media_files_names(anki_client, pattern).await? {
+            if let Err(e) = ensure_flat_filename(&filename) {
+                report.failed.push((filename, e));
+                continue;
+            }
+            let target = dir.join(&filename);
+            if tokio::fs::try_exists(&target).await.unwrap_or(false) {
+                report.skipped.push(filename);
+                continue;
+            }
+
+            let bytes = match MediaAction::retrieve_media_file(anki_client, &filename).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    report.failed.push((filename, e));
+                    continue;
+                }
+            };
+            let part = dir.join(format!("{}.part", filename));
+            let written = match tokio::fs::write(&part, bytes).await {
+                Ok(()) => tokio::fs::rename(&part, &target).await,
+                Err(e) => Err(e),
+            };
+            match written {
+                Ok(()) => report.downloaded.push(filename),
+                Err(e) => {
+                    let err = AnkiError::RequestError(format!(
+                        "failed to write {}: {}",
+                        target.display(),
+                        e
+                    ));
+                    report.failed.push((filename, err));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// A note whose fields reference a file that isn't in the media folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MissingMedia {
+    pub note_id: NoteId,
+    pub filename: String,
+}
+
+/// The result of `MediaAction::audit`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MediaAudit {
+    /// Files in the media folder that no note in scope references.
+    pub unused: Vec<String>,
+    /// References from notes in scope to files that don't exist.
+    pub missing: Vec<MissingMedia>,
+}
+
+impl MediaAction {
+    /// Cross-references the media folder with the `[sound:...]` and `<img src="...">`
+    /// references in the fields of notes matching `scope_query`.
+    ///
+    /// Files are only reported as unused relative to the scope, so pass `deck:*`
+    /// before deleting anything. Files starting with `_` are reserved for
+    /// templates and are never reported as unused.
+    pub async fn audit(
+        anki_client: &AnkiClient,
+        scope_query: &str,
+    ) -> Result<MediaAudit, AnkiError> {
+        let files = MediaAction::get_media_files_names(anki_client, "*").await?;
+        let ids = match NoteAction::find_note_ids(anki_client, scope_query).await {
+            Ok(ids) => ids,
+            Err(AnkiError::NoDataFound) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let notes = match ids.is_empty() {
+            true => Vec::new(),
+            false => NoteAction::get_notes_infos(anki_client, ids).await?,
+        };
+
+        let existing: HashSet<&str> = files.iter().map(String::as_str).collect();
+        let mut referenced = HashSet::new();
+        let mut missing = Vec::new();
+        for note in &notes {
+            for field in note.fields.values() {
+                for filename in extract_media_refs(&field.value) {
+                    if !existing.contains(filename.as_str()) {
+                        missing.push(MissingMedia {
+                            note_id: note.noteId,
+                            filename: filename.clone(),
+                        });
+                    }
+                    referenced.insert(filename);
+                }
+            }
+        }
+
+        let unused = files
+            .iter()
+            .filter(|f| !f.starts_with('_') && !referenced.contains(*f))
+            .cloned()
+            .collect();
+
+        Ok(MediaAudit { unused, missing })
+    }
+}
+
+impl MediaAudit {
+    /// Deletes the unused files found by the audit and returns their names.
+    ///
+    /// With `dry_run` set, returns the files that would be deleted without deleting them.
+    pub async fn delete_unused(
+        &self,
+        anki_client: &AnkiClient,
+        dry_run: bool,
+    ) -> Result<Vec<String>, AnkiError> {
+        if !dry_run {
+            for filename in &self.unused {
+                MediaAction::delete_media_file(anki_client, filename).await?;
+            }
+        }
+        Ok(self.unused.clone())
+    }
+}
+
+/// Extracts the media filenames referenced by a field's HTML.
+pub fn extract_media_refs(html: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+
+    let mut rest = html;
+    while let Some(start) = rest.find("[sound:") {
+        let after = &rest[start + 7..];
+        let Some(end) = after.find(']') else {
+            break;
+        };
+        refs.push(after[..end].to_string());
+        rest = &after[end..];
+    }
+
+    let mut rest = html;
+    while let Some(start) = rest.find("src=") {
+        let after = &rest[start + 4..];
+        let (value, len) = match after.chars().next() {
+            Some(q @ ('"' | '\'')) => match after[1..].find(q) {
+                Some(end) => (&after[1..end + 1], end + 2),
+                None => break,
+            },
+            _ => {
+                let end = after
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        // Remote images aren't stored in the media folder.
+        if !value.is_empty() && !value.contains("://") && !value.starts_with("data:") {
+            refs.push(percent_decode(value));
+        }
+        rest = &after[len..];
+    }
+
+    refs
+}
+
+/// Anki's editor percent-encodes some characters, like spaces, in `src` attributes.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
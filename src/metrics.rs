@@ -0,0 +1,112 @@
+use crate::error::AnkiError;
+use crate::middleware::Middleware;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds, in milliseconds, of the latency histogram buckets.
+/// Slower requests land in a final overflow bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// Request counts and latencies of one action.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActionMetrics {
+    pub requests: u64,
+    /// Requests that failed to send, failed to parse, or came back with an error.
+    pub errors: u64,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+    /// Request counts per `LATENCY_BUCKETS_MS` bucket, plus the overflow bucket.
+    pub latency_histogram: [u64; LATENCY_BUCKETS_MS.len() + 1],
+}
+
+impl ActionMetrics {
+    pub fn average_latency(&self) -> Option<Duration> {
+        (self.requests > 0).then(|| {
+            Duration::from_secs_f64(self.total_latency.as_secs_f64() / self.requests as f64)
+        })
+    }
+
+    fn record(&mut self, failed: bool, elapsed: Duration) {
+        self.requests += 1;
+        if failed {
+            self.errors += 1;
+        }
+        self.total_latency = self.total_latency.saturating_add(elapsed);
+        self.max_latency = self.max_latency.max(elapsed);
+        let ms = elapsed.as_millis();
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|b| ms <= *b as u128)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.latency_histogram[bucket] += 1;
+    }
+}
+
+/// Metrics of every action a client has sent, keyed by action name.
+pub type MetricsSnapshot = BTreeMap<String, ActionMetrics>;
+
+/// A middleware collecting per-action request counts, error counts and latencies.
+///
+/// Installed with `AnkiClient::with_metrics` and read with `AnkiClient::metrics`.
+/// With the `metrics` feature the same numbers are also reported to the `metrics`
+/// crate as `anki_direct_requests_total`, `anki_direct_errors_total` and the
+/// `anki_direct_request_seconds` histogram, labelled by action.
+#[derive(Debug, Default)]
+pub struct MetricsRecorder {
+    actions: Mutex<MetricsSnapshot>,
+}
+
+impl MetricsRecorder {
+    /// A copy of the metrics collected so far.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        match self.actions.lock() {
+            Ok(actions) => actions.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Clears the collected metrics.
+    pub fn reset(&self) {
+        match self.actions.lock() {
+            Ok(mut actions) => actions.clear(),
+            Err(poisoned) => poisoned.into_inner().clear(),
+        }
+    }
+
+    pub(crate) fn record(&self, action: &str, failed: bool, elapsed: Duration) {
+        let mut actions = match self.actions.lock() {
+            Ok(actions) => actions,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        actions
+            .entry(action.to_string())
+            .or_default()
+            .record(failed, elapsed);
+
+        #[cfg(feature = "metrics")]
+        {
+            let action = action.to_string();
+            metrics::counter!("anki_direct_requests_total", "action" => action.clone())
+                .increment(1);
+            if failed {
+                metrics::counter!("anki_direct_errors_total", "action" => action.clone())
+                    .increment(1);
+            }
+            metrics::histogram!("anki_direct_request_seconds", "action" => action)
+                .record(elapsed.as_secs_f64());
+        }
+    }
+}
+
+impl Middleware for MetricsRecorder {
+    fn after(&self, action: &str, response: Result<&Value, &AnkiError>, elapsed: Duration) {
+        let failed = match response {
+            Ok(body) => !body["error"].is_null(),
+            Err(_) => true,
+        };
+        self.record(action, failed, elapsed);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::error::AnkiError;
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+/// Hooks run around every request a client sends to AnkiConnect, for logging,
+/// auth injection, metrics or redaction without wrapping each action.
+///
+/// `before` hooks run in registration order, `after` hooks in reverse order.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::error::AnkiError;
+/// # use anki_direct::middleware::Middleware;
+/// # use serde_json::Value;
+/// # use std::time::Duration;
+/// struct Log;
+///
+/// impl Middleware for Log {
+///     fn after(&self, action: &str, response: Result<&Value, &AnkiError>, elapsed: Duration) {
+///         println!("{} took {:?}, ok: {}", action, elapsed, response.is_ok());
+///     }
+/// }
+///
+/// let client = AnkiClient::default().with_middleware(Log);
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Called with the JSON payload just before it is sent. Changes are sent as made.
+    fn before(&self, action: &str, payload: &mut Value) {
+        let _ = (action, payload);
+    }
+
+    /// Called with the raw response envelope, or the transport or parse error,
+    /// and the time the request took.
+    fn after(&self, action: &str, response: Result<&Value, &AnkiError>, elapsed: Duration) {
+        let _ = (action, response, elapsed);
+    }
+}
+
+impl fmt::Debug for dyn Middleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Middleware")
+    }
+}
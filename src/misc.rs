@@ -0,0 +1,156 @@
+#![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
+use crate::error::{AnkiError, SyncFailure};
+use crate::{post_generic_request, post_simple_action, AnkiClient};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize)]
+pub struct LoadProfileParams {
+    pub name: String,
+}
+
+impl ActionParams for LoadProfileParams {
+    const ACTION: Action = Action::LoadProfile;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportPackageParams {
+    pub deck: String,
+    pub path: String,
+    pub includeSched: bool,
+}
+
+impl ActionParams for ExportPackageParams {
+    const ACTION: Action = Action::ExportPackage;
+}
+
+/// Profile, sync and export actions. Each builds an `ActionRequest` from its own
+/// params type.
+#[derive(Debug)]
+pub struct MiscAction;
+
+impl MiscAction {
+    /// Syncs the collection with AnkiWeb.
+    ///
+    /// Failures reported by Anki come back as `AnkiError::SyncError`, so callers can tell
+    /// a missing login or a pending full sync apart from a network hiccup.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::error::AnkiError;
+    /// # use anki_direct::misc::MiscAction;
+    /// # async fn run() {
+    /// let client = AnkiClient::default();
+    /// match MiscAction::sync(&client).await {
+    ///     Ok(()) => {}
+    ///     Err(AnkiError::SyncError(e)) if e.is_transient() => { /* retry later */ }
+    ///     Err(e) => eprintln!("{}", e),
+    /// }
+    /// # }
+    /// ```
+    pub async fn sync(anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        let payload = serde_json::json!({
+            "action": Action::Sync,
+            "version": anki_client.version,
+        });
+
+        let res = post_generic_request::<Value>(&payload, anki_client).await?;
+        match res.error {
+            Some(e) => Err(AnkiError::SyncError(SyncFailure::from_message(&e))),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the version of the AnkiConnect plugin.
+    pub async fn version(anki_client: &AnkiClient) -> Result<u8, AnkiError> {
+        post_simple_action(anki_client, Action::Version).await
+    }
+
+    /// Asks Anki to reload the collection from disk, e.g. after writing files
+    /// into the media folder directly.
+    pub async fn reload_collection(anki_client: &AnkiClient) -> Result<(), AnkiError> {
+        let payload = serde_json::json!({
+            "action": Action::ReloadCollection,
+            "version": anki_client.version,
+        });
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Returns the names of all profiles.
+    pub async fn get_profiles(anki_client: &AnkiClient) -> Result<Vec<String>, AnkiError> {
+        post_simple_action(anki_client, Action::GetProfiles).await
+    }
+
+    /// Returns the name of the profile that is currently open.
+    pub async fn get_active_profile(anki_client: &AnkiClient) -> Result<String, AnkiError> {
+        post_simple_action(anki_client, Action::GetActiveProfile).await
+    }
+
+    /// Switches Anki to another profile.
+    ///
+    /// After a successful switch the detected AnkiConnect version is refreshed, so
+    /// version checks match what it supports; the configured `version` is kept.
+    /// A `CacheInvalidation` attached to the client expires every section, since the
+    /// other profile has its own collection. Returns `false` if the profile couldn't
+    /// be loaded.
+    pub async fn load_profile(anki_client: &AnkiClient, name: &str) -> Result<bool, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            LoadProfileParams {
+                name: name.to_string(),
+            },
+        );
+
+        let loaded: bool = post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()?;
+        if loaded {
+            anki_client.anki_connect_version().await?;
+        }
+        Ok(loaded)
+    }
+
+    /// Exports a deck to an `.apkg` file.
+    ///
+    /// * `path`: Where Anki writes the package. Relative paths are resolved against
+    ///   the current directory, since Anki's own working directory is unpredictable.
+    /// * `include_scheduling`: Whether review history and scheduling are included.
+    pub async fn export_package(
+        anki_client: &AnkiClient,
+        deck: &str,
+        path: impl AsRef<Path>,
+        include_scheduling: bool,
+    ) -> Result<(), AnkiError> {
+        let path = match std::path::absolute(path.as_ref()) {
+            Ok(path) => path,
+            Err(e) => return Err(AnkiError::ValidationError(e.to_string())),
+        };
+        let payload = ActionRequest::new(
+            anki_client.version,
+            ExportPackageParams {
+                deck: deck.to_string(),
+                path: path.to_string_lossy().into_owned(),
+                includeSched: include_scheduling,
+            },
+        );
+
+        let exported: bool = post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()?;
+        match exported {
+            true => Ok(()),
+            false => Err(AnkiError::RequestError(format!(
+                "could not export deck `{}` to {}",
+                deck,
+                path.display()
+            ))),
+        }
+    }
+}
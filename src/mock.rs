@@ -0,0 +1,170 @@
+use crate::action::Action;
+use crate::error::AnkiError;
+use crate::transport::{AnkiTransport, TransportError, TransportFuture};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// An in-memory AnkiConnect for unit tests: register the actions a test expects with
+/// canned responses, then hand a clone to `AnkiClient::with_transport`.
+///
+/// Expectations for the same action are answered in registration order and each is
+/// used once. Requests nothing was registered for fail with `AnkiError::RequestError`.
+/// `version` is answered with `6` unless expected explicitly, so version checks pass.
+/// `multi` batches are answered action by action.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::mock::MockBackend;
+/// # use anki_direct::notes::NoteAction;
+/// # use anki_direct::NoteId;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mock = MockBackend::new();
+/// mock.expect("findNotes").respond(vec![123]);
+///
+/// let client = AnkiClient::default().with_transport(mock.clone());
+/// let ids = NoteAction::find_note_ids(&client, "deck:Mining").await.unwrap();
+/// assert_eq!(ids, vec![NoteId(123)]);
+/// mock.assert_done();
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    expected: Vec<Expected>,
+    requests: Vec<Value>,
+}
+
+#[derive(Debug)]
+struct Expected {
+    action: String,
+    params: Option<Value>,
+    response: Value,
+}
+
+/// An expectation being registered with `MockBackend::expect`.
+/// It takes effect once `respond` or `fail` is called.
+#[must_use = "an expectation is only registered by `respond` or `fail`"]
+#[derive(Debug)]
+pub struct Expectation {
+    backend: MockBackend,
+    action: String,
+    params: Option<Value>,
+}
+
+impl Expectation {
+    /// Only matches requests with exactly these params.
+    pub fn with_params(mut self, params: impl Serialize) -> Self {
+        self.params = Some(serde_json::to_value(params).unwrap_or(Value::Null));
+        self
+    }
+
+    /// Answers the request with `result`.
+    pub fn respond(self, result: impl Serialize) {
+        let response = match serde_json::to_value(result) {
+            Ok(result) => json!({ "result": result, "error": null }),
+            Err(e) => json!({ "result": null, "error": format!("mock response: {}", e) }),
+        };
+        self.register(response);
+    }
+
+    /// Answers the request with an AnkiConnect error message.
+    pub fn fail(self, error: &str) {
+        self.register(json!({ "result": null, "error": error }));
+    }
+
+    fn register(self, response: Value) {
+        self.backend.state().expected.push(Expected {
+            action: self.action,
+            params: self.params,
+            response,
+        });
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts registering a response for the next `action` request.
+    pub fn expect(&self, action: impl Into<Action>) -> Expectation {
+        Expectation {
+            backend: self.clone(),
+            action: action.into().to_string(),
+            params: None,
+        }
+    }
+
+    /// Every request received so far, in order, including the actions inside `multi`.
+    pub fn requests(&self) -> Vec<Value> {
+        self.state().requests.clone()
+    }
+
+    /// The actions that were expected but never requested.
+    pub fn unmet(&self) -> Vec<String> {
+        let state = self.state();
+        state.expected.iter().map(|e| e.action.clone()).collect()
+    }
+
+    /// Panics if an expected action was never requested.
+    pub fn assert_done(&self) {
+        let unmet = self.unmet();
+        assert!(
+            unmet.is_empty(),
+            "expected actions never requested: {:?}",
+            unmet
+        );
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn answer(&self, request: &Value) -> Result<Value, AnkiError> {
+        let action = request["action"].as_str().unwrap_or_default();
+        if action == "multi" {
+            let Some(actions) = request["params"]["actions"].as_array() else {
+                return Err(AnkiError::RequestError("multi without actions".to_string()));
+            };
+            let mut results = Vec::with_capacity(actions.len());
+            for inner in actions {
+                results.push(self.answer(inner)?);
+            }
+            return Ok(json!({ "result": results, "error": null }));
+        }
+
+        let mut state = self.state();
+        state.requests.push(request.clone());
+        let params = &request["params"];
+        let found = state
+            .expected
+            .iter()
+            .position(|e| e.action == action && e.params.as_ref().is_none_or(|p| p == params));
+        match (found, action) {
+            (Some(i), _) => Ok(state.expected.remove(i).response),
+            (None, "version") => Ok(json!({ "result": 6, "error": null })),
+            (None, _) => Err(AnkiError::RequestError(format!(
+                "unexpected action {} with params {}",
+                action, params
+            ))),
+        }
+    }
+}
+
+impl AnkiTransport for MockBackend {
+    fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a> {
+        let res = self.answer(request).map_err(TransportError::Failed);
+        Box::pin(async move { res })
+    }
+}
@@ -0,0 +1,749 @@
+#![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
+use crate::error::AnkiError;
+use crate::result::FullModelDetails;
+use crate::template::TemplateIssue;
+use crate::{post_generic_request, AnkiClient};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize)]
+pub struct ModelFieldNamesParams {
+    pub modelName: String,
+}
+
+impl ActionParams for ModelFieldNamesParams {
+    const ACTION: Action = Action::ModelFieldNames;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FieldAddParams {
+    pub modelName: String,
+    pub fieldName: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+}
+
+impl ActionParams for FieldAddParams {
+    const ACTION: Action = Action::ModelFieldAdd;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FieldRemoveParams {
+    pub modelName: String,
+    pub fieldName: String,
+}
+
+impl ActionParams for FieldRemoveParams {
+    const ACTION: Action = Action::ModelFieldRemove;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FieldRenameParams {
+    pub modelName: String,
+    pub oldFieldName: String,
+    pub newFieldName: String,
+}
+
+impl ActionParams for FieldRenameParams {
+    const ACTION: Action = Action::ModelFieldRename;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FieldRepositionParams {
+    pub modelName: String,
+    pub fieldName: String,
+    pub index: usize,
+}
+
+impl ActionParams for FieldRepositionParams {
+    const ACTION: Action = Action::ModelFieldReposition;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FindModelsByNameParams {
+    pub modelNames: Vec<String>,
+}
+
+impl ActionParams for FindModelsByNameParams {
+    const ACTION: Action = Action::FindModelsByName;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CardTemplate {
+    pub Name: String,
+    pub Front: String,
+    pub Back: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateModelParams {
+    pub modelName: String,
+    pub inOrderFields: Vec<String>,
+    pub css: String,
+    pub isCloze: bool,
+    pub cardTemplates: Vec<CardTemplate>,
+}
+
+impl ActionParams for CreateModelParams {
+    const ACTION: Action = Action::CreateModel;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TemplateSides {
+    pub Front: String,
+    pub Back: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ModelTemplatesUpdate {
+    pub name: String,
+    pub templates: HashMap<String, TemplateSides>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateModelTemplatesParams {
+    pub model: ModelTemplatesUpdate,
+}
+
+impl ActionParams for UpdateModelTemplatesParams {
+    const ACTION: Action = Action::UpdateModelTemplates;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ModelStylingUpdate {
+    pub name: String,
+    pub css: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateModelStylingParams {
+    pub model: ModelStylingUpdate,
+}
+
+impl ActionParams for UpdateModelStylingParams {
+    const ACTION: Action = Action::UpdateModelStyling;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ModelTemplateAddParams {
+    pub modelName: String,
+    pub template: CardTemplate,
+}
+
+impl ActionParams for ModelTemplateAddParams {
+    const ACTION: Action = Action::ModelTemplateAdd;
+}
+
+/// The desired state of a model, as passed to `ModelAction::ensure_model`.
+///
+/// Fields that exist on the live model but not in the spec are left in place,
+/// after the spec'd fields, since removing them would delete note data.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelSpec {
+    pub name: String,
+    pub fields: Vec<String>,
+    /// `(old, new)` pairs. Applied before any fields are added, so data in `old` is kept.
+    #[serde(default)]
+    pub field_renames: Vec<(String, String)>,
+    pub templates: Vec<TemplateSpec>,
+    pub css: String,
+    #[serde(default)]
+    pub is_cloze: bool,
+}
+
+impl ModelSpec {
+    /// Describes an existing model as a spec, with fields and templates in model order.
+    pub fn from_model(model: &FullModelDetails) -> Self {
+        Self {
+            name: model.name.clone(),
+            fields: model.field_names(),
+            field_renames: Vec::new(),
+            templates: model
+                .templates_in_order()
+                .into_iter()
+                .map(|t| TemplateSpec {
+                    name: t.name.clone(),
+                    front: t.qfmt.clone(),
+                    back: t.afmt.clone(),
+                })
+                .collect(),
+            css: model.css.clone(),
+            is_cloze: model.is_cloze(),
+        }
+    }
+}
+
+impl From<ModelSpec> for CreateModelParams {
+    fn from(spec: ModelSpec) -> Self {
+        Self {
+            modelName: spec.name,
+            inOrderFields: spec.fields,
+            css: spec.css,
+            isCloze: spec.is_cloze,
+            cardTemplates: spec
+                .templates
+                .into_iter()
+                .map(|t| CardTemplate {
+                    Name: t.name,
+                    Front: t.front,
+                    Back: t.back,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Current version of the `ModelBackup` document layout.
+pub const MODEL_BACKUP_FORMAT: u32 = 1;
+
+/// A self-contained JSON document describing a model, produced by `ModelAction::export_json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelBackup {
+    pub format: u32,
+    /// The AnkiConnect endpoint the model was exported from.
+    pub exported_from: String,
+    pub model: ModelSpec,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TemplateSpec {
+    pub name: String,
+    pub front: String,
+    pub back: String,
+}
+
+/// A single change needed to bring a live model in line with a `ModelSpec`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MigrationStep {
+    CreateModel,
+    RenameField { old: String, new: String },
+    AddField { name: String },
+    RepositionField { name: String, index: usize },
+    AddTemplate { name: String },
+    UpdateTemplates { names: Vec<String> },
+    UpdateStyling,
+}
+
+/// The steps `ensure_model` ran, or would run in dry-run mode.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MigrationPlan {
+    pub model: String,
+    pub steps: Vec<MigrationStep>,
+    pub dry_run: bool,
+}
+
+impl MigrationPlan {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// The model actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct ModelAction;
+
+impl ModelAction {
+    /// Returns the names of every model in the collection.
+    pub async fn model_names(anki_client: &AnkiClient) -> Result<Vec<String>, AnkiError> {
+        let payload = serde_json::json!({
+            "action": Action::ModelNames,
+            "version": anki_client.version,
+        });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Fetches the full definition of a model: fields, templates and CSS.
+    pub async fn find_by_name(
+        anki_client: &AnkiClient,
+        model: &str,
+    ) -> Result<FullModelDetails, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FindModelsByNameParams {
+                modelNames: vec![model.to_string()],
+            },
+        );
+
+        let models: Vec<FullModelDetails> = post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()?;
+
+        match models.into_iter().next() {
+            Some(model) => Ok(model),
+            None => Err(AnkiError::NoDataFound),
+        }
+    }
+
+    /// Fetches the full definitions of several models in one request.
+    pub async fn find_by_names(
+        anki_client: &AnkiClient,
+        models: Vec<String>,
+    ) -> Result<Vec<FullModelDetails>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FindModelsByNameParams { modelNames: models },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Creates a new model and returns its definition as stored by Anki.
+    pub async fn create_model(
+        anki_client: &AnkiClient,
+        params: CreateModelParams,
+    ) -> Result<FullModelDetails, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, params);
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Creates `new_name` as a copy of the `source` model's fields, templates and CSS.
+    ///
+    /// AnkiConnect has no clone action, so the source is read with `findModelsByName`
+    /// and recreated with `createModel`. Notes are not copied.
+    pub async fn clone_model(
+        anki_client: &AnkiClient,
+        source: &str,
+        new_name: &str,
+    ) -> Result<FullModelDetails, AnkiError> {
+        let model = ModelAction::find_by_name(anki_client, source).await?;
+        let spec = ModelSpec {
+            name: new_name.to_string(),
+            ..ModelSpec::from_model(&model)
+        };
+
+        ModelAction::create_model(anki_client, spec.into()).await
+    }
+
+    /// Exports a model's fields, templates and CSS as a `ModelBackup` JSON document,
+    /// suitable for versioning in git and restoring with `import_json`.
+    pub async fn export_json(anki_client: &AnkiClient, model: &str) -> Result<Value, AnkiError> {
+        let model = ModelAction::find_by_name(anki_client, model).await?;
+        let backup = ModelBackup {
+            format: MODEL_BACKUP_FORMAT,
+            exported_from: anki_client.endpoint.clone(),
+            model: ModelSpec::from_model(&model),
+        };
+
+        match serde_json::to_value(backup) {
+            Ok(value) => Ok(value),
+            Err(e) => Err(AnkiError::ParseError(e.to_string())),
+        }
+    }
+
+    /// Restores a model from a document produced by `export_json`.
+    ///
+    /// Creates the model if it is missing; otherwise it is converged toward the backup
+    /// with `ensure_model`, so importing the same document twice is a no-op.
+    pub async fn import_json(
+        anki_client: &AnkiClient,
+        value: Value,
+    ) -> Result<MigrationPlan, AnkiError> {
+        let backup: ModelBackup = match serde_json::from_value(value) {
+            Ok(backup) => backup,
+            Err(e) => return Err(AnkiError::ParseError(e.to_string())),
+        };
+        if backup.format != MODEL_BACKUP_FORMAT {
+            return Err(AnkiError::ValidationError(format!(
+                "unsupported model backup format {} (expected {})",
+                backup.format, MODEL_BACKUP_FORMAT
+            )));
+        }
+
+        ModelAction::ensure_model(anki_client, &backup.model, false).await
+    }
+
+    /// Reports template tags that reference fields missing from the model,
+    /// e.g. after a field was renamed outside of Anki's editor.
+    ///
+    /// An empty `Vec` means every template is consistent with the field list.
+    pub async fn validate_templates(
+        anki_client: &AnkiClient,
+        model: &str,
+    ) -> Result<Vec<TemplateIssue>, AnkiError> {
+        let model = ModelAction::find_by_name(anki_client, model).await?;
+        Ok(model.template_issues())
+    }
+
+    /// Returns the field names of a model, in order.
+    pub async fn field_names(
+        anki_client: &AnkiClient,
+        model: &str,
+    ) -> Result<Vec<String>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            ModelFieldNamesParams {
+                modelName: model.to_string(),
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Adds a new field to a model.
+    ///
+    /// * `index`: Where to insert the field. `None` appends it after the last field.
+    ///
+    /// Fails with `AnkiError::ValidationError` if the field already exists
+    /// or the index is past the end of the current field list.
+    pub async fn field_add(
+        anki_client: &AnkiClient,
+        model: &str,
+        field: &str,
+        index: Option<usize>,
+    ) -> Result<(), AnkiError> {
+        let fields = ModelAction::field_names(anki_client, model).await?;
+        ensure_field_absent(&fields, model, field)?;
+        if let Some(index) = index {
+            ensure_index(index, fields.len(), model)?;
+        }
+
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FieldAddParams {
+                modelName: model.to_string(),
+                fieldName: field.to_string(),
+                index,
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Removes a field, and its contents on every note, from a model.
+    pub async fn field_remove(
+        anki_client: &AnkiClient,
+        model: &str,
+        field: &str,
+    ) -> Result<(), AnkiError> {
+        let fields = ModelAction::field_names(anki_client, model).await?;
+        field_position(&fields, model, field)?;
+        if fields.len() == 1 {
+            return Err(AnkiError::ValidationError(format!(
+                "cannot remove `{}`, the only field on model `{}`",
+                field, model
+            )));
+        }
+
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FieldRemoveParams {
+                modelName: model.to_string(),
+                fieldName: field.to_string(),
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Renames a field on a model. Anki updates the templates that reference it.
+    pub async fn field_rename(
+        anki_client: &AnkiClient,
+        model: &str,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<(), AnkiError> {
+        let fields = ModelAction::field_names(anki_client, model).await?;
+        field_position(&fields, model, old_name)?;
+        ensure_field_absent(&fields, model, new_name)?;
+
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FieldRenameParams {
+                modelName: model.to_string(),
+                oldFieldName: old_name.to_string(),
+                newFieldName: new_name.to_string(),
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Moves an existing field to `index` in the model's field list.
+    pub async fn field_reposition(
+        anki_client: &AnkiClient,
+        model: &str,
+        field: &str,
+        index: usize,
+    ) -> Result<(), AnkiError> {
+        let fields = ModelAction::field_names(anki_client, model).await?;
+        field_position(&fields, model, field)?;
+        ensure_index(index, fields.len() - 1, model)?;
+
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FieldRepositionParams {
+                modelName: model.to_string(),
+                fieldName: field.to_string(),
+                index,
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Replaces the front/back of existing card templates, keyed by template name.
+    pub async fn update_templates(
+        anki_client: &AnkiClient,
+        model: &str,
+        templates: HashMap<String, TemplateSides>,
+    ) -> Result<(), AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            UpdateModelTemplatesParams {
+                model: ModelTemplatesUpdate {
+                    name: model.to_string(),
+                    templates,
+                },
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Replaces the CSS shared by all of a model's card templates.
+    pub async fn update_styling(
+        anki_client: &AnkiClient,
+        model: &str,
+        css: &str,
+    ) -> Result<(), AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            UpdateModelStylingParams {
+                model: ModelStylingUpdate {
+                    name: model.to_string(),
+                    css: css.to_string(),
+                },
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Adds a new card template to a model.
+    pub async fn template_add(
+        anki_client: &AnkiClient,
+        model: &str,
+        template: CardTemplate,
+    ) -> Result<(), AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            ModelTemplateAddParams {
+                modelName: model.to_string(),
+                template,
+            },
+        );
+
+        post_generic_request::<Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Converges the live model toward `spec`, creating it if it doesn't exist.
+    ///
+    /// Returns the plan of steps taken. With `dry_run` set, nothing is sent
+    /// to Anki beyond the reads needed to compute the plan.
+    pub async fn ensure_model(
+        anki_client: &AnkiClient,
+        spec: &ModelSpec,
+        dry_run: bool,
+    ) -> Result<MigrationPlan, AnkiError> {
+        let current = match ModelAction::model_names(anki_client)
+            .await?
+            .contains(&spec.name)
+        {
+            true => Some(ModelAction::find_by_name(anki_client, &spec.name).await?),
+            false => None,
+        };
+
+        let plan = MigrationPlan {
+            model: spec.name.clone(),
+            steps: plan_migration(spec, current.as_ref()),
+            dry_run,
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+
+        for step in &plan.steps {
+            apply_migration_step(anki_client, spec, step).await?;
+        }
+
+        Ok(plan)
+    }
+}
+
+async fn apply_migration_step(
+    anki_client: &AnkiClient,
+    spec: &ModelSpec,
+    step: &MigrationStep,
+) -> Result<(), AnkiError> {
+    let spec_template = |name: &str| spec.templates.iter().find(|t| t.name == name);
+
+    match step {
+        MigrationStep::CreateModel => {
+            ModelAction::create_model(anki_client, spec.clone().into()).await?;
+            Ok(())
+        }
+        MigrationStep::RenameField { old, new } => {
+            ModelAction::field_rename(anki_client, &spec.name, old, new).await
+        }
+        MigrationStep::AddField { name } => {
+            ModelAction::field_add(anki_client, &spec.name, name, None).await
+        }
+        MigrationStep::RepositionField { name, index } => {
+            ModelAction::field_reposition(anki_client, &spec.name, name, *index).await
+        }
+        MigrationStep::AddTemplate { name } => {
+            let Some(t) = spec_template(name) else {
+                return Ok(());
+            };
+            let template = CardTemplate {
+                Name: t.name.clone(),
+                Front: t.front.clone(),
+                Back: t.back.clone(),
+            };
+            ModelAction::template_add(anki_client, &spec.name, template).await
+        }
+        MigrationStep::UpdateTemplates { names } => {
+            let templates = names
+                .iter()
+                .filter_map(|name| spec_template(name))
+                .map(|t| {
+                    let sides = TemplateSides {
+                        Front: t.front.clone(),
+                        Back: t.back.clone(),
+                    };
+                    (t.name.clone(), sides)
+                })
+                .collect();
+            ModelAction::update_templates(anki_client, &spec.name, templates).await
+        }
+        MigrationStep::UpdateStyling => {
+            ModelAction::update_styling(anki_client, &spec.name, &spec.css).await
+        }
+    }
+}
+
+/// Computes the steps needed to turn `current` into `spec`, without touching Anki.
+pub fn plan_migration(spec: &ModelSpec, current: Option<&FullModelDetails>) -> Vec<MigrationStep> {
+    let Some(current) = current else {
+        return vec![MigrationStep::CreateModel];
+    };
+
+    let mut steps = Vec::new();
+    let mut fields = current.field_names();
+
+    for (old, new) in &spec.field_renames {
+        let pos = fields.iter().position(|f| f == old);
+        if let (Some(pos), false) = (pos, fields.contains(new)) {
+            fields[pos] = new.clone();
+            steps.push(MigrationStep::RenameField {
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+    }
+
+    for name in &spec.fields {
+        if !fields.contains(name) {
+            fields.push(name.clone());
+            steps.push(MigrationStep::AddField { name: name.clone() });
+        }
+    }
+
+    for (index, name) in spec.fields.iter().enumerate() {
+        let Some(pos) = fields.iter().position(|f| f == name) else {
+            continue;
+        };
+        if pos != index {
+            let field = fields.remove(pos);
+            fields.insert(index, field);
+            steps.push(MigrationStep::RepositionField {
+                name: name.clone(),
+                index,
+            });
+        }
+    }
+
+    let mut changed = Vec::new();
+    for t in &spec.templates {
+        match current.tmpls.iter().find(|c| c.name == t.name) {
+            Some(c) if c.qfmt != t.front || c.afmt != t.back => changed.push(t.name.clone()),
+            Some(_) => {}
+            None => steps.push(MigrationStep::AddTemplate {
+                name: t.name.clone(),
+            }),
+        }
+    }
+    if !changed.is_empty() {
+        steps.push(MigrationStep::UpdateTemplates { names: changed });
+    }
+
+    if current.css != spec.css {
+        steps.push(MigrationStep::UpdateStyling);
+    }
+
+    steps
+}
+
+pub(crate) fn field_position(
+    fields: &[String],
+    model: &str,
+    field: &str,
+) -> Result<usize, AnkiError> {
+    match fields.iter().position(|f| f == field) {
+        Some(pos) => Ok(pos),
+        None => Err(AnkiError::ValidationError(format!(
+            "field `{}` does not exist on model `{}`",
+            field, model
+        ))),
+    }
+}
+
+pub(crate) fn ensure_field_absent(
+    fields: &[String],
+    model: &str,
+    field: &str,
+) -> Result<(), AnkiError> {
+    match fields.iter().any(|f| f == field) {
+        true => Err(AnkiError::ValidationError(format!(
+            "field `{}` already exists on model `{}`",
+            field, model
+        ))),
+        false => Ok(()),
+    }
+}
+
+pub(crate) fn ensure_index(index: usize, max: usize, model: &str) -> Result<(), AnkiError> {
+    match index > max {
+        true => Err(AnkiError::ValidationError(format!(
+            "index {} is out of range for model `{}` (max {})",
+            index, model, max
+        ))),
+        false => Ok(()),
+    }
+}
@@ -1,20 +1,23 @@
 #![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
 use crate::error::AnkiError;
-use crate::result::{NoteGuiEditRes, NotesInfoData, NotesInfoRes, NumVecRes};
-use crate::AnkiClient;
-use reqwest::Client;
+use crate::id::NoteId;
+use crate::result::{
+    NoteGuiEditRes, NoteModTime, NotesInfoBuffer, NotesInfoData, NotesInfoRes, NumVecRes,
+};
+use crate::{post_generic_request, post_raw_request, post_simple_action, AnkiClient};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize)]
 pub struct Note {
-    pub id: u128,
+    pub id: NoteId,
     pub fields: HashMap<String, String>,
     pub audio: Vec<Media>,
     pub picture: Option<Vec<Media>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Media {
     pub url: String,
     pub filename: String,
@@ -22,9 +25,48 @@ pub struct Media {
     pub fields: Vec<String>,
 }
 
+/// A note to add with `NoteAction::add_notes`.
+///
+/// AnkiConnect downloads the `audio` and `picture` files and appends them to the
+/// listed fields of the note.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NewNote {
+    pub deckName: String,
+    pub modelName: String,
+    pub fields: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audio: Vec<Media>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub picture: Vec<Media>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddNoteParams {
+    pub note: NewNote,
+}
+
+impl ActionParams for AddNoteParams {
+    const ACTION: Action = Action::AddNote;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddNotesParams {
+    pub notes: Vec<NewNote>,
+}
+
+impl ActionParams for AddNotesParams {
+    const ACTION: Action = Action::AddNotes;
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GuiEditNoteParams {
-    pub note: u128,
+    pub note: NoteId,
+}
+
+impl ActionParams for GuiEditNoteParams {
+    const ACTION: Action = Action::GuiEditNote;
 }
 
 #[derive(Serialize, Deserialize)]
@@ -32,14 +74,35 @@ pub struct UpdateNoteParams {
     pub note: Note,
 }
 
+impl ActionParams for UpdateNoteParams {
+    const ACTION: Action = Action::UpdateNote;
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct FindNotesParams {
     pub query: String,
 }
 
+impl ActionParams for FindNotesParams {
+    const ACTION: Action = Action::FindNotes;
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct NotesInfoParams {
-    pub notes: Vec<u128>,
+    pub notes: Vec<NoteId>,
+}
+
+impl ActionParams for NotesInfoParams {
+    const ACTION: Action = Action::NotesInfo;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NotesModTimeParams {
+    pub notes: Vec<NoteId>,
+}
+
+impl ActionParams for NotesModTimeParams {
+    const ACTION: Action = Action::NotesModTime;
 }
 
 // other
@@ -56,112 +119,146 @@ pub struct UserNoteFields {
     pub image: String,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum Params {
-    UpdateNote(UpdateNoteParams),
-    FindNotes(FindNotesParams),
-    NotesInfo(NotesInfoParams),
-    GuiEditNote(GuiEditNoteParams),
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct NoteAction {
-    pub action: String,
-    pub version: u8,
-    pub params: Params,
-}
+/// The note actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct NoteAction;
 
 impl NoteAction {
     pub async fn find_note_ids(
         anki_client: &AnkiClient,
         query: &str,
-    ) -> Result<Vec<u128>, AnkiError> {
-        let payload = NoteAction {
-            action: "findNotes".to_string(),
-            version: anki_client.version,
-            params: Params::FindNotes(FindNotesParams {
+    ) -> Result<Vec<NoteId>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            FindNotesParams {
                 query: query.to_string(),
-            }),
-        };
+            },
+        );
 
-        post_find_note_ids_req(payload, &anki_client.endpoint, &anki_client.client).await
+        post_find_note_ids_req(payload, anki_client).await
     }
 
     pub async fn get_notes_infos(
         anki_client: &AnkiClient,
-        ids: Vec<u128>,
+        ids: Vec<NoteId>,
     ) -> Result<Vec<NotesInfoData>, AnkiError> {
-        let payload = NoteAction {
-            action: "notesInfo".to_string(),
-            version: anki_client.version,
-            params: Params::NotesInfo(NotesInfoParams { notes: ids }),
-        };
+        let payload = ActionRequest::new(anki_client.version, NotesInfoParams { notes: ids });
+
+        post_get_notes_infos_req(payload, anki_client).await
+    }
+
+    /// Like `get_notes_infos`, but keeps the response so the notes can be parsed with
+    /// their strings borrowed from it, saving an allocation per field when scanning
+    /// or exporting large collections.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::notes::NoteAction;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let ids = NoteAction::find_note_ids(&client, "deck:Mining").await?;
+    /// let buffer = NoteAction::notes_infos_borrowed(&client, ids).await?;
+    /// for note in buffer.notes()? {
+    ///     let front = note.fields.get("Front").map(|f| &f.value);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn notes_infos_borrowed(
+        anki_client: &AnkiClient,
+        ids: Vec<NoteId>,
+    ) -> Result<NotesInfoBuffer, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, NotesInfoParams { notes: ids });
+
+        post_raw_request(&payload, anki_client)
+            .await
+            .map(NotesInfoBuffer::new)
+    }
+
+    /// Returns when each note was last modified, a much smaller response than `notesInfo`.
+    pub async fn notes_mod_time(
+        anki_client: &AnkiClient,
+        ids: Vec<NoteId>,
+    ) -> Result<Vec<NoteModTime>, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, NotesModTimeParams { notes: ids });
 
-        post_get_notes_infos_req(payload, &anki_client.endpoint, &anki_client.client).await
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
     }
 
-    pub async fn gui_edit_note(anki_client: &AnkiClient, id: u128) -> Result<(), AnkiError> {
-        let payload = NoteAction {
-            action: "guiEditNote".to_string(),
-            version: 6,
-            params: { Params::GuiEditNote(GuiEditNoteParams { note: id }) },
-        };
+    /// Adds a single note and returns its id.
+    pub async fn add_note(anki_client: &AnkiClient, note: NewNote) -> Result<NoteId, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, AddNoteParams { note });
 
-        post_gui_edit_note_req(payload, &anki_client.endpoint, &anki_client.client).await
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Adds notes, returning the id of each added note in order, or `None` for the
+    /// notes Anki refused, e.g. duplicates or notes with an empty first field.
+    ///
+    /// Requests larger than the client's payload limit are split; see
+    /// `AnkiClient::with_max_payload_bytes`. If one of the split requests fails after
+    /// others added notes, the error is `AnkiError::PartiallyAdded` with their ids.
+    pub async fn add_notes(
+        anki_client: &AnkiClient,
+        notes: Vec<NewNote>,
+    ) -> Result<Vec<Option<NoteId>>, AnkiError> {
+        let payload = ActionRequest::new(anki_client.version, AddNotesParams { notes });
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Returns every tag used in the collection.
+    pub async fn get_tags(anki_client: &AnkiClient) -> Result<Vec<String>, AnkiError> {
+        post_simple_action(anki_client, Action::GetTags).await
+    }
+
+    pub async fn gui_edit_note(anki_client: &AnkiClient, id: NoteId) -> Result<(), AnkiError> {
+        let payload = ActionRequest::new(6, GuiEditNoteParams { note: id });
+
+        post_gui_edit_note_req(payload, anki_client).await
     }
 }
 
 async fn post_gui_edit_note_req(
-    payload: NoteAction,
-    endpoint: &str,
-    client: &Client,
+    payload: ActionRequest<GuiEditNoteParams>,
+    anki_client: &AnkiClient,
 ) -> Result<(), AnkiError> {
-    let res = match client.post(endpoint).json(&payload).send().await {
-        Ok(response) => response,
-        Err(e) => return Err(AnkiError::RequestError(e.to_string())),
-    };
-
-    let body: Result<NoteGuiEditRes, reqwest::Error> = res.json().await;
-
-    match body {
-        Ok(res) => res.into_result(),
-        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    let res = post_generic_request(&payload, anki_client).await?;
+    NoteGuiEditRes {
+        result: res.result,
+        error: res.error,
     }
+    .into_result()
 }
 
 async fn post_get_notes_infos_req(
-    payload: NoteAction,
-    endpoint: &str,
-    client: &Client,
+    payload: ActionRequest<NotesInfoParams>,
+    anki_client: &AnkiClient,
 ) -> Result<Vec<NotesInfoData>, AnkiError> {
-    let res = match client.post(endpoint).json(&payload).send().await {
-        Ok(response) => response,
-        Err(e) => return Err(AnkiError::RequestError(e.to_string())),
-    };
-
-    let body: Result<NotesInfoRes, reqwest::Error> = res.json().await;
-
-    match body {
-        Ok(res) => res.into_result(),
-        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    let res = post_generic_request(&payload, anki_client).await?;
+    NotesInfoRes {
+        result: res.result,
+        error: res.error,
     }
+    .into_result()
 }
 
 async fn post_find_note_ids_req(
-    payload: NoteAction,
-    endpoint: &str,
-    client: &Client,
-) -> Result<Vec<u128>, AnkiError> {
-    let res = match client.post(endpoint).json(&payload).send().await {
-        Ok(response) => response,
-        Err(e) => return Err(AnkiError::RequestError(e.to_string())),
-    };
-
-    let body: Result<NumVecRes, reqwest::Error> = res.json().await;
-
-    match body {
-        Ok(res) => res.into_result(),
-        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    payload: ActionRequest<FindNotesParams>,
+    anki_client: &AnkiClient,
+) -> Result<Vec<NoteId>, AnkiError> {
+    let res = post_generic_request(&payload, anki_client).await?;
+    NumVecRes {
+        result: res.result,
+        error: res.error,
     }
+    .into_result()
 }
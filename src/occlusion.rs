@@ -0,0 +1,378 @@
+use crate::error::AnkiError;
+use crate::id::NoteId;
+use crate::media::{infer_filename, sha256_hex, MediaAction, MediaSource, StoreMediaFileParams};
+use crate::notes::{NewNote, NoteAction};
+use crate::AnkiClient;
+use std::collections::HashMap;
+
+/// The name of Anki's built-in image occlusion notetype (Anki 23.10+).
+pub const BUILTIN_MODEL: &str = "Image Occlusion";
+
+/// The name of the notetype created by the Image Occlusion Enhanced add-on.
+pub const ENHANCED_MODEL: &str = "Image Occlusion Enhanced";
+
+/// Colors Image Occlusion Enhanced draws its masks with.
+const MASK_FILL: &str = "#FFEBA2";
+const QUESTION_FILL: &str = "#FF7E7E";
+const MASK_STROKE: &str = "#2D2D2D";
+
+/// A rectangle to hide, in pixels of the base image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OcclusionRect {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl OcclusionRect {
+    pub fn new(left: f64, top: f64, width: f64, height: f64) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+}
+
+/// An SVG mask of an Image Occlusion Enhanced note, to be stored as media.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OcclusionMask {
+    pub filename: String,
+    pub svg: String,
+}
+
+/// What the other occlusions do on a card.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OcclusionMode {
+    /// Every occlusion is hidden; the card asks for one of them.
+    #[default]
+    HideAllGuessOne,
+    /// Only the asked occlusion is hidden.
+    HideOneGuessOne,
+}
+
+/// An image occlusion note: a base image and the rectangles hidden on it, one card per
+/// occlusion.
+///
+/// Produces notes for Anki's built-in notetype (`add_builtin`), or for Image Occlusion
+/// Enhanced (`add_enhanced`), which takes one note per card and SVG masks stored as
+/// media. Rectangles are given in pixels, so the image's size is needed too.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::media::MediaSource;
+/// # use anki_direct::occlusion::{ImageOcclusion, OcclusionRect};
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let id = ImageOcclusion::new(MediaSource::path("heart.png")?, 800, 600)
+///     .deck("Anatomy")
+///     .header("Chambers of the heart")
+///     .occlusion(OcclusionRect::new(120.0, 80.0, 140.0, 40.0))
+///     .occlusion(OcclusionRect::new(420.0, 310.0, 150.0, 40.0))
+///     .add_builtin(&client)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ImageOcclusion {
+    pub image: MediaSource,
+    pub filename: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub deck: String,
+    pub model: Option<String>,
+    pub header: String,
+    pub back_extra: String,
+    /// One entry per card, each holding the rectangles hidden together on it.
+    pub occlusions: Vec<Vec<OcclusionRect>>,
+    pub mode: OcclusionMode,
+    pub tags: Vec<String>,
+}
+
+impl ImageOcclusion {
+    /// Starts a note for `image`, which is `width` by `height` pixels.
+    pub fn new(image: MediaSource, width: u32, height: u32) -> Self {
+        Self {
+            image,
+            filename: None,
+            width,
+            height,
+            deck: "Default".to_string(),
+            model: None,
+            header: String::new(),
+            back_extra: String::new(),
+            occlusions: Vec::new(),
+            mode: OcclusionMode::default(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// The filename the image is stored under. Inferred from the source by default.
+    pub fn filename(mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_string());
+        self
+    }
+
+    pub fn deck(mut self, deck: &str) -> Self {
+        self.deck = deck.to_string();
+        self
+    }
+
+    /// The notetype, for copies of the stock ones under another name. Defaults to
+    /// `BUILTIN_MODEL` or `ENHANCED_MODEL`.
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    pub fn header(mut self, header: &str) -> Self {
+        self.header = header.to_string();
+        self
+    }
+
+    /// Shown on the back of every card; the `Back Extra` field of the built-in
+    /// notetype, `Remarks` for Image Occlusion Enhanced.
+    pub fn back_extra(mut self, back_extra: &str) -> Self {
+        self.back_extra = back_extra.to_string();
+        self
+    }
+
+    /// Adds a card hiding `rect`.
+    pub fn occlusion(mut self, rect: OcclusionRect) -> Self {
+        self.occlusions.push(vec![rect]);
+        self
+    }
+
+    /// Adds a card hiding several rectangles at once, e.g. every label of one kind.
+    pub fn occlusion_group(mut self, rects: Vec<OcclusionRect>) -> Self {
+        self.occlusions.push(rects);
+        self
+    }
+
+    pub fn mode(mut self, mode: OcclusionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Stores the image and adds a note of the built-in notetype, returning its id.
+    pub async fn add_builtin(mut self, anki_client: &AnkiClient) -> Result<NoteId, AnkiError> {
+        self.validate()?;
+        let image = self.store_image(anki_client).await?;
+        NoteAction::add_note(anki_client, self.builtin_note(&image)?).await
+    }
+
+    /// Stores the image and the masks, then adds one Image Occlusion Enhanced note per
+    /// card, returning their ids as `NoteAction::add_notes` does.
+    pub async fn add_enhanced(
+        mut self,
+        anki_client: &AnkiClient,
+    ) -> Result<Vec<Option<NoteId>>, AnkiError> {
+        self.validate()?;
+        let image = self.store_image(anki_client).await?;
+        let (notes, masks) = self.enhanced_notes(&image)?;
+        for mask in masks {
+            MediaAction::store_media_bytes(anki_client, &mask.filename, mask.svg.as_bytes(), true)
+                .await?;
+        }
+        NoteAction::add_notes(anki_client, notes).await
+    }
+
+    /// The built-in notetype's note, referencing the image already stored as `image`.
+    ///
+    /// Rectangles are written as the `{{cN::image-occlusion:rect:...}}` clozes the
+    /// Anki editor saves, with coordinates relative to the image's size.
+    pub fn builtin_note(&self, image: &str) -> Result<NewNote, AnkiError> {
+        self.validate()?;
+        let oi = match self.mode {
+            OcclusionMode::HideAllGuessOne => ":oi=1",
+            OcclusionMode::HideOneGuessOne => "",
+        };
+        let (width, height) = (f64::from(self.width), f64::from(self.height));
+        let mut clozes = Vec::new();
+        for (i, rects) in self.occlusions.iter().enumerate() {
+            for rect in rects {
+                clozes.push(format!(
+                    "{{{{c{}::image-occlusion:rect:left={}:top={}:width={}:height={}{}}}}}",
+                    i + 1,
+                    relative(rect.left, width),
+                    relative(rect.top, height),
+                    relative(rect.width, width),
+                    relative(rect.height, height),
+                    oi
+                ));
+            }
+        }
+
+        let fields = HashMap::from([
+            ("Occlusion".to_string(), clozes.join("<br>")),
+            ("Image".to_string(), format!("<img src=\"{}\">", image)),
+            ("Header".to_string(), self.header.clone()),
+            ("Back Extra".to_string(), self.back_extra.clone()),
+        ]);
+        Ok(NewNote {
+            deckName: self.deck.clone(),
+            modelName: self.model.as_deref().unwrap_or(BUILTIN_MODEL).to_string(),
+            fields,
+            tags: self.tags.clone(),
+            ..NewNote::default()
+        })
+    }
+
+    /// The Image Occlusion Enhanced notes, one per card, referencing the image already
+    /// stored as `image`, and the SVG masks they reference, to store alongside.
+    ///
+    /// Filenames are derived from the image and the rectangles, so adding the same
+    /// occlusions again overwrites the same masks.
+    pub fn enhanced_notes(
+        &self,
+        image: &str,
+    ) -> Result<(Vec<NewNote>, Vec<OcclusionMask>), AnkiError> {
+        self.validate()?;
+        let uniq = &sha256_hex(format!("{}{:?}", image, self.occlusions).as_bytes())[..32];
+        let all: Vec<(usize, &OcclusionRect)> = self
+            .occlusions
+            .iter()
+            .enumerate()
+            .flat_map(|(card, rects)| rects.iter().map(move |rect| (card, rect)))
+            .collect();
+
+        let original = format!("{}-O.svg", uniq);
+        let mut masks = vec![OcclusionMask {
+            filename: original.clone(),
+            svg: self.mask_svg(all.iter().map(|(_, rect)| (*rect, MASK_FILL))),
+        }];
+        let mut notes = Vec::with_capacity(self.occlusions.len());
+        for card in 0..self.occlusions.len() {
+            let id = format!("{}-{}", uniq, card + 1);
+            let asked = all.iter().filter(|(c, _)| *c == card);
+            let others = all.iter().filter(|(c, _)| *c != card);
+            let (question, answer) = match self.mode {
+                OcclusionMode::HideAllGuessOne => (
+                    self.mask_svg(
+                        others
+                            .clone()
+                            .map(|(_, rect)| (*rect, MASK_FILL))
+                            .chain(asked.map(|(_, rect)| (*rect, QUESTION_FILL))),
+                    ),
+                    self.mask_svg(others.map(|(_, rect)| (*rect, MASK_FILL))),
+                ),
+                OcclusionMode::HideOneGuessOne => (
+                    self.mask_svg(asked.map(|(_, rect)| (*rect, QUESTION_FILL))),
+                    self.mask_svg(std::iter::empty()),
+                ),
+            };
+            let question_file = format!("{}-Q.svg", id);
+            let answer_file = format!("{}-A.svg", id);
+
+            let img = |src: &str| format!("<img src=\"{}\" />", src);
+            let fields = HashMap::from([
+                ("ID (hidden)".to_string(), id.clone()),
+                ("Header".to_string(), self.header.clone()),
+                ("Image".to_string(), img(image)),
+                ("Question Mask".to_string(), img(&question_file)),
+                ("Footer".to_string(), String::new()),
+                ("Remarks".to_string(), self.back_extra.clone()),
+                ("Answer Mask".to_string(), img(&answer_file)),
+                ("Original Mask".to_string(), img(&original)),
+            ]);
+            notes.push(NewNote {
+                deckName: self.deck.clone(),
+                modelName: self.model.as_deref().unwrap_or(ENHANCED_MODEL).to_string(),
+                fields,
+                tags: self.tags.clone(),
+                ..NewNote::default()
+            });
+            masks.push(OcclusionMask {
+                filename: question_file,
+                svg: question,
+            });
+            masks.push(OcclusionMask {
+                filename: answer_file,
+                svg: answer,
+            });
+        }
+        Ok((notes, masks))
+    }
+
+    /// An SVG the size of the image holding `shapes`, drawn like the add-on draws them.
+    fn mask_svg<'a>(&self, shapes: impl Iterator<Item = (&'a OcclusionRect, &'a str)>) -> String {
+        let mut svg = format!(
+            "<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\"><g><title>Masks</title>",
+            self.width, self.height
+        );
+        for (rect, fill) in shapes {
+            let class = match fill {
+                QUESTION_FILL => " class=\"qshape\"",
+                _ => "",
+            };
+            svg.push_str(&format!(
+                "<rect{} x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\"/>",
+                class, rect.left, rect.top, rect.width, rect.height, fill, MASK_STROKE
+            ));
+        }
+        svg.push_str("</g></svg>");
+        svg
+    }
+
+    /// Stores the image, leaving an empty source in its place.
+    async fn store_image(&mut self, anki_client: &AnkiClient) -> Result<String, AnkiError> {
+        let filename = match &self.filename {
+            Some(filename) => filename.clone(),
+            None => infer_filename(&self.image)?,
+        };
+        let image = std::mem::replace(&mut self.image, MediaSource::Bytes(Vec::new()));
+        let params = StoreMediaFileParams::new(&filename, image)?;
+        MediaAction::store_media_file(anki_client, params).await
+    }
+
+    fn validate(&self) -> Result<(), AnkiError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(AnkiError::ValidationError(
+                "the image size must not be zero".to_string(),
+            ));
+        }
+        if self.occlusions.is_empty() || self.occlusions.iter().any(Vec::is_empty) {
+            return Err(AnkiError::ValidationError(
+                "an image occlusion note needs at least one occlusion, and every occlusion a rectangle"
+                    .to_string(),
+            ));
+        }
+        let (width, height) = (f64::from(self.width), f64::from(self.height));
+        for rect in self.occlusions.iter().flatten() {
+            let inside = rect.left >= 0.0
+                && rect.top >= 0.0
+                && rect.width > 0.0
+                && rect.height > 0.0
+                && rect.left + rect.width <= width
+                && rect.top + rect.height <= height;
+            if !inside {
+                return Err(AnkiError::ValidationError(format!(
+                    "occlusion {:?} is not inside the {}x{} image",
+                    rect, self.width, self.height
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `value` as a fraction of `total`, written like the Anki editor writes it: at most
+/// four decimals, without a leading zero.
+fn relative(value: f64, total: f64) -> String {
+    let rounded = (value / total * 10_000.0).round() / 10_000.0;
+    let text = rounded.to_string();
+    match text.strip_prefix("0.") {
+        Some(decimals) => format!(".{}", decimals),
+        None => text,
+    }
+}
@@ -0,0 +1,250 @@
+use crate::error::AnkiError;
+use crate::id::NoteId;
+use crate::result::GenericResult;
+use crate::{send_request, AnkiClient};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// The largest request body sent by default, in bytes.
+///
+/// AnkiConnect reads a whole request into memory before answering, and bodies
+/// much larger than this tend to end in a connection reset rather than an error.
+pub const DEFAULT_MAX_PAYLOAD_BYTES: usize = 32 * 1024 * 1024;
+
+/// How the default transport encodes request bodies.
+///
+/// AnkiConnect itself only reads plain JSON, so compression is for endpoints
+/// behind a reverse proxy that decodes `Content-Encoding: gzip`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Gzips every request body. Requires the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// Splits an `addNotes` payload into payloads of at most `limit` serialized bytes,
+/// keeping the notes in order.
+///
+/// Fails with `ValidationError` if a single note is larger than the limit on its own.
+pub(crate) fn split_add_notes(payload: Value, limit: usize) -> Result<Vec<Value>, AnkiError> {
+    if serialized_len(&payload)? <= limit {
+        return Ok(vec![payload]);
+    }
+    let mut envelope = payload;
+    let notes = match envelope["params"]["notes"].take() {
+        Value::Array(notes) => notes,
+        notes => {
+            envelope["params"]["notes"] = notes;
+            return Ok(vec![envelope]);
+        }
+    };
+    envelope["params"]["notes"] = Value::Array(Vec::new());
+    let overhead = serialized_len(&envelope)?;
+
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut chunk_len = overhead;
+    for (i, note) in notes.into_iter().enumerate() {
+        // Every note after the first in a chunk also needs a comma.
+        let note_len = serialized_len(&note)? + 1;
+        if overhead + note_len > limit {
+            return Err(AnkiError::ValidationError(format!(
+                "note {} of addNotes is {} bytes, more than the payload limit of {} bytes",
+                i,
+                note_len - 1,
+                limit
+            )));
+        }
+        if chunk_len + note_len > limit {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = overhead;
+        }
+        chunk_len += note_len;
+        chunk.push(note);
+    }
+    chunks.push(chunk);
+
+    Ok(chunks
+        .into_iter()
+        .map(|notes| {
+            let mut payload = envelope.clone();
+            payload["params"]["notes"] = Value::Array(notes);
+            payload
+        })
+        .collect())
+}
+
+/// Sends an `addNotes` payload in as many requests as `limit` requires and joins
+/// their results, so huge media uploads don't reset the connection.
+///
+/// Stops at the first request that fails. If an earlier request already added notes,
+/// the error is `AnkiError::PartiallyAdded`, carrying their ids.
+pub(crate) async fn post_add_notes<T: DeserializeOwned>(
+    payload: Value,
+    limit: usize,
+    anki_client: &AnkiClient,
+) -> Result<GenericResult<T>, AnkiError> {
+    let mut chunks = split_add_notes(payload, limit)?;
+    if chunks.len() == 1 {
+        return send_request(chunks.remove(0), anki_client).await;
+    }
+
+    let mut ids: Vec<Value> = Vec::new();
+    let mut answered = false;
+    for chunk in chunks {
+        let res = send_request::<Vec<Value>>(chunk, anki_client)
+            .await
+            .and_then(|res| match res.error {
+                Some(e) => Err(AnkiError::from_message(e)),
+                None => Ok(res.result),
+            });
+        match res {
+            Ok(Some(chunk_ids)) => {
+                answered = true;
+                ids.extend(chunk_ids);
+            }
+            // Dry runs answer every chunk with `null`.
+            Ok(None) => {}
+            Err(error) if ids.is_empty() => return Err(error),
+            Err(error) => {
+                return Err(AnkiError::PartiallyAdded {
+                    added: parse_ids(ids)?,
+                    error: Box::new(error),
+                })
+            }
+        }
+    }
+    if !answered {
+        return Ok(GenericResult {
+            result: None,
+            error: None,
+        });
+    }
+    match serde_json::from_value(Value::Array(ids)) {
+        Ok(result) => Ok(GenericResult {
+            result: Some(result),
+            error: None,
+        }),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+fn parse_ids(ids: Vec<Value>) -> Result<Vec<Option<NoteId>>, AnkiError> {
+    match serde_json::from_value(Value::Array(ids)) {
+        Ok(ids) => Ok(ids),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+/// Serializes a request body with `compression`, returning the body and its
+/// `Content-Encoding`, if any.
+pub(crate) fn encode_body(
+    request: &Value,
+    compression: Compression,
+) -> Result<(Vec<u8>, Option<&'static str>), AnkiError> {
+    match serde_json::to_vec(request) {
+        Ok(body) => compress(body, compression),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+/// Compresses a serialized request body, returning it and its `Content-Encoding`, if any.
+pub(crate) fn compress(
+    body: Vec<u8>,
+    compression: Compression,
+) -> Result<(Vec<u8>, Option<&'static str>), AnkiError> {
+    match compression {
+        Compression::None => Ok((body, None)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Write;
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            match encoder.write_all(&body).and_then(|_| encoder.finish()) {
+                Ok(body) => Ok((body, Some("gzip"))),
+                Err(e) => Err(AnkiError::RequestError(e.to_string())),
+            }
+        }
+    }
+}
+
+/// Serializes `envelope` with `params` as its `params`, adding `data` base64 encoded as
+/// `params[field]`. The encoding is written straight into the body, which is sized up
+/// front, so no intermediate base64 string is built.
+pub(crate) fn write_base64_body(
+    envelope: &Value,
+    params: &Value,
+    field: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, AnkiError> {
+    let (Some(_), Some(_)) = (envelope.as_object(), params.as_object()) else {
+        return Err(AnkiError::ValidationError(
+            "the envelope and params must be JSON objects".to_string(),
+        ));
+    };
+    let encoded_len = match base64::encoded_len(data.len(), true) {
+        Some(len) => len,
+        None => {
+            return Err(AnkiError::ValidationError(format!(
+                "{} bytes are too many to base64 encode",
+                data.len()
+            )))
+        }
+    };
+    let field = Value::from(field).to_string();
+    let head_len = serialized_len(envelope)? + serialized_len(params)? + field.len() + 14;
+    let mut body = Vec::with_capacity(head_len + encoded_len);
+
+    // `{"action":..}` and `{"filename":..}` become `{"action":..,"params":{"filename":..,"data":"`.
+    write_open_object(&mut body, envelope)?;
+    body.extend_from_slice(b"\"params\":");
+    write_open_object(&mut body, params)?;
+    body.extend_from_slice(field.as_bytes());
+    body.extend_from_slice(b":\"");
+    let start = body.len();
+    body.resize(start + encoded_len, 0);
+    if let Err(e) = STANDARD.encode_slice(data, &mut body[start..]) {
+        return Err(AnkiError::ParseError(e.to_string()));
+    }
+    body.extend_from_slice(b"\"}}");
+    Ok(body)
+}
+
+/// Writes a JSON object without its closing brace, followed by a comma if it has
+/// any members, ready for more members.
+fn write_open_object(body: &mut Vec<u8>, object: &Value) -> Result<(), AnkiError> {
+    if let Err(e) = serde_json::to_writer(&mut *body, object) {
+        return Err(AnkiError::ParseError(e.to_string()));
+    }
+    body.pop();
+    if body.last() != Some(&b'{') {
+        body.push(b',');
+    }
+    Ok(())
+}
+
+/// The size of `value` as JSON, without keeping the serialized bytes around.
+fn serialized_len(value: &Value) -> Result<usize, AnkiError> {
+    let mut counter = ByteCounter(0);
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => Ok(counter.0),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
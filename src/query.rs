@@ -0,0 +1,695 @@
+use crate::gui::Ease;
+use crate::id::{CardId, NoteId};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::ops::{BitAnd, BitOr, Not};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The longest query `split_ids` produces by default. Anki turns `nid:` and `cid:`
+/// searches into SQL, and SQLite rejects statements over 1,000,000 bytes.
+pub const MAX_QUERY_LEN: usize = 100_000;
+
+/// The values Anki accepts after `is:`.
+const IS_VALUES: &[&str] = &[
+    "new",
+    "learn",
+    "review",
+    "due",
+    "suspended",
+    "buried",
+    "buried-manually",
+    "buried-sibling",
+];
+
+/// The state a card is in, searched with `is:`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CardState {
+    New,
+    Learn,
+    Review,
+    Due,
+    Suspended,
+    Buried,
+}
+
+impl CardState {
+    pub fn name(self) -> &'static str {
+        match self {
+            CardState::New => "new",
+            CardState::Learn => "learn",
+            CardState::Review => "review",
+            CardState::Due => "due",
+            CardState::Suspended => "suspended",
+            CardState::Buried => "buried",
+        }
+    }
+}
+
+/// An Anki search, rendered to the query string taken by `findNotes`, `findCards`
+/// and `guiBrowse` through `Display`.
+///
+/// Names and values are matched literally: quotes, backslashes and the `*` and `_`
+/// wildcards are escaped, and terms containing spaces or parentheses are quoted.
+/// Queries combine with `&`, `|` and `!`, which parenthesize nested groups as needed.
+///
+/// # Example
+///
+/// ```
+/// use anki_direct::query::{AnkiQuery, CardState};
+///
+/// let query = AnkiQuery::Deck("Japanese::Mining 2024".into());
+/// assert_eq!(query.to_string(), r#""deck:Japanese::Mining 2024""#);
+///
+/// let query = AnkiQuery::Field { name: "Word".into(), value: "食べる*".into() };
+/// assert_eq!(query.to_string(), r"Word:食べる\*");
+///
+/// let query = AnkiQuery::Deck("X".into())
+///     & (AnkiQuery::Tag("a".into()) | AnkiQuery::Tag("b".into()))
+///     & !AnkiQuery::Is(CardState::Suspended);
+/// assert_eq!(query.to_string(), "deck:X (tag:a or tag:b) -is:suspended");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AnkiQuery {
+    /// `is:new`, `is:due`, ...
+    Is(CardState),
+    /// Cards in the deck or any of its subdecks.
+    Deck(String),
+    /// Notes with the tag or any of its child tags.
+    Tag(String),
+    /// Notes of the note type with this name.
+    Note(String),
+    /// Notes whose field `name` is exactly `value`.
+    Field {
+        name: String,
+        value: String,
+    },
+    /// Cards added in the last `days` days, today being day 1.
+    Added(u32),
+    /// Notes whose fields were edited in the last `days` days.
+    Edited(u32),
+    /// Cards answered in the last `days` days, with `ease` if given. Anki caps `days`
+    /// at 365.
+    Rated {
+        days: u32,
+        ease: Option<Ease>,
+    },
+    /// Cards first answered in the last `days` days.
+    Introduced(u32),
+    /// Notes with a field matching the regular expression. The pattern is passed to Anki
+    /// unchanged, apart from escaping quotes.
+    Regex(String),
+    /// Notes whose field `name` matches the regular expression `pattern`.
+    FieldRegex {
+        name: String,
+        pattern: String,
+    },
+    /// Notes containing the text with combining characters such as accents ignored,
+    /// e.g. `はは` also matching `ばば`.
+    NoCombining(String),
+    /// The notes with these ids, as `nid:1,2,3`. Must not be empty.
+    NoteIds(Vec<NoteId>),
+    /// The cards with these ids, as `cid:1,2,3`. Must not be empty.
+    CardIds(Vec<CardId>),
+    /// Notes containing the text in any field, matched literally. Quotes, colons,
+    /// parentheses and wildcards are escaped, so user input is safe to search for.
+    Text(String),
+    /// A query string used as is. Parenthesized when combined with other queries.
+    Raw(String),
+    /// Matches what every query matches. Matches everything when empty, written
+    /// as `deck:*`.
+    And(Vec<AnkiQuery>),
+    /// Matches what any query matches. Matches nothing when empty, written as
+    /// `-deck:*`.
+    Or(Vec<AnkiQuery>),
+    Not(Box<AnkiQuery>),
+    /// The query in parentheses.
+    Group(Box<AnkiQuery>),
+}
+
+impl AnkiQuery {
+    /// Checks a hand-written query for mistakes that make Anki find nothing instead of
+    /// failing, and returns it as `Raw` along with what was found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anki_direct::query::{AnkiQuery, QueryWarning};
+    ///
+    /// let (query, warnings) = AnkiQuery::parse("deck:Mining is:suspend");
+    /// assert_eq!(query, AnkiQuery::Raw("deck:Mining is:suspend".into()));
+    /// assert_eq!(
+    ///     warnings,
+    ///     [QueryWarning::UnknownIsValue { value: "suspend".into(), at: 15 }]
+    /// );
+    /// ```
+    pub fn parse(query: &str) -> (AnkiQuery, Vec<QueryWarning>) {
+        let mut warnings = Vec::new();
+        let mut open_parens = Vec::new();
+        let mut open_quote = None;
+        let mut term_start = None;
+        let mut escaped = false;
+        for (at, c) in query.char_indices() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match c {
+                '\\' => escaped = true,
+                '"' => match open_quote.take() {
+                    Some(_) => {}
+                    None => open_quote = Some(at),
+                },
+                _ if open_quote.is_some() => {}
+                '(' => open_parens.push(at),
+                ')' => match open_parens.pop() {
+                    Some(_) => {}
+                    None => warnings.push(QueryWarning::UnbalancedParens { at }),
+                },
+                _ if c.is_whitespace() => {
+                    if let Some(start) = term_start.take() {
+                        lint_term(&query[start..at], start, &mut warnings);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if term_start.is_none() && !matches!(c, '(' | ')') {
+                term_start = Some(at);
+            }
+        }
+        if let Some(start) = term_start {
+            lint_term(&query[start..], start, &mut warnings);
+        }
+        if let Some(at) = open_quote {
+            warnings.push(QueryWarning::UnbalancedQuotes { at });
+        }
+        warnings.extend(
+            open_parens
+                .into_iter()
+                .map(|at| QueryWarning::UnbalancedParens { at }),
+        );
+        warnings.sort_by_key(QueryWarning::at);
+        (AnkiQuery::Raw(query.to_string()), warnings)
+    }
+
+    /// Splits a `NoteIds` or `CardIds` query into queries of at most `max_len` bytes,
+    /// e.g. `MAX_QUERY_LEN`, keeping the ids in order. Other queries are returned whole.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anki_direct::query::{AnkiQuery, MAX_QUERY_LEN};
+    /// use anki_direct::NoteId;
+    ///
+    /// let ids: Vec<NoteId> = (1_700_000_000_000..1_700_000_050_000).map(NoteId).collect();
+    /// let queries = AnkiQuery::NoteIds(ids).split_ids(MAX_QUERY_LEN);
+    /// assert!(queries.iter().all(|q| q.to_string().len() <= MAX_QUERY_LEN));
+    /// ```
+    pub fn split_ids(self, max_len: usize) -> Vec<AnkiQuery> {
+        match self {
+            AnkiQuery::NoteIds(ids) => chunk_ids(ids, max_len)
+                .into_iter()
+                .map(AnkiQuery::NoteIds)
+                .collect(),
+            AnkiQuery::CardIds(ids) => chunk_ids(ids, max_len)
+                .into_iter()
+                .map(AnkiQuery::CardIds)
+                .collect(),
+            query => vec![query],
+        }
+    }
+
+    /// Whether the query has to be parenthesized to be combined with another.
+    fn is_compound(&self) -> bool {
+        match self {
+            AnkiQuery::Raw(_) => true,
+            // A lone query is written bare, so it is as compound as its child.
+            AnkiQuery::And(queries) | AnkiQuery::Or(queries) if queries.len() == 1 => {
+                queries[0].is_compound()
+            }
+            AnkiQuery::And(queries) => queries.len() > 1,
+            // An empty `Or` is written as a negation.
+            AnkiQuery::Or(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Writes the query, in parentheses if it is compound.
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.is_compound() {
+            true => write!(f, "({})", self),
+            false => write!(f, "{}", self),
+        }
+    }
+
+    fn fmt_joined(
+        queries: &[AnkiQuery],
+        separator: &str,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        if let [query] = queries {
+            return write!(f, "{}", query);
+        }
+        for (i, query) in queries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            query.fmt_nested(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for AnkiQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let term = match self {
+            AnkiQuery::Is(state) => return write!(f, "is:{}", state.name()),
+            AnkiQuery::Deck(name) => format!("deck:{}", escape(name)),
+            AnkiQuery::Tag(name) => format!("tag:{}", escape(name)),
+            AnkiQuery::Note(model) => format!("note:{}", escape(model)),
+            AnkiQuery::Field { name, value } => {
+                format!("{}:{}", escape_field(name), escape(value))
+            }
+            AnkiQuery::Regex(pattern) => format!("re:{}", pattern.replace('"', "\\\"")),
+            AnkiQuery::FieldRegex { name, pattern } => {
+                format!("{}:re:{}", escape_field(name), pattern.replace('"', "\\\""))
+            }
+            AnkiQuery::NoCombining(text) => format!("nc:{}", escape(text)),
+            AnkiQuery::Text(text) => {
+                let term = escape_text(text);
+                // A bare `and` or `or` would be read as an operator.
+                return match needs_quotes(&term)
+                    || text.eq_ignore_ascii_case("and")
+                    || text.eq_ignore_ascii_case("or")
+                {
+                    true => write!(f, "\"{}\"", term),
+                    false => f.write_str(&term),
+                };
+            }
+            AnkiQuery::Added(days) => return write!(f, "added:{}", days),
+            AnkiQuery::Edited(days) => return write!(f, "edited:{}", days),
+            AnkiQuery::Rated { days, ease: None } => return write!(f, "rated:{}", days),
+            AnkiQuery::Rated {
+                days,
+                ease: Some(ease),
+            } => return write!(f, "rated:{}:{}", days, u8::from(*ease)),
+            AnkiQuery::Introduced(days) => return write!(f, "introduced:{}", days),
+            AnkiQuery::NoteIds(ids) => return write!(f, "nid:{}", join_ids(ids)),
+            AnkiQuery::CardIds(ids) => return write!(f, "cid:{}", join_ids(ids)),
+            AnkiQuery::Raw(query) => return f.write_str(query),
+            // An empty string would match everything, and vanish when nested.
+            AnkiQuery::And(queries) if queries.is_empty() => return f.write_str("deck:*"),
+            AnkiQuery::Or(queries) if queries.is_empty() => return f.write_str("-deck:*"),
+            AnkiQuery::And(queries) => return AnkiQuery::fmt_joined(queries, " ", f),
+            AnkiQuery::Or(queries) => return AnkiQuery::fmt_joined(queries, " or ", f),
+            AnkiQuery::Not(query) => {
+                f.write_str("-")?;
+                return query.fmt_nested(f);
+            }
+            AnkiQuery::Group(query) => return write!(f, "({})", query),
+        };
+        match needs_quotes(&term) {
+            true => write!(f, "\"{}\"", term),
+            false => f.write_str(&term),
+        }
+    }
+}
+
+impl From<AnkiQuery> for String {
+    fn from(query: AnkiQuery) -> Self {
+        query.to_string()
+    }
+}
+
+impl From<CardState> for AnkiQuery {
+    fn from(state: CardState) -> Self {
+        AnkiQuery::Is(state)
+    }
+}
+
+impl BitAnd for AnkiQuery {
+    type Output = AnkiQuery;
+
+    fn bitand(self, rhs: AnkiQuery) -> AnkiQuery {
+        match self {
+            AnkiQuery::And(mut queries) => {
+                queries.push(rhs);
+                AnkiQuery::And(queries)
+            }
+            query => AnkiQuery::And(vec![query, rhs]),
+        }
+    }
+}
+
+impl BitOr for AnkiQuery {
+    type Output = AnkiQuery;
+
+    fn bitor(self, rhs: AnkiQuery) -> AnkiQuery {
+        match self {
+            AnkiQuery::Or(mut queries) => {
+                queries.push(rhs);
+                AnkiQuery::Or(queries)
+            }
+            query => AnkiQuery::Or(vec![query, rhs]),
+        }
+    }
+}
+
+impl Not for AnkiQuery {
+    type Output = AnkiQuery;
+
+    fn not(self) -> AnkiQuery {
+        AnkiQuery::Not(Box::new(self))
+    }
+}
+
+/// Splits ids into lists that render, with a `nid:` or `cid:` prefix, in at most
+/// `max_len` bytes, unless a single id is already longer.
+fn chunk_ids<T: Display>(ids: Vec<T>, max_len: usize) -> Vec<Vec<T>> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut len = "nid:".len();
+    for id in ids {
+        // The id and, after the first one, its separating comma.
+        let id_len = id.to_string().len();
+        if !chunk.is_empty() && len + 1 + id_len > max_len {
+            chunks.push(std::mem::take(&mut chunk));
+            len = "nid:".len();
+        }
+        len += usize::from(!chunk.is_empty()) + id_len;
+        chunk.push(id);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+fn join_ids(ids: &[impl Display]) -> String {
+    let ids: Vec<String> = ids.iter().map(ToString::to_string).collect();
+    ids.join(",")
+}
+
+/// Builds an `AnkiQuery` matching every added term, for those who prefer chaining
+/// calls to combining variants.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::query::AnkiQuery;
+/// let query = AnkiQuery::builder()
+///     .deck("Japanese")
+///     .tag("mined")
+///     .is_new()
+///     .added_within_days(7)
+///     .build();
+/// assert_eq!(query.to_string(), "deck:Japanese tag:mined is:new added:7");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AnkiQueryBuilder {
+    terms: Vec<AnkiQuery>,
+}
+
+impl AnkiQuery {
+    /// Starts building a query from chained terms.
+    pub fn builder() -> AnkiQueryBuilder {
+        AnkiQueryBuilder::default()
+    }
+}
+
+impl AnkiQueryBuilder {
+    /// Adds any query as a term.
+    pub fn and(mut self, query: AnkiQuery) -> Self {
+        self.terms.push(query);
+        self
+    }
+
+    /// Excludes what `query` matches.
+    pub fn not(self, query: AnkiQuery) -> Self {
+        self.and(!query)
+    }
+
+    /// Adds a term matching any of `queries`.
+    pub fn any_of(self, queries: impl IntoIterator<Item = AnkiQuery>) -> Self {
+        self.and(AnkiQuery::Or(queries.into_iter().collect()))
+    }
+
+    pub fn deck(self, name: &str) -> Self {
+        self.and(AnkiQuery::Deck(name.to_string()))
+    }
+
+    pub fn tag(self, name: &str) -> Self {
+        self.and(AnkiQuery::Tag(name.to_string()))
+    }
+
+    /// Notes of the note type `model`.
+    pub fn note(self, model: &str) -> Self {
+        self.and(AnkiQuery::Note(model.to_string()))
+    }
+
+    pub fn field(self, name: &str, value: &str) -> Self {
+        self.and(AnkiQuery::Field {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    pub fn text(self, text: &str) -> Self {
+        self.and(AnkiQuery::Text(text.to_string()))
+    }
+
+    pub fn is(self, state: CardState) -> Self {
+        self.and(AnkiQuery::Is(state))
+    }
+
+    pub fn is_new(self) -> Self {
+        self.is(CardState::New)
+    }
+
+    pub fn is_learn(self) -> Self {
+        self.is(CardState::Learn)
+    }
+
+    pub fn is_review(self) -> Self {
+        self.is(CardState::Review)
+    }
+
+    pub fn is_due(self) -> Self {
+        self.is(CardState::Due)
+    }
+
+    pub fn is_suspended(self) -> Self {
+        self.is(CardState::Suspended)
+    }
+
+    pub fn is_buried(self) -> Self {
+        self.is(CardState::Buried)
+    }
+
+    pub fn added_within_days(self, days: u32) -> Self {
+        self.and(AnkiQuery::Added(days))
+    }
+
+    pub fn edited_within_days(self, days: u32) -> Self {
+        self.and(AnkiQuery::Edited(days))
+    }
+
+    /// Cards answered in the last `days` days, with `ease` if given.
+    pub fn rated_within_days(self, days: u32, ease: Option<Ease>) -> Self {
+        self.and(AnkiQuery::Rated { days, ease })
+    }
+
+    pub fn introduced_within_days(self, days: u32) -> Self {
+        self.and(AnkiQuery::Introduced(days))
+    }
+
+    /// The query matching every term. A single term is returned as is, and no terms
+    /// match everything.
+    pub fn build(mut self) -> AnkiQuery {
+        match self.terms.len() {
+            1 => self.terms.remove(0),
+            _ => AnkiQuery::And(self.terms),
+        }
+    }
+}
+
+/// Named queries shared by an application and its users, e.g. `leeches`.
+///
+/// Clones share the same presets. Get the client's with `AnkiClient::queries`; with the
+/// `cache` feature, pass `Cache::queries` to `AnkiClient::with_queries` to persist them
+/// with the cache.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::query::{AnkiQuery, CardState};
+/// let client = AnkiClient::default();
+/// client.queries().save("leeches", AnkiQuery::Tag("leech".into()));
+/// let leeches = client.queries().get("leeches").unwrap();
+/// assert_eq!(leeches.to_string(), "tag:leech");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QueryPresets {
+    presets: Arc<Mutex<BTreeMap<String, AnkiQuery>>>,
+}
+
+impl QueryPresets {
+    /// Saves `query` under `name`, returning the query it replaced.
+    pub fn save(&self, name: &str, query: AnkiQuery) -> Option<AnkiQuery> {
+        self.lock().insert(name.to_string(), query)
+    }
+
+    pub fn get(&self, name: &str) -> Option<AnkiQuery> {
+        self.lock().get(name).cloned()
+    }
+
+    pub fn remove(&self, name: &str) -> Option<AnkiQuery> {
+        self.lock().remove(name)
+    }
+
+    /// The names of the saved queries, in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, AnkiQuery>> {
+        match self.presets.lock() {
+            Ok(presets) => presets,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Serialize for QueryPresets {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.lock().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for QueryPresets {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let presets = BTreeMap::deserialize(deserializer)?;
+        Ok(QueryPresets {
+            presets: Arc::new(Mutex::new(presets)),
+        })
+    }
+}
+
+/// A likely mistake in a query found by `AnkiQuery::parse`. `at` is the byte offset
+/// of the offending part of the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryWarning {
+    /// A `"` that is never closed.
+    UnbalancedQuotes { at: usize },
+    /// A `(` that is never closed, or a `)` that closes nothing.
+    UnbalancedParens { at: usize },
+    /// An `is:` search Anki doesn't know, which matches nothing.
+    UnknownIsValue { value: String, at: usize },
+    /// A colon that makes the term a field search, although it looks like text,
+    /// e.g. in `10:30` or `https://example.com`. Escape it as `\:` to search for it.
+    UnescapedColon { term: String, at: usize },
+}
+
+impl QueryWarning {
+    pub fn at(&self) -> usize {
+        match self {
+            QueryWarning::UnbalancedQuotes { at }
+            | QueryWarning::UnbalancedParens { at }
+            | QueryWarning::UnknownIsValue { at, .. }
+            | QueryWarning::UnescapedColon { at, .. } => *at,
+        }
+    }
+}
+
+impl Display for QueryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryWarning::UnbalancedQuotes { at } => write!(f, "unclosed quote at {}", at),
+            QueryWarning::UnbalancedParens { at } => {
+                write!(f, "unbalanced parenthesis at {}", at)
+            }
+            QueryWarning::UnknownIsValue { value, at } => {
+                write!(f, "unknown search is:{} at {}", value, at)
+            }
+            QueryWarning::UnescapedColon { term, at } => {
+                write!(f, "unescaped colon in {} at {}", term, at)
+            }
+        }
+    }
+}
+
+/// Lints one whitespace-separated term of a raw query, starting at byte `start`.
+fn lint_term(term: &str, start: usize, warnings: &mut Vec<QueryWarning>) {
+    let trimmed = term.trim_start_matches(['-', '(', '"']);
+    let start = start + term.len() - trimmed.len();
+    let trimmed = trimmed.trim_end_matches([')', '"']);
+    let Some(colon) = unescaped_colon(trimmed) else {
+        return;
+    };
+    let (name, value) = (&trimmed[..colon], &trimmed[colon + 1..]);
+    if name.eq_ignore_ascii_case("is") {
+        if !IS_VALUES.contains(&value.to_lowercase().as_str()) {
+            warnings.push(QueryWarning::UnknownIsValue {
+                value: value.to_string(),
+                at: start + colon + 1,
+            });
+        }
+    } else if name.is_empty() || value.starts_with("//") || name.chars().all(|c| c.is_ascii_digit())
+    {
+        warnings.push(QueryWarning::UnescapedColon {
+            term: trimmed.to_string(),
+            at: start + colon,
+        });
+    }
+}
+
+/// The byte offset of the first colon not escaped with a backslash.
+pub(crate) fn unescaped_colon(term: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (at, c) in term.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ':' => return Some(at),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Escapes the characters Anki treats specially inside a search term.
+pub(crate) fn escape(text: &str) -> String {
+    escape_chars(text, &['\\', '"', '*', '_'])
+}
+
+fn escape_chars(text: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes a field name, in which a colon would end the name.
+fn escape_field(name: &str) -> String {
+    escape(name).replace(':', "\\:")
+}
+
+/// Escapes free text, in which a colon would start a field search and parentheses
+/// would start a group.
+fn escape_text(text: &str) -> String {
+    escape_chars(text, &['\\', '"', '*', '_', ':', '(', ')'])
+}
+
+/// Whether a term has to be wrapped in double quotes to be read as one term.
+fn needs_quotes(term: &str) -> bool {
+    term.starts_with('-')
+        || term
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '(' | ')' | '"'))
+}
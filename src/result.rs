@@ -1,36 +1,248 @@
 #![allow(non_snake_case)]
 use crate::error::AnkiError;
+#[cfg(feature = "chrono")]
+use crate::id::from_secs;
+use crate::id::{CardId, DeckId, ModelId, NoteId};
+use crate::template::{render_card, validate_template, CardPreview, TemplateIssue, TemplateSide};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 /// # Example Result
-/// ```
+/// ```json
 /// {
 ///    "result": [1483959289817, 1483959291695],
 ///    "error": null
 /// }
 /// ```
 ///
+/// Ids are read as `u64`, which holds any epoch-millisecond id on every target.
+///
 /// `NumVecRes` can be returned from the following requests:
 /// - FindNotes
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NumVecRes {
-    pub result: Option<Vec<u128>>,
+    pub result: Option<Vec<NoteId>>,
     pub error: Option<String>,
 }
 
+/// The `{ result, error }` envelope shared by every AnkiConnect response.
+///
+/// Actions that return `null` on success should be read with `into_unit()`.
 #[derive(Serialize, Deserialize, Debug)]
+pub struct GenericResult<T> {
+    pub result: Option<T>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FieldData {
     pub value: String,
     pub order: u8,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NotesInfoData {
-    pub noteId: u128,
+    pub noteId: NoteId,
     pub modelName: String,
     pub tags: Vec<String>,
     pub fields: HashMap<String, FieldData>,
+    /// Seconds since the epoch. Sent by AnkiConnect versions from 2023 on.
+    #[serde(default, rename = "mod", skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+#[cfg(feature = "chrono")]
+impl NotesInfoData {
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.noteId.created_at()
+    }
+
+    /// When the note was last modified, if AnkiConnect sent it.
+    pub fn modified_at(&self) -> Option<DateTime<Utc>> {
+        from_secs(self.modified?)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl NoteModTime {
+    pub fn modified_at(&self) -> Option<DateTime<Utc>> {
+        from_secs(self.modified)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl CardModTime {
+    pub fn modified_at(&self) -> Option<DateTime<Utc>> {
+        from_secs(self.modified)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl CardInfo {
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.cardId.created_at()
+    }
+
+    pub fn modified_at(&self) -> Option<DateTime<Utc>> {
+        from_secs(self.modified)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteModTime {
+    pub noteId: NoteId,
+    /// Seconds since the epoch.
+    #[serde(rename = "mod")]
+    pub modified: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardModTime {
+    pub cardId: CardId,
+    /// Seconds since the epoch.
+    #[serde(rename = "mod")]
+    pub modified: u64,
+}
+
+/// A card as returned by `cardsInfo`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CardInfo {
+    pub cardId: CardId,
+    pub note: NoteId,
+    pub deckName: String,
+    pub modelName: String,
+    /// The rendered question HTML.
+    pub question: String,
+    /// The rendered answer HTML.
+    pub answer: String,
+    pub fields: HashMap<String, FieldData>,
+    pub fieldOrder: usize,
+    pub css: String,
+    pub ord: usize,
+    #[serde(rename = "type")]
+    pub card_type: i8,
+    pub queue: i8,
+    pub due: i64,
+    pub interval: i64,
+    pub factor: u32,
+    pub reps: u64,
+    pub lapses: u64,
+    pub left: u32,
+    #[serde(rename = "mod")]
+    pub modified: u64,
+}
+
+/// A field of a `NotesInfoRef` or `CardInfoRef`, borrowing its value from the response.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FieldDataRef<'a> {
+    #[serde(borrow)]
+    pub value: Cow<'a, str>,
+    pub order: u8,
+}
+
+/// `NotesInfoData` borrowing its strings from a `NotesInfoBuffer`.
+///
+/// Strings are only copied when they contain JSON escapes.
+#[derive(Deserialize, Debug, Clone)]
+pub struct NotesInfoRef<'a> {
+    pub noteId: NoteId,
+    #[serde(borrow)]
+    pub modelName: Cow<'a, str>,
+    #[serde(borrow)]
+    pub tags: Vec<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub fields: HashMap<Cow<'a, str>, FieldDataRef<'a>>,
+    #[serde(default, rename = "mod")]
+    pub modified: Option<u64>,
+}
+
+/// `CardInfo` borrowing its strings from a `CardsInfoBuffer`.
+///
+/// Strings are only copied when they contain JSON escapes.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CardInfoRef<'a> {
+    pub cardId: CardId,
+    pub note: NoteId,
+    #[serde(borrow)]
+    pub deckName: Cow<'a, str>,
+    #[serde(borrow)]
+    pub modelName: Cow<'a, str>,
+    #[serde(borrow)]
+    pub question: Cow<'a, str>,
+    #[serde(borrow)]
+    pub answer: Cow<'a, str>,
+    #[serde(borrow)]
+    pub fields: HashMap<Cow<'a, str>, FieldDataRef<'a>>,
+    pub fieldOrder: usize,
+    #[serde(borrow)]
+    pub css: Cow<'a, str>,
+    pub ord: usize,
+    #[serde(rename = "type")]
+    pub card_type: i8,
+    pub queue: i8,
+    pub due: i64,
+    pub interval: i64,
+    pub factor: u32,
+    pub reps: u64,
+    pub lapses: u64,
+    pub left: u32,
+    #[serde(rename = "mod")]
+    pub modified: u64,
+}
+
+/// The raw `notesInfo` results kept by `NoteAction::notes_infos_borrowed`, one per
+/// request the ids were chunked into.
+#[derive(Debug)]
+pub struct NotesInfoBuffer {
+    results: Vec<Box<RawValue>>,
+}
+
+impl NotesInfoBuffer {
+    pub(crate) fn new(results: Vec<Box<RawValue>>) -> Self {
+        Self { results }
+    }
+
+    /// Parses the notes, borrowing their strings from this buffer.
+    pub fn notes(&self) -> Result<Vec<NotesInfoRef<'_>>, AnkiError> {
+        parse_raw_lists(&self.results)
+    }
+}
+
+/// The raw `cardsInfo` results kept by `CardAction::cards_info_borrowed`, one per
+/// request the ids were chunked into.
+#[derive(Debug)]
+pub struct CardsInfoBuffer {
+    results: Vec<Box<RawValue>>,
+}
+
+impl CardsInfoBuffer {
+    pub(crate) fn new(results: Vec<Box<RawValue>>) -> Self {
+        Self { results }
+    }
+
+    /// Parses the cards, borrowing their strings from this buffer.
+    pub fn cards(&self) -> Result<Vec<CardInfoRef<'_>>, AnkiError> {
+        parse_raw_lists(&self.results)
+    }
+}
+
+/// Parses each raw result as a list and joins the lists in order.
+fn parse_raw_lists<'a, T: Deserialize<'a>>(
+    results: &'a [Box<RawValue>],
+) -> Result<Vec<T>, AnkiError> {
+    let mut items = Vec::new();
+    for raw in results {
+        match serde_json::from_str::<Vec<T>>(raw.get()) {
+            Ok(chunk) => items.extend(chunk),
+            Err(e) => return Err(AnkiError::ParseError(e.to_string())),
+        }
+    }
+    Ok(items)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,6 +251,179 @@ pub struct NotesInfoRes {
     pub error: Option<String>,
 }
 
+/// A model as returned by `findModelsByName`.
+///
+/// Keys this struct doesn't model are kept in `extra`, so a model can be
+/// round-tripped without losing data added by newer Anki versions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FullModelDetails {
+    pub id: ModelId,
+    pub name: String,
+    pub flds: Vec<ModelFieldDetails>,
+    pub tmpls: Vec<ModelTemplateDetails>,
+    pub css: String,
+    #[serde(rename = "type")]
+    pub model_type: ModelType,
+    /// Index into `flds` of the field the browser sorts by.
+    pub sortf: usize,
+    #[serde(default)]
+    pub latexPre: String,
+    #[serde(default)]
+    pub latexPost: String,
+    /// The deck new cards of this model were last added to.
+    #[serde(default)]
+    pub did: Option<DeckId>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// A deck options group, as returned by `getDeckConfig`.
+///
+/// Keys this struct doesn't model, like the `new`, `rev` and `lapse` settings,
+/// are kept in `extra`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeckConfig {
+    pub id: u64,
+    pub name: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// One row of Anki's review log, as returned by `cardReviews`.
+///
+/// Serialized as AnkiConnect's 9-element array, in field order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "ReviewTuple", into = "ReviewTuple")]
+pub struct ReviewRow {
+    /// Review time in milliseconds since the epoch, which is also the review's id.
+    pub review_id: u64,
+    pub card_id: CardId,
+    pub usn: i64,
+    /// The answer button pressed, 1 (Again) to 4 (Easy), or 0 for manual rescheduling.
+    pub ease: u8,
+    /// The new interval, in days if positive or seconds if negative.
+    pub ivl: i64,
+    pub last_ivl: i64,
+    /// The new ease factor in permille, e.g. `2500` for 250%.
+    pub factor: i64,
+    /// Time taken to answer, in milliseconds.
+    pub time: u64,
+    pub review_type: ReviewKind,
+}
+
+type ReviewTuple = (u64, CardId, i64, u8, i64, i64, i64, u64, ReviewKind);
+
+impl From<ReviewTuple> for ReviewRow {
+    fn from(t: ReviewTuple) -> Self {
+        ReviewRow {
+            review_id: t.0,
+            card_id: t.1,
+            usn: t.2,
+            ease: t.3,
+            ivl: t.4,
+            last_ivl: t.5,
+            factor: t.6,
+            time: t.7,
+            review_type: t.8,
+        }
+    }
+}
+
+impl From<ReviewRow> for ReviewTuple {
+    fn from(r: ReviewRow) -> Self {
+        (
+            r.review_id,
+            r.card_id,
+            r.usn,
+            r.ease,
+            r.ivl,
+            r.last_ivl,
+            r.factor,
+            r.time,
+            r.review_type,
+        )
+    }
+}
+
+/// The kind of review a log row records, serialized as Anki's `0`-`4`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "u8", into = "u8")]
+pub enum ReviewKind {
+    Learn,
+    Review,
+    Relearn,
+    Filtered,
+    Manual,
+}
+
+impl From<u8> for ReviewKind {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => ReviewKind::Learn,
+            2 => ReviewKind::Relearn,
+            3 => ReviewKind::Filtered,
+            4 => ReviewKind::Manual,
+            _ => ReviewKind::Review,
+        }
+    }
+}
+
+impl From<ReviewKind> for u8 {
+    fn from(value: ReviewKind) -> Self {
+        match value {
+            ReviewKind::Learn => 0,
+            ReviewKind::Review => 1,
+            ReviewKind::Relearn => 2,
+            ReviewKind::Filtered => 3,
+            ReviewKind::Manual => 4,
+        }
+    }
+}
+
+/// Whether a model is a standard or a cloze notetype, serialized as Anki's `0`/`1`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "u8", into = "u8")]
+pub enum ModelType {
+    Standard,
+    Cloze,
+}
+
+impl From<u8> for ModelType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => ModelType::Cloze,
+            _ => ModelType::Standard,
+        }
+    }
+}
+
+impl From<ModelType> for u8 {
+    fn from(value: ModelType) -> Self {
+        match value {
+            ModelType::Standard => 0,
+            ModelType::Cloze => 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelFieldDetails {
+    pub name: String,
+    pub ord: usize,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelTemplateDetails {
+    pub name: String,
+    pub ord: usize,
+    pub qfmt: String,
+    pub afmt: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NoteGuiEditRes {
     pub result: Option<String>,
@@ -48,7 +433,7 @@ pub struct NoteGuiEditRes {
 impl NoteGuiEditRes {
     pub fn into_result(self) -> Result<(), AnkiError> {
         match self.error {
-            Some(e) => Err(AnkiError::RequestError(e)),
+            Some(e) => Err(AnkiError::from_message(e)),
             None => Ok(()),
         }
     }
@@ -57,7 +442,7 @@ impl NoteGuiEditRes {
 impl NotesInfoRes {
     pub fn into_result(self) -> Result<Vec<NotesInfoData>, AnkiError> {
         match self.error {
-            Some(e) => Err(AnkiError::RequestError(e)),
+            Some(e) => Err(AnkiError::from_message(e)),
             None => match self.result {
                 Some(vec) if vec.is_empty() => Err(AnkiError::NoDataFound),
                 Some(vec) => Ok(vec),
@@ -68,9 +453,9 @@ impl NotesInfoRes {
 }
 
 impl NumVecRes {
-    pub fn into_result(self) -> Result<Vec<u128>, AnkiError> {
+    pub fn into_result(self) -> Result<Vec<NoteId>, AnkiError> {
         match self.error {
-            Some(e) => Err(AnkiError::RequestError(e)),
+            Some(e) => Err(AnkiError::from_message(e)),
             None => match self.result {
                 Some(vec) if vec.is_empty() => Err(AnkiError::NoDataFound),
                 Some(vec) => Ok(vec),
@@ -79,3 +464,119 @@ impl NumVecRes {
         }
     }
 }
+
+impl<T> GenericResult<T> {
+    pub fn into_result(self) -> Result<T, AnkiError> {
+        match self.error {
+            Some(e) => Err(AnkiError::from_message(e)),
+            None => match self.result {
+                Some(res) => Ok(res),
+                None => Err(AnkiError::NoDataFound),
+            },
+        }
+    }
+
+    pub fn into_unit(self) -> Result<(), AnkiError> {
+        match self.error {
+            Some(e) => Err(AnkiError::from_message(e)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FullModelDetails {
+    /// Renders a card of this model locally, without opening Anki's editor.
+    ///
+    /// * `card_ord`: The template `ord` for standard models, or the cloze number
+    ///   minus one for cloze models (matching the `ord` Anki gives the card).
+    pub fn preview(
+        &self,
+        card_ord: usize,
+        fields: &HashMap<String, String>,
+    ) -> Result<CardPreview, AnkiError> {
+        let tmpl = match self.is_cloze() {
+            true => self.templates_in_order().into_iter().next(),
+            false => self.tmpls.iter().find(|t| t.ord == card_ord),
+        };
+        let Some(tmpl) = tmpl else {
+            return Err(AnkiError::ValidationError(format!(
+                "model `{}` has no card template with ord {}",
+                self.name, card_ord
+            )));
+        };
+
+        let cloze = self.is_cloze().then_some(card_ord + 1);
+        Ok(render_card(&tmpl.qfmt, &tmpl.afmt, fields, cloze))
+    }
+
+    /// Checks every template's front and back for references to fields the model lacks,
+    /// and for unbalanced `{{#...}}`/`{{/...}}` sections.
+    pub fn template_issues(&self) -> Vec<TemplateIssue> {
+        let fields = self.field_names();
+        self.tmpls
+            .iter()
+            .flat_map(|t| {
+                let front = validate_template(&t.name, TemplateSide::Front, &t.qfmt, &fields);
+                let back = validate_template(&t.name, TemplateSide::Back, &t.afmt, &fields);
+                front.into_iter().chain(back)
+            })
+            .collect()
+    }
+
+    pub fn is_cloze(&self) -> bool {
+        self.model_type == ModelType::Cloze
+    }
+
+    /// Checks that cloze deletions (`{{c1::...}}`) are only used on cloze models,
+    /// and that a cloze model's note has at least one.
+    ///
+    /// Catches the mismatch before AnkiConnect rejects the note with an opaque error.
+    pub fn check_cloze_markup<'a>(
+        &self,
+        field_values: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), AnkiError> {
+        let has_cloze = field_values.into_iter().any(has_cloze_markup);
+        match (self.is_cloze(), has_cloze) {
+            (true, false) => Err(AnkiError::ValidationError(format!(
+                "cloze model `{}` requires at least one cloze deletion",
+                self.name
+            ))),
+            (false, true) => Err(AnkiError::ValidationError(format!(
+                "standard model `{}` cannot contain cloze deletions",
+                self.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the field names in their model order.
+    pub fn field_names(&self) -> Vec<String> {
+        let mut flds: Vec<&ModelFieldDetails> = self.flds.iter().collect();
+        flds.sort_by_key(|f| f.ord);
+        flds.into_iter().map(|f| f.name.clone()).collect()
+    }
+
+    /// Returns the card templates sorted by `ord`, the order Anki generates cards in.
+    pub fn templates_in_order(&self) -> Vec<&ModelTemplateDetails> {
+        let mut tmpls: Vec<&ModelTemplateDetails> = self.tmpls.iter().collect();
+        tmpls.sort_by_key(|t| t.ord);
+        tmpls
+    }
+
+    /// Returns the name of the field the browser sorts by.
+    pub fn sort_field(&self) -> Option<&str> {
+        self.flds
+            .iter()
+            .find(|f| f.ord == self.sortf)
+            .map(|f| f.name.as_str())
+    }
+}
+
+/// Returns true if `text` contains a cloze deletion such as `{{c1::answer}}`.
+pub fn has_cloze_markup(text: &str) -> bool {
+    text.match_indices("{{c").any(|(i, _)| {
+        let rest = &text[i + 3..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        digits > 0 && rest[digits..].starts_with("::")
+    })
+}
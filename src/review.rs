@@ -0,0 +1,119 @@
+use crate::cards::{CardAction, CardAnswer};
+use crate::error::AnkiError;
+use crate::gui::Ease;
+use crate::id::CardId;
+use crate::result::CardInfo;
+use crate::AnkiClient;
+use std::collections::VecDeque;
+
+/// A review session over a deck's due cards, driven entirely through AnkiConnect
+/// without opening Anki's reviewer. Lets terminal and web front-ends act as a reviewer.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::gui::Ease;
+/// # use anki_direct::review::ReviewSession;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let mut session = ReviewSession::start(&client, "Mining").await?;
+/// while let Some(card) = session.next_card().await? {
+///     println!("{}", card.question);
+///     session.answer(Ease::Good).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ReviewSession<'a> {
+    anki_client: &'a AnkiClient,
+    deck: String,
+    queue: VecDeque<CardId>,
+    current: Option<CardInfo>,
+    reviewed: usize,
+}
+
+impl<'a> ReviewSession<'a> {
+    /// Starts a session over the cards currently due in `deck` and its subdecks.
+    pub async fn start(anki_client: &'a AnkiClient, deck: &str) -> Result<Self, AnkiError> {
+        let mut session = Self {
+            anki_client,
+            deck: deck.to_string(),
+            queue: VecDeque::new(),
+            current: None,
+            reviewed: 0,
+        };
+        session.refill().await?;
+        Ok(session)
+    }
+
+    /// Moves to the next due card and returns it, with its rendered question and answer.
+    ///
+    /// When the queue runs out the deck is searched again, so cards answered
+    /// `Again` come back once they are due. Returns `None` when nothing is left.
+    pub async fn next_card(&mut self) -> Result<Option<&CardInfo>, AnkiError> {
+        if self.queue.is_empty() {
+            self.refill().await?;
+        }
+        let Some(id) = self.queue.pop_front() else {
+            self.current = None;
+            return Ok(None);
+        };
+
+        let mut infos = CardAction::get_cards_infos(self.anki_client, vec![id]).await?;
+        self.current = infos.pop();
+        Ok(self.current.as_ref())
+    }
+
+    /// The card returned by the last `next_card`, if it hasn't been answered yet.
+    pub fn current(&self) -> Option<&CardInfo> {
+        self.current.as_ref()
+    }
+
+    /// Rates the current card. Call `next_card` to advance.
+    ///
+    /// If the answer fails the card stays current, so `answer` can be retried.
+    pub async fn answer(&mut self, ease: Ease) -> Result<(), AnkiError> {
+        let Some(card) = &self.current else {
+            return Err(AnkiError::ValidationError(
+                "no card to answer, call next_card first".to_string(),
+            ));
+        };
+
+        let id = card.cardId;
+        let answer = CardAnswer { cardId: id, ease };
+        let answered = CardAction::answer_cards(self.anki_client, vec![answer]).await?;
+        match answered.first() {
+            Some(true) => {
+                self.current = None;
+                self.reviewed += 1;
+                Ok(())
+            }
+            _ => Err(AnkiError::RequestError(format!(
+                "card {} could not be answered",
+                id
+            ))),
+        }
+    }
+
+    /// Number of cards answered in this session.
+    pub fn reviewed(&self) -> usize {
+        self.reviewed
+    }
+
+    /// Number of cards known to be due, not counting the current one.
+    pub fn remaining(&self) -> usize {
+        self.queue.len()
+    }
+
+    async fn refill(&mut self) -> Result<(), AnkiError> {
+        let query = format!("\"deck:{}\" is:due", self.deck.replace('"', "\\\""));
+        match CardAction::find_card_ids(self.anki_client, &query).await {
+            Ok(ids) => self.queue.extend(ids),
+            Err(AnkiError::NoDataFound) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+}
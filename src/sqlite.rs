@@ -0,0 +1,837 @@
+use crate::action::Action;
+use crate::error::AnkiError;
+use crate::id::{DeckId, ModelId, NoteId};
+use crate::query::{escape, unescaped_colon, AnkiQuery};
+use crate::result::{FieldData, NotesInfoData};
+use crate::transport::{AnkiTransport, TransportFuture};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Separates deck name components in the `decks` table of newer collections.
+const DECK_SEPARATOR: char = '\x1f';
+
+/// Separates field values in a note's `flds` column.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A collection opened straight from its `collection.anki2` file, read-only, for
+/// batch analysis while Anki is closed.
+///
+/// Offers the read actions of `NoteAction` and `DeckAction` without AnkiConnect, or
+/// through them with `SqliteTransport`. Anki keeps the file locked while a profile
+/// is open, so opening fails with `AnkiError::CollectionUnavailable` then.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::query::AnkiQuery;
+/// # use anki_direct::sqlite::SqliteCollection;
+/// # fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let collection = SqliteCollection::open("/home/me/.local/share/Anki2/User 1/collection.anki2")?;
+/// let ids = collection.find_note_ids(&AnkiQuery::Deck("Mining".into()))?;
+/// let notes = collection.notes_infos(&ids)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SqliteCollection {
+    conn: Connection,
+    models: HashMap<ModelId, SqliteModel>,
+    decks: HashMap<DeckId, String>,
+}
+
+#[derive(Debug, Clone)]
+struct SqliteModel {
+    name: String,
+    /// Field names by `ord`.
+    fields: Vec<String>,
+}
+
+/// A note as stored, before its fields are named.
+struct NoteRow {
+    id: NoteId,
+    model: ModelId,
+    tags: Vec<String>,
+    fields: Vec<String>,
+    modified: u64,
+}
+
+/// Models and decks of collections before schema 15 live as JSON in the `col` table.
+#[derive(Deserialize)]
+struct LegacyModel {
+    name: String,
+    flds: Vec<LegacyField>,
+}
+
+#[derive(Deserialize)]
+struct LegacyField {
+    name: String,
+    ord: usize,
+}
+
+#[derive(Deserialize)]
+struct LegacyDeck {
+    name: String,
+}
+
+impl SqliteCollection {
+    /// Opens a collection file read-only.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AnkiError> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let conn = Connection::open_with_flags(path, flags).map_err(sql_error)?;
+        // Newer collections sort names with Anki's own collation.
+        conn.create_collation("unicase", |a, b| a.to_lowercase().cmp(&b.to_lowercase()))
+            .map_err(sql_error)?;
+
+        let mut collection = Self {
+            conn,
+            models: HashMap::new(),
+            decks: HashMap::new(),
+        };
+        match collection.has_table("notetypes")? {
+            true => collection.load_schema()?,
+            false => collection.load_legacy_schema()?,
+        }
+        Ok(collection)
+    }
+
+    /// Returns every deck's name with its id, like `DeckAction::deck_names_and_ids`.
+    pub fn deck_names_and_ids(&self) -> HashMap<String, DeckId> {
+        self.decks
+            .iter()
+            .map(|(id, name)| (name.clone(), *id))
+            .collect()
+    }
+
+    /// Returns the names of every model, sorted.
+    pub fn model_names(&self) -> Vec<String> {
+        let names: BTreeSet<_> = self.models.values().map(|m| m.name.clone()).collect();
+        names.into_iter().collect()
+    }
+
+    /// Returns every tag used by a note, sorted, like `NoteAction::get_tags`.
+    pub fn get_tags(&self) -> Result<Vec<String>, AnkiError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tags FROM notes")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sql_error)?;
+        let mut tags = BTreeSet::new();
+        for row in rows {
+            tags.extend(split_tags(&row.map_err(sql_error)?));
+        }
+        Ok(tags.into_iter().collect())
+    }
+
+    /// Returns the ids of the notes matching `query`, sorted.
+    ///
+    /// Supports deck, tag, note type, field, plain text and note id terms combined
+    /// with and, or and not; other terms fail with `AnkiError::ValidationError`.
+    /// Terms match their text literally, as `AnkiQuery` escapes it, while `Raw`
+    /// queries are parsed as search strings, where `*` and `_` are wildcards.
+    /// A note matches a deck if any of its cards is in it, and terms are matched
+    /// per note rather than per card.
+    pub fn find_note_ids(&self, query: &AnkiQuery) -> Result<Vec<NoteId>, AnkiError> {
+        self.find(&Search::from_query(query)?)
+    }
+
+    /// Returns the notes with the given ids, like `NoteAction::get_notes_infos`.
+    /// Ids without a note are skipped.
+    pub fn notes_infos(&self, ids: &[NoteId]) -> Result<Vec<NotesInfoData>, AnkiError> {
+        let rows = self.note_rows(Some(ids))?;
+        let mut by_id: HashMap<NoteId, NoteRow> = rows.into_iter().map(|r| (r.id, r)).collect();
+        let mut notes = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(row) = by_id.remove(id) else {
+                continue;
+            };
+            let Some(model) = self.models.get(&row.model) else {
+                return Err(AnkiError::ModelNotFound(row.model.to_string()));
+            };
+            let fields = row
+                .fields
+                .into_iter()
+                .enumerate()
+                .map(|(ord, value)| {
+                    let name = model.fields.get(ord).cloned().unwrap_or_default();
+                    let order = u8::try_from(ord).unwrap_or(u8::MAX);
+                    (name, FieldData { value, order })
+                })
+                .collect();
+            notes.push(NotesInfoData {
+                noteId: row.id,
+                modelName: model.name.clone(),
+                tags: row.tags,
+                fields,
+                modified: Some(row.modified),
+            });
+        }
+        Ok(notes)
+    }
+
+    /// Returns every deck's name, sorted, like `DeckAction::deck_names`.
+    pub fn deck_names(&self) -> Vec<String> {
+        let names: BTreeSet<_> = self.decks.values().cloned().collect();
+        names.into_iter().collect()
+    }
+
+    fn find(&self, search: &Search) -> Result<Vec<NoteId>, AnkiError> {
+        let decks = self.note_decks()?;
+        let no_decks = BTreeSet::new();
+        let mut ids = Vec::new();
+        for note in self.note_rows(None)? {
+            let note_decks = decks.get(&note.id).unwrap_or(&no_decks);
+            if self.matches(search, &note, note_decks) {
+                ids.push(note.id);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn has_table(&self, name: &str) -> Result<bool, AnkiError> {
+        self.conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [name],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count > 0)
+            .map_err(sql_error)
+    }
+
+    /// Reads models and decks from the tables of schema 15 and later.
+    fn load_schema(&mut self) -> Result<(), AnkiError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name FROM notetypes")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sql_error)?;
+        for row in rows {
+            let (id, name) = row.map_err(sql_error)?;
+            let model = SqliteModel {
+                name,
+                fields: Vec::new(),
+            };
+            self.models.insert(model_id(id)?, model);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT ntid, ord, name FROM fields ORDER BY ntid, ord")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(sql_error)?;
+        for row in rows {
+            let (ntid, ord, name) = row.map_err(sql_error)?;
+            if let Some(model) = self.models.get_mut(&model_id(ntid)?) {
+                set_field(&mut model.fields, ord, name);
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name FROM decks")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sql_error)?;
+        for row in rows {
+            let (id, name) = row.map_err(sql_error)?;
+            let name = name.replace(DECK_SEPARATOR, "::");
+            self.decks.insert(deck_id(id)?, name);
+        }
+        Ok(())
+    }
+
+    /// Reads models and decks from the JSON columns of collections before schema 15.
+    fn load_legacy_schema(&mut self) -> Result<(), AnkiError> {
+        let (models, decks): (String, String) = self
+            .conn
+            .query_row("SELECT models, decks FROM col", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(sql_error)?;
+
+        let models: HashMap<String, LegacyModel> = parse_json(&models)?;
+        for (id, model) in models {
+            let mut fields = Vec::new();
+            for field in model.flds {
+                set_field(&mut fields, field.ord as i64, field.name);
+            }
+            let id = parse_id(&id).map(ModelId)?;
+            self.models.insert(
+                id,
+                SqliteModel {
+                    name: model.name,
+                    fields,
+                },
+            );
+        }
+
+        let decks: HashMap<String, LegacyDeck> = parse_json(&decks)?;
+        for (id, deck) in decks {
+            self.decks.insert(parse_id(&id).map(DeckId)?, deck.name);
+        }
+        Ok(())
+    }
+
+    /// The notes with `ids`, or every note.
+    fn note_rows(&self, ids: Option<&[NoteId]>) -> Result<Vec<NoteRow>, AnkiError> {
+        let sql = match ids {
+            Some(ids) => format!(
+                "SELECT id, mid, tags, flds, mod FROM notes WHERE id IN ({})",
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            None => "SELECT id, mid, tags, flds, mod FROM notes".to_string(),
+        };
+        let mut stmt = self.conn.prepare(&sql).map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .map_err(sql_error)?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            let (id, mid, tags, flds, modified) = row.map_err(sql_error)?;
+            notes.push(NoteRow {
+                id: note_id(id)?,
+                model: model_id(mid)?,
+                tags: split_tags(&tags),
+                fields: flds.split(FIELD_SEPARATOR).map(str::to_string).collect(),
+                modified: u64::try_from(modified).unwrap_or_default(),
+            });
+        }
+        Ok(notes)
+    }
+
+    /// The decks each note has cards in, including the home decks of cards in
+    /// filtered decks.
+    fn note_decks(&self) -> Result<HashMap<NoteId, BTreeSet<DeckId>>, AnkiError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT nid, did, odid FROM cards")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .map_err(sql_error)?;
+
+        let mut decks: HashMap<NoteId, BTreeSet<DeckId>> = HashMap::new();
+        for row in rows {
+            let (nid, did, odid) = row.map_err(sql_error)?;
+            let note_decks = decks.entry(note_id(nid)?).or_default();
+            note_decks.insert(deck_id(did)?);
+            if odid != 0 {
+                note_decks.insert(deck_id(odid)?);
+            }
+        }
+        Ok(decks)
+    }
+
+    fn matches(&self, search: &Search, note: &NoteRow, decks: &BTreeSet<DeckId>) -> bool {
+        match search {
+            Search::Deck(pattern) => decks.iter().any(|id| {
+                self.decks.get(id).is_some_and(|name| {
+                    glob_matches(pattern, name) || glob_matches(&format!("{}::*", pattern), name)
+                })
+            }),
+            Search::Tag(pattern) => note.tags.iter().any(|tag| {
+                glob_matches(pattern, tag) || glob_matches(&format!("{}::*", pattern), tag)
+            }),
+            Search::Note(pattern) => self
+                .models
+                .get(&note.model)
+                .is_some_and(|model| glob_matches(pattern, &model.name)),
+            Search::Field { name, value } => {
+                let Some(model) = self.models.get(&note.model) else {
+                    return false;
+                };
+                model.fields.iter().enumerate().any(|(ord, field)| {
+                    glob_matches(name, field)
+                        && note.fields.get(ord).is_some_and(|v| glob_matches(value, v))
+                })
+            }
+            Search::Text(text) => {
+                let pattern = format!("*{}*", text);
+                note.fields
+                    .iter()
+                    .any(|value| glob_matches(&pattern, value))
+            }
+            Search::NoteIds(ids) => ids.contains(&note.id),
+            Search::And(searches) => searches.iter().all(|s| self.matches(s, note, decks)),
+            Search::Or(searches) => searches.iter().any(|s| self.matches(s, note, decks)),
+            Search::Not(search) => !self.matches(search, note, decks),
+        }
+    }
+}
+
+/// Answers AnkiConnect's read actions from a collection file, so the crate's own
+/// actions, like `NoteAction::find_note_ids`, work with Anki closed.
+///
+/// Answers `version`, `deckNames`, `deckNamesAndIds`, `modelNames`, `getTags`,
+/// `findNotes` and `notesInfo`, alone or in a `multi`; other actions fail with an
+/// AnkiConnect error. Search strings support the terms `find_note_ids` does.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::notes::NoteAction;
+/// # use anki_direct::sqlite::SqliteTransport;
+/// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+/// let transport = SqliteTransport::open("/home/me/.local/share/Anki2/User 1/collection.anki2")?;
+/// let client = AnkiClient::default().with_transport(transport);
+/// let ids = NoteAction::find_note_ids(&client, "deck:Mining tag:leech").await?;
+/// let notes = NoteAction::get_notes_infos(&client, ids).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SqliteTransport {
+    collection: Mutex<SqliteCollection>,
+}
+
+impl SqliteTransport {
+    pub fn new(collection: SqliteCollection) -> Self {
+        Self {
+            collection: Mutex::new(collection),
+        }
+    }
+
+    /// Opens a collection file read-only, like `SqliteCollection::open`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AnkiError> {
+        SqliteCollection::open(path).map(Self::new)
+    }
+
+    /// The result of one request, or of each action of a `multi`.
+    fn answer(&self, request: &Value) -> Result<Value, AnkiError> {
+        let action = Action::from(request["action"].as_str().unwrap_or_default());
+        let params = &request["params"];
+        if action == Action::Multi {
+            let Some(actions) = params["actions"].as_array() else {
+                return Err(AnkiError::RequestError("multi without actions".to_string()));
+            };
+            let results: Vec<Value> = actions
+                .iter()
+                .map(|inner| envelope(self.answer(inner)))
+                .collect();
+            return Ok(Value::Array(results));
+        }
+
+        let collection = match self.collection.lock() {
+            Ok(collection) => collection,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match action {
+            Action::Version => Ok(json!(6)),
+            Action::DeckNames => to_json(collection.deck_names()),
+            Action::DeckNamesAndIds => to_json(collection.deck_names_and_ids()),
+            Action::ModelNames => to_json(collection.model_names()),
+            Action::GetTags => to_json(collection.get_tags()?),
+            Action::FindNotes => {
+                let query = params["query"].as_str().unwrap_or_default();
+                to_json(collection.find(&Search::parse(query)?)?)
+            }
+            Action::NotesInfo => {
+                let ids: Vec<NoteId> = serde_json::from_value(params["notes"].clone())
+                    .map_err(|e| AnkiError::ParseError(e.to_string()))?;
+                to_json(collection.notes_infos(&ids)?)
+            }
+            other => Err(AnkiError::RequestError(format!(
+                "`{}` isn't supported when reading the collection file",
+                other
+            ))),
+        }
+    }
+}
+
+impl AnkiTransport for SqliteTransport {
+    fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a> {
+        let res = envelope(self.answer(request));
+        Box::pin(async move { Ok(res) })
+    }
+}
+
+/// AnkiConnect's `{ result, error }` envelope.
+fn envelope(result: Result<Value, AnkiError>) -> Value {
+    match result {
+        Ok(result) => json!({ "result": result, "error": null }),
+        Err(e) => json!({ "result": null, "error": e.to_string() }),
+    }
+}
+
+fn to_json(value: impl Serialize) -> Result<Value, AnkiError> {
+    serde_json::to_value(value).map_err(|e| AnkiError::ParseError(e.to_string()))
+}
+
+/// Search prefixes Anki reserves that the SQLite backend can't evaluate.
+const UNSUPPORTED_PREFIXES: &[&str] = &[
+    "added",
+    "card",
+    "cid",
+    "did",
+    "dupe",
+    "edited",
+    "flag",
+    "introduced",
+    "is",
+    "mid",
+    "nc",
+    "preset",
+    "prop",
+    "rated",
+    "re",
+    "resched",
+    "w",
+];
+
+/// A search the SQLite backend can evaluate. Its terms are Anki search patterns, in
+/// which `*` and `_` are wildcards unless escaped with a backslash.
+#[derive(Debug, Clone, PartialEq)]
+enum Search {
+    Deck(String),
+    Tag(String),
+    Note(String),
+    Field { name: String, value: String },
+    Text(String),
+    NoteIds(Vec<NoteId>),
+    And(Vec<Search>),
+    Or(Vec<Search>),
+    Not(Box<Search>),
+}
+
+impl Search {
+    /// Converts a query, escaping its literal terms, or fails with `ValidationError`
+    /// if it has terms the backend can't evaluate.
+    fn from_query(query: &AnkiQuery) -> Result<Self, AnkiError> {
+        let all = |queries: &[AnkiQuery]| -> Result<Vec<Search>, AnkiError> {
+            queries.iter().map(Search::from_query).collect()
+        };
+        Ok(match query {
+            AnkiQuery::Deck(name) => Search::Deck(escape(name)),
+            AnkiQuery::Tag(name) => Search::Tag(escape(name)),
+            AnkiQuery::Note(model) => Search::Note(escape(model)),
+            AnkiQuery::Field { name, value } => Search::Field {
+                name: escape(name),
+                value: escape(value),
+            },
+            AnkiQuery::Text(text) => Search::Text(escape(text)),
+            AnkiQuery::NoteIds(ids) => Search::NoteIds(ids.clone()),
+            AnkiQuery::Raw(query) => Search::parse(query)?,
+            AnkiQuery::And(queries) => Search::And(all(queries)?),
+            AnkiQuery::Or(queries) => Search::Or(all(queries)?),
+            AnkiQuery::Not(query) => Search::Not(Box::new(Search::from_query(query)?)),
+            AnkiQuery::Group(query) => Search::from_query(query)?,
+            other => {
+                return Err(AnkiError::ValidationError(format!(
+                    "`{}` isn't supported when reading the collection file",
+                    other
+                )))
+            }
+        })
+    }
+
+    /// Parses a search string, like the query of `findNotes`. An empty one matches
+    /// every note.
+    fn parse(query: &str) -> Result<Self, AnkiError> {
+        let invalid = |reason: String| {
+            AnkiError::ValidationError(format!(
+                "`{}` can't be searched in the collection file: {}",
+                query, reason
+            ))
+        };
+        let tokens = tokenize(query).map_err(invalid)?;
+        if tokens.is_empty() {
+            return Ok(Search::And(Vec::new()));
+        }
+        let mut parser = Parser { tokens, at: 0 };
+        let search = parser.or().map_err(invalid)?;
+        match parser.tokens.get(parser.at) {
+            None => Ok(search),
+            Some(_) => Err(invalid("unbalanced parentheses".to_string())),
+        }
+    }
+
+    /// A single term, like `deck:Mining` or `dog`.
+    fn term(word: &str) -> Result<Self, String> {
+        let Some(colon) = unescaped_colon(word) else {
+            return Ok(Search::Text(word.to_string()));
+        };
+        let (name, value) = (&word[..colon], &word[colon + 1..]);
+        Ok(match name.to_lowercase().as_str() {
+            "deck" => Search::Deck(value.to_string()),
+            "tag" => Search::Tag(value.to_string()),
+            "note" => Search::Note(value.to_string()),
+            "nid" => Search::NoteIds(
+                value
+                    .split(',')
+                    .map(|id| match id.trim().parse() {
+                        Ok(id) => Ok(NoteId(id)),
+                        Err(_) => Err(format!("invalid note id `{}`", id)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            prefix if UNSUPPORTED_PREFIXES.contains(&prefix) => {
+                return Err(format!("`{}:` terms aren't supported", name))
+            }
+            _ => Search::Field {
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    /// A term with its quotes removed and its escapes kept.
+    Word(String),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '-' => Token::Not,
+            _ => {
+                let mut word = String::new();
+                let (mut quoted, mut had_quotes) = (false, false);
+                while let Some(&c) = chars.peek() {
+                    if !quoted && (c.is_whitespace() || matches!(c, '(' | ')')) {
+                        break;
+                    }
+                    chars.next();
+                    match c {
+                        '"' => (quoted, had_quotes) = (!quoted, true),
+                        '\\' => {
+                            word.push(c);
+                            word.extend(chars.next());
+                        }
+                        _ => word.push(c),
+                    }
+                }
+                if quoted {
+                    return Err("unbalanced quotes".to_string());
+                }
+                // A quoted `and` or `or` is a term.
+                match word.to_lowercase().as_str() {
+                    "and" if !had_quotes => Token::And,
+                    "or" if !had_quotes => Token::Or,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        if matches!(token, Token::Open | Token::Close | Token::Not) {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parses tokens into a search, with `and` binding tighter than `or`.
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Search, String> {
+        let mut any = vec![self.and()?];
+        while self.tokens.get(self.at) == Some(&Token::Or) {
+            self.at += 1;
+            any.push(self.and()?);
+        }
+        Ok(match any.len() {
+            1 => any.remove(0),
+            _ => Search::Or(any),
+        })
+    }
+
+    fn and(&mut self) -> Result<Search, String> {
+        let mut all = Vec::new();
+        loop {
+            match self.tokens.get(self.at) {
+                None | Some(Token::Close | Token::Or) => break,
+                Some(Token::And) => self.at += 1,
+                Some(_) => all.push(self.unary()?),
+            }
+        }
+        match all.len() {
+            0 => Err("expected a search term".to_string()),
+            1 => Ok(all.remove(0)),
+            _ => Ok(Search::And(all)),
+        }
+    }
+
+    fn unary(&mut self) -> Result<Search, String> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        match token {
+            Some(Token::Not) => Ok(Search::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let search = self.or()?;
+                match self.tokens.get(self.at) {
+                    Some(Token::Close) => {
+                        self.at += 1;
+                        Ok(search)
+                    }
+                    _ => Err("unbalanced parentheses".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => Search::term(&word),
+            _ => Err("expected a search term".to_string()),
+        }
+    }
+}
+
+/// One element of a search pattern.
+#[derive(Debug, PartialEq)]
+enum Glob {
+    /// `*`
+    Any,
+    /// `_`
+    One,
+    Char(char),
+}
+
+/// Matches `text` against an Anki search pattern, case-insensitively, where `*`
+/// matches any run of characters, `_` a single one, and a backslash makes the next
+/// character literal.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = glob_pattern(pattern);
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and the text position it has consumed up to.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(Glob::Any) => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(Glob::One) => {
+                p += 1;
+                t += 1;
+            }
+            Some(Glob::Char(c)) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|g| *g == Glob::Any)
+}
+
+fn glob_pattern(pattern: &str) -> Vec<Glob> {
+    let pattern = pattern.to_lowercase();
+    let mut chars = pattern.chars();
+    let mut globs = Vec::new();
+    while let Some(c) = chars.next() {
+        globs.push(match c {
+            '*' => Glob::Any,
+            '_' => Glob::One,
+            '\\' => Glob::Char(chars.next().unwrap_or('\\')),
+            c => Glob::Char(c),
+        });
+    }
+    globs
+}
+
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split_whitespace().map(str::to_string).collect()
+}
+
+fn set_field(fields: &mut Vec<String>, ord: i64, name: String) {
+    let Ok(ord) = usize::try_from(ord) else {
+        return;
+    };
+    if fields.len() <= ord {
+        fields.resize(ord + 1, String::new());
+    }
+    fields[ord] = name;
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, AnkiError> {
+    serde_json::from_str(json).map_err(|e| AnkiError::ParseError(e.to_string()))
+}
+
+fn parse_id(id: &str) -> Result<u64, AnkiError> {
+    id.parse()
+        .map_err(|_| AnkiError::ParseError(format!("invalid id `{}`", id)))
+}
+
+fn note_id(id: i64) -> Result<NoteId, AnkiError> {
+    NoteId::try_from(id).map_err(|_| AnkiError::ParseError(format!("invalid note id {}", id)))
+}
+
+fn model_id(id: i64) -> Result<ModelId, AnkiError> {
+    ModelId::try_from(id).map_err(|_| AnkiError::ParseError(format!("invalid model id {}", id)))
+}
+
+fn deck_id(id: i64) -> Result<DeckId, AnkiError> {
+    DeckId::try_from(id).map_err(|_| AnkiError::ParseError(format!("invalid deck id {}", id)))
+}
+
+fn sql_error(e: rusqlite::Error) -> AnkiError {
+    match e.sqlite_error_code() {
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+            AnkiError::CollectionUnavailable
+        }
+        _ => AnkiError::RequestError(e.to_string()),
+    }
+}
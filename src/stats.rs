@@ -0,0 +1,669 @@
+#![allow(non_snake_case)]
+use crate::action::{Action, ActionParams, ActionRequest};
+use crate::decks::DeckAction;
+use crate::error::AnkiError;
+use crate::result::{ReviewKind, ReviewRow};
+use crate::{post_generic_request, post_simple_action, AnkiClient};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionStatsParams {
+    pub wholeCollection: bool,
+}
+
+impl ActionParams for CollectionStatsParams {
+    const ACTION: Action = Action::GetCollectionStatsHtml;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CardReviewsParams {
+    pub deck: String,
+    pub startID: u64,
+}
+
+impl ActionParams for CardReviewsParams {
+    const ACTION: Action = Action::CardReviews;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LatestReviewIdParams {
+    pub deck: String,
+}
+
+impl ActionParams for LatestReviewIdParams {
+    const ACTION: Action = Action::GetLatestReviewId;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InsertReviewsParams {
+    pub reviews: Vec<ReviewRow>,
+}
+
+impl ActionParams for InsertReviewsParams {
+    const ACTION: Action = Action::InsertReviews;
+}
+
+/// The review statistics actions. Each builds an `ActionRequest` from its own params type.
+#[derive(Debug)]
+pub struct StatsAction;
+
+/// Headline numbers read from the statistics page by `parse_collection_stats`.
+///
+/// Fields are `None` when the page doesn't show them, e.g. for an empty deck
+/// or a layout this parser doesn't recognise.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CollectionStats {
+    pub total_cards: Option<u64>,
+    pub mature_cards: Option<u64>,
+    /// Share of correct answers, from 0 to 100.
+    pub retention: Option<f64>,
+    /// Average answer time in seconds.
+    pub average_answer_seconds: Option<f64>,
+}
+
+impl StatsAction {
+    /// Returns how many cards have been reviewed today, by Anki's day cutoff.
+    pub async fn reviewed_today(anki_client: &AnkiClient) -> Result<u64, AnkiError> {
+        post_simple_action(anki_client, Action::GetNumCardsReviewedToday).await
+    }
+
+    /// Returns how many cards were reviewed on each day that had reviews.
+    ///
+    /// Days without reviews are absent rather than zero.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::stats::StatsAction;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let by_day = StatsAction::reviewed_by_day(&client).await?;
+    /// let today = chrono::Local::now().date_naive();
+    /// let studied_today = by_day.contains_key(&today);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reviewed_by_day(
+        anki_client: &AnkiClient,
+    ) -> Result<BTreeMap<NaiveDate, u64>, AnkiError> {
+        let days: Vec<(String, u64)> =
+            post_simple_action(anki_client, Action::GetNumCardsReviewedByDay).await?;
+
+        let mut by_day = BTreeMap::new();
+        for (day, count) in days {
+            match NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+                Ok(date) => {
+                    by_day.insert(date, count);
+                }
+                Err(e) => return Err(AnkiError::ParseError(format!("{}: {}", day, e))),
+            }
+        }
+        Ok(by_day)
+    }
+
+    /// Returns the HTML of Anki's statistics page, for the whole collection
+    /// or only the current deck.
+    pub async fn collection_stats_html(
+        anki_client: &AnkiClient,
+        whole_collection: bool,
+    ) -> Result<String, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            CollectionStatsParams {
+                wholeCollection: whole_collection,
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Returns the review log rows of a deck newer than `since_id`.
+    ///
+    /// Only cards in exactly `deck` are included, not its subdecks. AnkiConnect creates
+    /// the deck if there is none by that name; see `all_card_reviews` for every deck.
+    ///
+    /// Pass `0` for the full history, then the largest `review_id` seen so far
+    /// to collect new reviews incrementally.
+    pub async fn card_reviews(
+        anki_client: &AnkiClient,
+        deck: &str,
+        since_id: u64,
+    ) -> Result<Vec<ReviewRow>, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            CardReviewsParams {
+                deck: deck.to_string(),
+                startID: since_id,
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Returns the review log rows of every deck newer than `since_id`, oldest first.
+    ///
+    /// `cardReviews` takes a single deck by its exact name, without subdecks, and
+    /// creates the deck if it doesn't exist, so there is no wildcard for it. Instead
+    /// the decks are listed and each is asked for in one `multi` request.
+    pub async fn all_card_reviews(
+        anki_client: &AnkiClient,
+        since_id: u64,
+    ) -> Result<Vec<ReviewRow>, AnkiError> {
+        let decks = DeckAction::deck_names(anki_client).await?;
+        let mut batch = anki_client.batch();
+        let mut items = Vec::with_capacity(decks.len());
+        for deck in decks {
+            let params = CardReviewsParams {
+                deck,
+                startID: since_id,
+            };
+            let request = ActionRequest::new(anki_client.version, params);
+            items.push(batch.push::<Vec<ReviewRow>>(&request)?);
+        }
+
+        let mut results = batch.send().await?;
+        let mut rows = Vec::new();
+        for item in items {
+            match results.take(item) {
+                Ok(deck_rows) => rows.extend(deck_rows),
+                Err(AnkiError::NoDataFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        rows.sort_by_key(|r| r.review_id);
+        Ok(rows)
+    }
+
+    /// Writes rows into the review log, e.g. history migrated from another SRS app.
+    ///
+    /// Every row is checked with `validate_review_row` first and nothing is sent
+    /// if any is invalid. Rows whose `review_id` already exists are ignored by Anki.
+    pub async fn insert_reviews(
+        anki_client: &AnkiClient,
+        reviews: Vec<ReviewRow>,
+    ) -> Result<(), AnkiError> {
+        for (i, row) in reviews.iter().enumerate() {
+            if let Err(e) = validate_review_row(row) {
+                return Err(AnkiError::ValidationError(format!(
+                    "review row {}: {}",
+                    i, e
+                )));
+            }
+        }
+
+        let payload = ActionRequest::new(anki_client.version, InsertReviewsParams { reviews });
+
+        post_generic_request::<serde_json::Value>(&payload, anki_client)
+            .await?
+            .into_unit()
+    }
+
+    /// Pulls a deck's review log since `since` and computes its retention figures.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::stats::StatsAction;
+    /// # async fn run() -> Result<(), anki_direct::error::AnkiError> {
+    /// let client = AnkiClient::default();
+    /// let month_ago = chrono::Utc::now() - chrono::Duration::days(30);
+    /// let report = StatsAction::retention(&client, "Mining", month_ago).await?;
+    /// println!("true retention: {:?}", report.true_retention());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn retention(
+        anki_client: &AnkiClient,
+        deck: &str,
+        since: DateTime<Utc>,
+    ) -> Result<RetentionReport, AnkiError> {
+        let since_id = since.timestamp_millis().max(0) as u64;
+        let rows = match StatsAction::card_reviews(anki_client, deck, since_id).await {
+            Ok(rows) => rows,
+            Err(AnkiError::NoDataFound) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(retention_report(&rows))
+    }
+
+    /// Exports the review log rows in `scope` to `writer` and returns how many were written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use anki_direct::AnkiClient;
+    /// # use anki_direct::stats::{ReviewExportFormat, ReviewScope, StatsAction};
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = AnkiClient::default();
+    /// let file = std::fs::File::create("reviews.csv")?;
+    /// StatsAction::export_reviews(&client, &ReviewScope::all(), ReviewExportFormat::Csv, file)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_reviews(
+        anki_client: &AnkiClient,
+        scope: &ReviewScope,
+        format: ReviewExportFormat,
+        writer: impl Write + Send,
+    ) -> Result<usize, AnkiError> {
+        let since_id = scope
+            .since
+            .map_or(0, |s| s.timestamp_millis().max(0) as u64);
+        let rows = match &scope.deck {
+            Some(deck) => match StatsAction::card_reviews(anki_client, deck, since_id).await {
+                Ok(rows) => rows,
+                Err(AnkiError::NoDataFound) => Vec::new(),
+                Err(e) => return Err(e),
+            },
+            None => StatsAction::all_card_reviews(anki_client, since_id).await?,
+        };
+
+        let written = match format {
+            ReviewExportFormat::Csv => write_reviews_csv(&rows, writer).map_err(|e| e.to_string()),
+            #[cfg(feature = "parquet")]
+            ReviewExportFormat::Parquet => {
+                write_reviews_parquet(&rows, writer).map_err(|e| e.to_string())
+            }
+        };
+        match written {
+            Ok(()) => Ok(rows.len()),
+            Err(e) => Err(AnkiError::RequestError(format!(
+                "failed to write reviews: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Builds review counts per day and per hour for the days in `range`.
+    ///
+    /// Daily counts come from `getNumCardsReviewedByDay` and follow Anki's day cutoff;
+    /// hourly counts come from the review log of every deck and use local time,
+    /// so reviews just after midnight can land on different days in the two maps.
+    pub async fn heatmap(
+        anki_client: &AnkiClient,
+        range: RangeInclusive<NaiveDate>,
+    ) -> Result<Heatmap, AnkiError> {
+        let by_day = StatsAction::reviewed_by_day(anki_client).await?;
+        let days = range
+            .start()
+            .iter_days()
+            .take_while(|d| d <= range.end())
+            .map(|d| (d, by_day.get(&d).copied().unwrap_or_default()))
+            .collect();
+
+        let since = range
+            .start()
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(Local)
+            .earliest()
+            .map_or(0, |t| t.timestamp_millis().max(0) as u64);
+        let rows = StatsAction::all_card_reviews(anki_client, since).await?;
+        let mut hours = bucket_reviews_by_hour(&rows, &Local);
+        hours.retain(|d, _| range.contains(d));
+
+        Ok(Heatmap { days, hours })
+    }
+
+    /// Returns the id of the newest review logged in a deck, or `0` if it has none.
+    ///
+    /// Review ids are millisecond timestamps, so this is a cheap way to notice new reviews.
+    pub async fn latest_review_id(anki_client: &AnkiClient, deck: &str) -> Result<u64, AnkiError> {
+        let payload = ActionRequest::new(
+            anki_client.version,
+            LatestReviewIdParams {
+                deck: deck.to_string(),
+            },
+        );
+
+        post_generic_request(&payload, anki_client)
+            .await?
+            .into_result()
+    }
+
+    /// Fetches the statistics page and extracts its headline numbers.
+    pub async fn collection_stats(
+        anki_client: &AnkiClient,
+        whole_collection: bool,
+    ) -> Result<CollectionStats, AnkiError> {
+        let html = StatsAction::collection_stats_html(anki_client, whole_collection).await?;
+        Ok(parse_collection_stats(&html))
+    }
+}
+
+/// Which review log rows `StatsAction::export_reviews` exports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewScope {
+    /// `None` for every deck.
+    pub deck: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl ReviewScope {
+    /// The whole history of the cards in exactly this deck. Subdecks are not included;
+    /// `cardReviews` only looks at cards whose deck is the one named.
+    pub fn deck(deck: &str) -> Self {
+        Self {
+            deck: Some(deck.to_string()),
+            since: None,
+        }
+    }
+
+    /// The whole history of every deck.
+    pub fn all() -> Self {
+        Self {
+            deck: None,
+            since: None,
+        }
+    }
+
+    /// Only reviews after `since`.
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+}
+
+/// File formats `StatsAction::export_reviews` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewExportFormat {
+    /// Comma separated, with a header row.
+    Csv,
+    /// A single Parquet row group. Requires the `parquet` feature.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Column names of exported review logs, in `ReviewRow` field order.
+pub const REVIEW_COLUMNS: [&str; 9] = [
+    "review_id",
+    "card_id",
+    "usn",
+    "ease",
+    "ivl",
+    "last_ivl",
+    "factor",
+    "time",
+    "review_type",
+];
+
+/// Writes review log rows as CSV with a header row.
+pub fn write_reviews_csv(rows: &[ReviewRow], mut writer: impl Write) -> std::io::Result<()> {
+    writeln!(writer, "{}", REVIEW_COLUMNS.join(","))?;
+    for r in rows {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{}",
+            r.review_id,
+            r.card_id,
+            r.usn,
+            r.ease,
+            r.ivl,
+            r.last_ivl,
+            r.factor,
+            r.time,
+            u8::from(r.review_type)
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes review log rows as a Parquet file.
+///
+/// `card_id` is stored as a 64-bit integer, which holds every millisecond id Anki generates.
+#[cfg(feature = "parquet")]
+pub fn write_reviews_parquet(
+    rows: &[ReviewRow],
+    writer: impl Write + Send,
+) -> Result<(), parquet::errors::ParquetError> {
+    use arrow_array::{ArrayRef, Int64Array, RecordBatch, UInt64Array, UInt8Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("review_id", DataType::UInt64, false),
+        Field::new("card_id", DataType::UInt64, false),
+        Field::new("usn", DataType::Int64, false),
+        Field::new("ease", DataType::UInt8, false),
+        Field::new("ivl", DataType::Int64, false),
+        Field::new("last_ivl", DataType::Int64, false),
+        Field::new("factor", DataType::Int64, false),
+        Field::new("time", DataType::UInt64, false),
+        Field::new("review_type", DataType::UInt8, false),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|r| r.review_id),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|r| r.card_id.0),
+        )),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.usn))),
+        Arc::new(UInt8Array::from_iter_values(rows.iter().map(|r| r.ease))),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.ivl))),
+        Arc::new(Int64Array::from_iter_values(
+            rows.iter().map(|r| r.last_ivl),
+        )),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.factor))),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|r| r.time))),
+        Arc::new(UInt8Array::from_iter_values(
+            rows.iter().map(|r| u8::from(r.review_type)),
+        )),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(writer, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Reviews per day, and per hour within each day, for calendar heatmaps.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Heatmap {
+    /// Reviews per day by Anki's day cutoff. Every day of the range is present, zero or not.
+    pub days: BTreeMap<NaiveDate, u64>,
+    /// Reviews per local clock hour, for days that had reviews.
+    pub hours: BTreeMap<NaiveDate, [u32; 24]>,
+}
+
+impl Heatmap {
+    /// The largest daily count, for scaling a colour ramp.
+    pub fn max_per_day(&self) -> u64 {
+        self.days.values().copied().max().unwrap_or_default()
+    }
+}
+
+/// Counts reviews per clock hour of each calendar day in `tz`.
+pub fn bucket_reviews_by_hour<Tz: TimeZone>(
+    rows: &[ReviewRow],
+    tz: &Tz,
+) -> BTreeMap<NaiveDate, [u32; 24]> {
+    let mut hours: BTreeMap<NaiveDate, [u32; 24]> = BTreeMap::new();
+    for row in rows {
+        let Some(at) = tz.timestamp_millis_opt(row.review_id as i64).single() else {
+            continue;
+        };
+        let local = at.naive_local();
+        hours.entry(local.date()).or_insert([0; 24])[local.hour() as usize] += 1;
+    }
+    hours
+}
+
+/// Interval in days from which Anki counts a card as mature.
+pub const MATURE_INTERVAL_DAYS: i64 = 21;
+
+/// Review counts for one maturity group in a `RetentionReport`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct MaturityStats {
+    pub reviews: usize,
+    /// Reviews answered with Again.
+    pub again: usize,
+}
+
+impl MaturityStats {
+    /// Share of reviews answered with Again, from 0 to 1.
+    pub fn again_rate(&self) -> Option<f64> {
+        (self.reviews > 0).then(|| self.again as f64 / self.reviews as f64)
+    }
+
+    /// Share of reviews passed, from 0 to 1.
+    pub fn retention(&self) -> Option<f64> {
+        self.again_rate().map(|rate| 1.0 - rate)
+    }
+}
+
+/// Retention and timing figures computed by `retention_report`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    /// Every logged answer in the period, including learning steps.
+    pub total_reviews: usize,
+    /// Reviews of cards whose previous interval was under 21 days.
+    pub young: MaturityStats,
+    /// Reviews of cards whose previous interval was 21 days or more.
+    pub mature: MaturityStats,
+    /// Average answer time in seconds, over every logged answer.
+    pub average_answer_seconds: Option<f64>,
+}
+
+impl RetentionReport {
+    /// True retention: the pass rate of review cards, ignoring learning and relearning steps.
+    pub fn true_retention(&self) -> Option<f64> {
+        MaturityStats {
+            reviews: self.young.reviews + self.mature.reviews,
+            again: self.young.again + self.mature.again,
+        }
+        .retention()
+    }
+}
+
+/// Computes retention figures from review log rows.
+///
+/// Only `ReviewKind::Review` rows count towards retention; manual reschedules are skipped entirely.
+pub fn retention_report(rows: &[ReviewRow]) -> RetentionReport {
+    let mut report = RetentionReport::default();
+    let mut total_time = 0u64;
+
+    for row in rows {
+        match row.review_type {
+            ReviewKind::Manual => continue,
+            ReviewKind::Review => {
+                let group = match row.last_ivl >= MATURE_INTERVAL_DAYS {
+                    true => &mut report.mature,
+                    false => &mut report.young,
+                };
+                group.reviews += 1;
+                if row.ease == 1 {
+                    group.again += 1;
+                }
+            }
+            _ => {}
+        }
+        report.total_reviews += 1;
+        total_time += row.time;
+    }
+
+    if report.total_reviews > 0 {
+        report.average_answer_seconds =
+            Some(total_time as f64 / 1000.0 / report.total_reviews as f64);
+    }
+    report
+}
+
+/// Millisecond timestamps between 2001 and 2286; seconds or microseconds fall outside.
+const MILLIS_RANGE: std::ops::Range<u64> = 1_000_000_000_000..10_000_000_000_000;
+
+/// Checks that a review row's values are in the places Anki expects them.
+///
+/// Catches the usual mistakes when building rows by hand: timestamps in seconds
+/// instead of milliseconds, swapped id columns, and out of range buttons or factors.
+pub fn validate_review_row(row: &ReviewRow) -> Result<(), String> {
+    if !MILLIS_RANGE.contains(&row.review_id) {
+        return Err(format!(
+            "review_id {} is not a millisecond timestamp",
+            row.review_id
+        ));
+    }
+    if !(row.card_id.0 > 0 && row.card_id.0 < MILLIS_RANGE.end) {
+        return Err(format!("card_id {} is not a card id", row.card_id));
+    }
+    if row.ease > 4 {
+        return Err(format!("ease {} is not between 0 and 4", row.ease));
+    }
+    if row.ease == 0 && row.review_type != ReviewKind::Manual {
+        return Err("ease 0 is only used for manual rescheduling".to_string());
+    }
+    if row.factor < 0 {
+        return Err(format!("factor {} is negative", row.factor));
+    }
+    Ok(())
+}
+
+/// Best-effort extraction of headline numbers from `getCollectionStatsHTML` output.
+///
+/// The page is meant for people, not programs, so this looks for the labels Anki's
+/// English statistics page uses and leaves a field `None` when its label isn't found.
+pub fn parse_collection_stats(html: &str) -> CollectionStats {
+    let text = html_text(html);
+    CollectionStats {
+        total_cards: number_after(&text, "Total").map(|n| n as u64),
+        mature_cards: number_after(&text, "Mature").map(|n| n as u64),
+        retention: number_after(&text, "Correct"),
+        average_answer_seconds: number_before(&text, "s/card"),
+    }
+}
+
+/// Replaces tags with spaces, so table cells stay separated.
+fn html_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&nbsp;", " ")
+}
+
+/// The first number directly following `label`, skipping whitespace and colons.
+fn number_after(text: &str, label: &str) -> Option<f64> {
+    text.match_indices(label).find_map(|(i, _)| {
+        let rest =
+            text[i + label.len()..].trim_start_matches(|c: char| c.is_whitespace() || c == ':');
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == ',' || c == '.'))
+            .unwrap_or(rest.len());
+        parse_number(&rest[..len])
+    })
+}
+
+/// The number directly preceding `label`.
+fn number_before(text: &str, label: &str) -> Option<f64> {
+    text.match_indices(label).find_map(|(i, _)| {
+        let head = &text[..i];
+        let start = head
+            .rfind(|c: char| !(c.is_ascii_digit() || c == ',' || c == '.'))
+            .map_or(0, |j| j + 1);
+        parse_number(&head[start..])
+    })
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    s.trim_end_matches('.').replace(',', "").parse().ok()
+}
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Field names Anki fills in itself rather than reading from the note.
+pub const SPECIAL_FIELDS: [&str; 8] = [
+    "FrontSide",
+    "Card",
+    "CardFlag",
+    "CardID",
+    "Deck",
+    "Subdeck",
+    "Tags",
+    "Type",
+];
+
+/// A piece of a card template, as split by `tokenize`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'a> {
+    Text(&'a str),
+    /// `{{Field}}` or `{{filter:...:Field}}`.
+    Replacement {
+        field: &'a str,
+        filters: Vec<&'a str>,
+    },
+    /// `{{#Field}}`, or `{{^Field}}` when `negated`.
+    SectionOpen {
+        field: &'a str,
+        negated: bool,
+    },
+    /// `{{/Field}}`.
+    SectionClose {
+        field: &'a str,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateSide {
+    Front,
+    Back,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TemplateIssueKind {
+    /// A tag references a field that the model doesn't have.
+    UnknownField(String),
+    /// A `{{#Field}}`/`{{^Field}}` section is never closed.
+    UnclosedSection(String),
+    /// A `{{/Field}}` doesn't match the innermost open section.
+    UnexpectedClose(String),
+}
+
+/// A problem found in one side of one card template.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TemplateIssue {
+    pub template: String,
+    pub side: TemplateSide,
+    pub kind: TemplateIssueKind,
+}
+
+/// Splits a template into text and `{{...}}` tags.
+///
+/// An unterminated `{{` is kept as text, matching how Anki renders it.
+pub fn tokenize(template: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        tokens.push(parse_tag(rest[start + 2..start + 2 + len].trim()));
+        rest = &rest[start + 2 + len + 2..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+
+    tokens
+}
+
+fn parse_tag(inner: &str) -> Token<'_> {
+    if let Some(field) = inner.strip_prefix('#') {
+        return Token::SectionOpen {
+            field: field.trim(),
+            negated: false,
+        };
+    }
+    if let Some(field) = inner.strip_prefix('^') {
+        return Token::SectionOpen {
+            field: field.trim(),
+            negated: true,
+        };
+    }
+    if let Some(field) = inner.strip_prefix('/') {
+        return Token::SectionClose {
+            field: field.trim(),
+        };
+    }
+
+    let mut parts: Vec<&str> = inner.split(':').collect();
+    let field = parts.pop().unwrap_or_default().trim();
+    Token::Replacement {
+        field,
+        filters: parts.into_iter().map(str::trim).collect(),
+    }
+}
+
+/// Checks one side of a template against the model's field names.
+pub fn validate_template(
+    template: &str,
+    side: TemplateSide,
+    source: &str,
+    fields: &[String],
+) -> Vec<TemplateIssue> {
+    let known = |field: &str| SPECIAL_FIELDS.contains(&field) || fields.iter().any(|f| f == field);
+    let issue = |kind| TemplateIssue {
+        template: template.to_string(),
+        side,
+        kind,
+    };
+
+    let mut issues = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+
+    for token in tokenize(source) {
+        match token {
+            Token::Text(_) => {}
+            Token::Replacement { field, .. } => {
+                if !field.is_empty() && !known(field) {
+                    issues.push(issue(TemplateIssueKind::UnknownField(field.to_string())));
+                }
+            }
+            Token::SectionOpen { field, .. } => {
+                if !known(field) {
+                    issues.push(issue(TemplateIssueKind::UnknownField(field.to_string())));
+                }
+                open.push(field);
+            }
+            Token::SectionClose { field } => match open.last() {
+                Some(last) if *last == field => {
+                    open.pop();
+                }
+                _ => issues.push(issue(TemplateIssueKind::UnexpectedClose(field.to_string()))),
+            },
+        }
+    }
+
+    for field in open {
+        issues.push(issue(TemplateIssueKind::UnclosedSection(field.to_string())));
+    }
+
+    issues
+}
+
+/// The HTML of both sides of a card, as rendered by `render_card`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CardPreview {
+    pub front: String,
+    pub back: String,
+}
+
+/// Renders a card locally from its template sides and the note's field values.
+///
+/// * `cloze`: The cloze number being shown, for cloze models. `None` for standard models.
+///
+/// Supports field replacement, `{{FrontSide}}`, `{{#Field}}`/`{{^Field}}` sections and
+/// the `cloze:` and `text:` filters. Other filters are ignored and the raw field value is used,
+/// so the output is an approximation of what Anki's reviewer shows, without its CSS or scripts.
+pub fn render_card(
+    qfmt: &str,
+    afmt: &str,
+    fields: &HashMap<String, String>,
+    cloze: Option<usize>,
+) -> CardPreview {
+    let front = render(qfmt, fields, cloze, None);
+    let back = render(afmt, fields, cloze, Some(&front));
+    CardPreview { front, back }
+}
+
+fn render(
+    source: &str,
+    fields: &HashMap<String, String>,
+    cloze: Option<usize>,
+    front_side: Option<&str>,
+) -> String {
+    let is_back = front_side.is_some();
+    let value = |field: &str| match field {
+        "FrontSide" => front_side.unwrap_or_default(),
+        _ => fields.get(field).map(String::as_str).unwrap_or_default(),
+    };
+
+    let mut out = String::new();
+    // Whether each enclosing section is shown; text is emitted only when all of them are.
+    let mut sections: Vec<bool> = Vec::new();
+
+    for token in tokenize(source) {
+        let active = sections.iter().all(|s| *s);
+        match token {
+            Token::SectionOpen { field, negated } => {
+                let non_empty = !strip_html(value(field)).trim().is_empty();
+                sections.push(active && non_empty != negated);
+            }
+            Token::SectionClose { .. } => {
+                sections.pop();
+            }
+            Token::Text(text) if active => out.push_str(text),
+            Token::Replacement { field, filters } if active => {
+                let mut text = value(field).to_string();
+                for filter in filters.iter().rev() {
+                    text = match *filter {
+                        "cloze" => render_cloze(&text, cloze.unwrap_or(1), is_back),
+                        "text" => strip_html(&text),
+                        _ => text,
+                    };
+                }
+                out.push_str(&text);
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// Replaces `{{cN::answer::hint}}` deletions, hiding the active one on the front.
+fn render_cloze(text: &str, active: usize, is_back: bool) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{c") {
+        let after = &rest[start + 3..];
+        let digits = after.chars().take_while(|c| c.is_ascii_digit()).count();
+        let body = after[digits..].strip_prefix("::").filter(|_| digits > 0);
+        let Some((body, end)) = body.and_then(|b| b.find("}}").map(|end| (b, end))) else {
+            out.push_str(&rest[..start + 3]);
+            rest = after;
+            continue;
+        };
+        let number: usize = after[..digits].parse().unwrap_or_default();
+        let inner = &body[..end];
+        let (answer, hint) = match inner.split_once("::") {
+            Some((answer, hint)) => (answer, Some(hint)),
+            None => (inner, None),
+        };
+
+        out.push_str(&rest[..start]);
+        match (number == active, is_back) {
+            (true, false) => out.push_str(&format!(
+                "<span class=\"cloze\">[{}]</span>",
+                hint.unwrap_or("...")
+            )),
+            (true, true) => out.push_str(&format!("<span class=\"cloze\">{}</span>", answer)),
+            (false, _) => out.push_str(answer),
+        }
+        rest = &body[end..][2..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn strip_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
@@ -1,7 +1,28 @@
 #[cfg(test)]
 mod tests {
+    use crate::batch::BatchResults;
+    use crate::error::{AnkiError, SyncFailure};
+    use crate::id::{CardId, NoteId};
+    use crate::media::{
+        check_extension, extract_media_refs, infer_filename, parse_data_url, with_hash_suffix,
+        GuessPolicy, MediaSource, SniffPolicy,
+    };
+    use crate::models::{
+        ensure_field_absent, ensure_index, field_position, plan_migration, MigrationStep,
+        ModelSpec, TemplateSpec,
+    };
     use crate::notes::NoteAction;
+    use crate::result::{has_cloze_markup, FullModelDetails};
+    #[cfg(feature = "stats")]
+    use crate::result::{ReviewKind, ReviewRow};
+    #[cfg(feature = "stats")]
+    use crate::stats::{
+        bucket_reviews_by_hour, parse_collection_stats, retention_report, validate_review_row,
+        write_reviews_csv,
+    };
+    use crate::template::{render_card, validate_template, TemplateIssueKind, TemplateSide};
     use crate::AnkiClient;
+    use std::collections::HashMap;
 
     #[tokio::test]
     async fn test_find_newest_notes() {
@@ -9,13 +30,13 @@ mod tests {
         let res = NoteAction::find_note_ids(&client, "is:new").await.unwrap();
 
         // Assert
-        assert_eq!(*res.last().unwrap(), 1717752795958);
+        assert_eq!(*res.last().unwrap(), NoteId(1717752795958));
     }
 
     #[tokio::test]
     async fn fetch_note_info() {
         let client = AnkiClient::default();
-        let res = NoteAction::get_notes_infos(&client, vec![1717752795958])
+        let res = NoteAction::get_notes_infos(&client, vec![NoteId(1717752795958)])
             .await
             .unwrap();
         let word = &res
@@ -28,4 +49,2593 @@ mod tests {
 
         assert_eq!(*word, "筒抜け");
     }
+
+    #[test]
+    fn validate_model_field_ops() {
+        let fields = vec!["Front".to_string(), "Back".to_string()];
+
+        assert_eq!(field_position(&fields, "Basic", "Back").unwrap(), 1);
+        assert!(field_position(&fields, "Basic", "Extra").is_err());
+        assert!(ensure_field_absent(&fields, "Basic", "Front").is_err());
+        assert!(ensure_index(2, fields.len(), "Basic").is_ok());
+        assert!(ensure_index(3, fields.len(), "Basic").is_err());
+    }
+
+    #[test]
+    fn plan_model_migration() {
+        let current: FullModelDetails = serde_json::from_value(serde_json::json!({
+            "id": 1676000000000u64,
+            "name": "Mining",
+            "sortf": 0,
+            "vers": [],
+            "flds": [{ "name": "Word", "ord": 0 }, { "name": "Meaning", "ord": 1 }],
+            "tmpls": [{ "name": "Card 1", "ord": 0, "qfmt": "{{Word}}", "afmt": "{{Meaning}}" }],
+            "css": ".card {}",
+            "type": 0
+        }))
+        .unwrap();
+        let spec = ModelSpec {
+            name: "Mining".to_string(),
+            fields: vec!["Sentence".into(), "Expression".into(), "Meaning".into()],
+            field_renames: vec![("Word".into(), "Expression".into())],
+            templates: vec![TemplateSpec {
+                name: "Card 1".into(),
+                front: "{{Expression}}".into(),
+                back: "{{Meaning}}".into(),
+            }],
+            css: ".card {}".to_string(),
+            is_cloze: false,
+        };
+
+        assert!(!current.is_cloze());
+        assert_eq!(current.sort_field(), Some("Word"));
+        assert!(current.extra.contains_key("vers"));
+        assert!(current.check_cloze_markup(["{{c1::筒抜け}}"]).is_err());
+        assert!(has_cloze_markup("a {{c12::b::hint}} c"));
+        assert!(!has_cloze_markup("{{cloze:Text}}"));
+        assert_eq!(
+            plan_migration(&spec, None),
+            vec![MigrationStep::CreateModel]
+        );
+        assert_eq!(
+            plan_migration(&spec, Some(&current)),
+            vec![
+                MigrationStep::RenameField {
+                    old: "Word".into(),
+                    new: "Expression".into()
+                },
+                MigrationStep::AddField {
+                    name: "Sentence".into()
+                },
+                MigrationStep::RepositionField {
+                    name: "Sentence".into(),
+                    index: 0
+                },
+                MigrationStep::UpdateTemplates {
+                    names: vec!["Card 1".into()]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_template_references() {
+        let fields = vec!["Text".to_string(), "Extra".to_string()];
+        let tmpl = "{{cloze:Text}}{{#Hint}}{{hint:Hint}}{{/Hint}}{{^Extra}}{{FrontSide}}{{/Text}}";
+        let kinds: Vec<TemplateIssueKind> =
+            validate_template("Cloze", TemplateSide::Front, tmpl, &fields)
+                .into_iter()
+                .map(|i| i.kind)
+                .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TemplateIssueKind::UnknownField("Hint".into()),
+                TemplateIssueKind::UnknownField("Hint".into()),
+                TemplateIssueKind::UnexpectedClose("Text".into()),
+                TemplateIssueKind::UnclosedSection("Extra".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_cloze_card_preview() {
+        let fields = HashMap::from([
+            (
+                "Text".to_string(),
+                "{{c1::話::noun}}が{{c2::筒抜け}}だ".to_string(),
+            ),
+            ("Extra".to_string(), String::new()),
+        ]);
+        let preview = render_card(
+            "{{cloze:Text}}{{#Extra}}<hr>{{Extra}}{{/Extra}}",
+            "{{FrontSide}}<br>{{cloze:Text}}{{^Extra}}!{{/Extra}}",
+            &fields,
+            Some(2),
+        );
+
+        assert_eq!(preview.front, "話が<span class=\"cloze\">[...]</span>だ");
+        assert_eq!(
+            preview.back,
+            "話が<span class=\"cloze\">[...]</span>だ<br>話が<span class=\"cloze\">筒抜け</span>だ!"
+        );
+    }
+
+    #[test]
+    fn extract_field_media_refs() {
+        let html = r#"[sound:a b.mp3]<img src="shot%201.webp"><img src='x.png'><img src=https://e.com/y.jpg>"#;
+
+        assert_eq!(
+            extract_media_refs(html),
+            vec!["a b.mp3", "shot 1.webp", "x.png"]
+        );
+    }
+
+    #[test]
+    fn infer_and_hash_media_filenames() {
+        let url = MediaSource::Url("https://example.com/audio/%E7%AD%92.mp3?x=1".into());
+        let bytes = MediaSource::Bytes(vec![1, 2, 3]);
+
+        assert_eq!(infer_filename(&url).unwrap(), "筒.mp3");
+        assert!(infer_filename(&bytes).is_err());
+        assert_eq!(
+            infer_filename(&MediaSource::DataUrl("data:image/png;base64,YWJj".into())).unwrap(),
+            "paste-ba7816bf.png"
+        );
+        assert_eq!(
+            parse_data_url("data:,a%20b").unwrap(),
+            ("text/plain".to_string(), b"a b".to_vec())
+        );
+        assert_eq!(with_hash_suffix("line.mp3", b"abc"), "line-ba7816bf.mp3");
+        assert_eq!(with_hash_suffix("noext", b"abc"), "noext-ba7816bf");
+    }
+
+    #[test]
+    fn sniff_media_extensions() {
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A];
+        let html = b"<!DOCTYPE html><title>404</title>";
+
+        assert_eq!(
+            check_extension("a.PNG", &png, SniffPolicy::Reject).unwrap(),
+            "a.PNG"
+        );
+        assert_eq!(
+            check_extension("a.jpg", &png, SniffPolicy::FixExtension).unwrap(),
+            "a.png"
+        );
+        assert!(check_extension("a.mp3", html, SniffPolicy::FixExtension).is_err());
+        assert_eq!(
+            check_extension("a.txt", html, SniffPolicy::Reject).unwrap(),
+            "a.txt"
+        );
+    }
+
+    #[test]
+    fn strict_media_source_constructors() {
+        assert!(MediaSource::url("ftp://example.com/a.mp3").is_err());
+        assert!(MediaSource::bytes(Vec::new()).is_err());
+        assert!(MediaSource::try_from("not/there.mp3").is_err());
+        assert!(matches!(
+            MediaSource::guess("not/there.mp3", GuessPolicy::Lenient),
+            Ok(MediaSource::Path(_))
+        ));
+        assert!(matches!(
+            MediaSource::try_from("HTTPS://example.com/a.mp3"),
+            Ok(MediaSource::Url(_))
+        ));
+        assert!(matches!(
+            MediaSource::try_from("Cargo.toml"),
+            Ok(MediaSource::Path(p)) if p.is_absolute()
+        ));
+    }
+
+    #[test]
+    fn classify_sync_failures() {
+        assert_eq!(
+            SyncFailure::from_message("sync: auth not configured"),
+            SyncFailure::AuthRequired
+        );
+        assert!(SyncFailure::from_message("Full sync required").needs_user_attention());
+        assert!(SyncFailure::from_message("Connection timed out").is_transient());
+        assert!(matches!(
+            SyncFailure::from_message("collection is locked"),
+            SyncFailure::Other(_)
+        ));
+    }
+
+    #[test]
+    fn classify_anki_connect_errors() {
+        let error = |m: &str| AnkiError::from_message(m.to_string());
+        assert!(matches!(
+            error("deck was not found: Mining::Anime"),
+            AnkiError::DeckNotFound(d) if d == "Mining::Anime"
+        ));
+        assert!(matches!(
+            error("model was not found: Basic"),
+            AnkiError::ModelNotFound(m) if m == "Basic"
+        ));
+        assert!(matches!(
+            error("cannot create note because it is a duplicate"),
+            AnkiError::DuplicateNote
+        ));
+        assert!(matches!(
+            error("cannot create note because it is empty"),
+            AnkiError::EmptyNote
+        ));
+        assert!(matches!(
+            error("valid api key must be provided"),
+            AnkiError::PermissionDenied
+        ));
+        assert!(matches!(
+            error("unsupported action"),
+            AnkiError::RequestError(e) if e == "unsupported action"
+        ));
+    }
+
+    #[test]
+    fn batch_multi_payload_and_results() {
+        let client = AnkiClient::default();
+        let mut batch = client.batch();
+        let notes = batch.find_notes("is:new");
+        let cards = batch.find_cards("is:due");
+        let tags = batch.action::<()>("addTags", None);
+
+        let payload = batch.payload();
+        assert_eq!(payload["action"], "multi");
+        assert_eq!(payload["params"]["actions"][1]["action"], "findCards");
+        assert_eq!(payload["params"]["actions"][1]["params"]["query"], "is:due");
+        assert!(payload["params"]["actions"][2].get("params").is_none());
+
+        let raw = serde_json::json!([
+            { "result": [1, 2], "error": null },
+            { "result": null, "error": "collection is not available" },
+            { "result": null, "error": null },
+        ]);
+        let mut results = BatchResults::new(serde_json::from_value(raw).unwrap());
+        assert_eq!(results.take(notes).unwrap(), vec![NoteId(1), NoteId(2)]);
+        assert!(matches!(
+            results.take(cards),
+            Err(AnkiError::CollectionUnavailable)
+        ));
+        assert!(results.take_unit(tags).is_ok());
+        assert_eq!(results.remaining(), 0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn parse_collection_stats_headlines() {
+        let html = "<div>Studied <b>40 cards</b> in <b>7.4 minutes</b> today \
+            (11.09s/card).</div><div>Correct: <b>85.00%</b> (34 of 40)</div>\
+            <table><tr><td>Mature</td><td align=right>1,234</td></tr>\
+            <tr><td>Total</td><td align=right>3,400</td></tr></table>";
+        let stats = parse_collection_stats(html);
+        assert_eq!(stats.total_cards, Some(3400));
+        assert_eq!(stats.mature_cards, Some(1234));
+        assert_eq!(stats.retention, Some(85.0));
+        assert_eq!(stats.average_answer_seconds, Some(11.09));
+        assert_eq!(parse_collection_stats("<p>No cards.</p>").total_cards, None);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn review_rows_round_trip_as_tuples() {
+        let raw = serde_json::json!([
+            1594194095746u64,
+            1485369733217u64,
+            -1,
+            3,
+            4,
+            -60,
+            2500,
+            6157,
+            0
+        ]);
+        let row: ReviewRow = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(row.card_id, CardId(1485369733217));
+        assert_eq!(row.last_ivl, -60);
+        assert_eq!(row.review_type, ReviewKind::Learn);
+        assert_eq!(serde_json::to_value(row).unwrap(), raw);
+
+        assert!(validate_review_row(&row).is_ok());
+        let seconds = ReviewRow {
+            review_id: 1594194095,
+            ..row
+        };
+        assert!(validate_review_row(&seconds).is_err());
+        let manual = ReviewRow { ease: 0, ..row };
+        assert!(validate_review_row(&manual).is_err());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn compute_retention_report() {
+        let row = |ease, last_ivl, time, review_type| ReviewRow {
+            review_id: 1594194095746,
+            card_id: CardId(1485369733217),
+            usn: -1,
+            ease,
+            ivl: 1,
+            last_ivl,
+            factor: 2500,
+            time,
+            review_type,
+        };
+        let rows = [
+            row(3, 30, 4000, ReviewKind::Review),
+            row(1, 40, 6000, ReviewKind::Review),
+            row(3, 5, 2000, ReviewKind::Review),
+            row(1, -60, 8000, ReviewKind::Learn),
+            row(0, 0, 0, ReviewKind::Manual),
+        ];
+        let report = retention_report(&rows);
+        assert_eq!(report.total_reviews, 4);
+        assert_eq!(report.mature.again_rate(), Some(0.5));
+        assert_eq!(report.young.retention(), Some(1.0));
+        assert!((report.true_retention().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(report.average_answer_seconds, Some(5.0));
+        assert_eq!(retention_report(&[]).true_retention(), None);
+
+        let mut csv = Vec::new();
+        write_reviews_csv(&rows[..1], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "review_id,card_id,usn,ease,ivl,last_ivl,factor,time,review_type\n\
+             1594194095746,1485369733217,-1,3,1,30,2500,4000,1\n"
+        );
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn bucket_reviews_into_hours() {
+        let at = |review_id| ReviewRow {
+            review_id,
+            card_id: CardId(1485369733217),
+            usn: -1,
+            ease: 3,
+            ivl: 1,
+            last_ivl: 1,
+            factor: 2500,
+            time: 1000,
+            review_type: ReviewKind::Review,
+        };
+        // 2020-07-08 07:41 UTC, twice, and 2020-07-09 00:00 UTC.
+        let rows = [at(1594194095746), at(1594194100000), at(1594252800000)];
+        let hours = bucket_reviews_by_hour(&rows, &chrono::Utc);
+        let day = |d| chrono::NaiveDate::from_ymd_opt(2020, 7, d).unwrap();
+        assert_eq!(hours[&day(8)][7], 2);
+        assert_eq!(hours[&day(9)][0], 1);
+        assert_eq!(hours.len(), 2);
+    }
+
+    #[test]
+    fn action_min_versions() {
+        assert_eq!(crate::version::min_version("notesModTime"), Some(6));
+        assert_eq!(crate::version::min_version("findNotes"), None);
+
+        use crate::action::Action;
+        assert_eq!(Action::from("findNotes"), Action::FindNotes);
+        assert_eq!(
+            Action::from("guiFutureThing"),
+            Action::Custom("guiFutureThing".to_string())
+        );
+        assert_eq!(Action::GetLatestReviewId.min_version(), Some(6));
+        assert_eq!(
+            serde_json::to_value(Action::GetCollectionStatsHtml).unwrap(),
+            "getCollectionStatsHTML"
+        );
+        let action: Action = serde_json::from_value("notesInfo".into()).unwrap();
+        assert_eq!(action, Action::NotesInfo);
+        assert!(Action::ALL.iter().all(|a| Action::from(a.name()) == *a));
+
+        use crate::version::{parse_api_version, ApiVersionInfo};
+        assert_eq!(parse_api_version("AnkiConnect v.6"), Some(6));
+        assert_eq!(parse_api_version("AnkiConnect"), None);
+        let info: ApiVersionInfo = serde_json::from_str(r#"{"api_version": 5}"#).unwrap();
+        assert_eq!(info.version(), Some(5));
+        let info: ApiVersionInfo = serde_json::from_str("{}").unwrap();
+        assert_eq!(info.version(), None);
+    }
+
+    #[test]
+    fn custom_endpoints() {
+        let client = AnkiClient::new_endpoint("https://anki.my-lan:8765").unwrap();
+        assert_eq!(client.endpoint, "https://anki.my-lan:8765/");
+        assert_eq!(client.format_url("9000"), "https://anki.my-lan:9000");
+        assert_eq!(AnkiClient::new("8765", 6).endpoint, "http://localhost:8765");
+        assert!(AnkiClient::new_endpoint("ftp://anki.my-lan").is_err());
+        assert!(AnkiClient::new_endpoint("localhost:8765").is_err());
+
+        let built = AnkiClient::builder()
+            .endpoint("http://10.0.0.2:8765")
+            .version(5)
+            .timeout(std::time::Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(
+            (built.endpoint.as_str(), built.version),
+            ("http://10.0.0.2:8765/", 5)
+        );
+        assert!(AnkiClient::builder()
+            .client(reqwest::Client::new())
+            .user_agent("x")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn client_from_toml_config() {
+        let config = crate::config::AnkiConfig::from_toml(
+            "url = \"http://192.168.1.20:8765\"\nkey = \"secret\"\ntimeout_secs = 2.5\n",
+        )
+        .unwrap();
+        assert_eq!(config.timeout_secs, Some(2.5));
+        let client = config.into_builder().unwrap().build().unwrap();
+        assert_eq!(client.endpoint, "http://192.168.1.20:8765/");
+        assert_eq!(client.api_key.as_deref(), Some("secret"));
+        assert!(crate::config::AnkiConfig::from_toml("port = 8765").is_err());
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_spaces_requests() {
+        let limiter = crate::limit::Limiter::new(crate::limit::RateLimit {
+            max_in_flight: 1,
+            min_interval: std::time::Duration::from_millis(30),
+        });
+        let start = std::time::Instant::now();
+        drop(limiter.acquire().await);
+        drop(limiter.acquire().await);
+        drop(limiter.acquire().await);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(60));
+    }
+
+    #[test]
+    fn record_action_metrics() {
+        use crate::metrics::MetricsRecorder;
+        use crate::middleware::Middleware;
+        use std::time::Duration;
+
+        let recorder = MetricsRecorder::default();
+        let ok = serde_json::json!({ "result": 6, "error": null });
+        let failed = serde_json::json!({ "result": null, "error": "unsupported action" });
+        recorder.after("version", Ok(&ok), Duration::from_millis(3));
+        recorder.after("version", Ok(&failed), Duration::from_millis(300));
+        recorder.after(
+            "findNotes",
+            Err(&AnkiError::NoDataFound),
+            Duration::from_secs(20),
+        );
+
+        let snapshot = recorder.snapshot();
+        let version = &snapshot["version"];
+        assert_eq!((version.requests, version.errors), (2, 1));
+        assert_eq!(version.latency_histogram[0], 1);
+        assert_eq!(version.latency_histogram[6], 1);
+        assert_eq!(version.max_latency, Duration::from_millis(300));
+        assert_eq!(snapshot["findNotes"].latency_histogram[11], 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_token_stops_operations() {
+        let token = crate::cancel::CancelToken::new();
+        let never = std::future::pending::<Result<(), AnkiError>>();
+        let canceller = token.clone();
+        tokio::spawn(async move { canceller.cancel() });
+        assert!(matches!(token.run(never).await, Err(AnkiError::Cancelled)));
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn custom_transport_answers_actions() {
+        use crate::transport::{AnkiTransport, TransportFuture};
+
+        struct Canned;
+        impl AnkiTransport for Canned {
+            fn call<'a>(&'a self, request: &'a serde_json::Value) -> TransportFuture<'a> {
+                let body = match request["action"].as_str() {
+                    Some("findNotes") => serde_json::json!({ "result": [7, 8], "error": null }),
+                    _ => serde_json::json!({ "result": null, "error": "unsupported action" }),
+                };
+                Box::pin(async move { Ok(body) })
+            }
+        }
+
+        let client = AnkiClient::default().with_transport(Canned);
+        let ids = NoteAction::find_note_ids(&client, "deck:Mining")
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![NoteId(7), NoteId(8)]);
+        assert!(matches!(
+            NoteAction::get_notes_infos(&client, ids).await,
+            Err(AnkiError::RequestError(_))
+        ));
+    }
+
+    #[test]
+    fn dry_run_intercepts_mutating_actions() {
+        use crate::dry_run::{is_mutating, DryRunPlan};
+
+        assert!(is_mutating("addNotes"));
+        assert!(is_mutating("storeMediaFile"));
+        assert!(is_mutating("modelFieldRename"));
+        for action in [
+            "sync",
+            "suspend",
+            "unsuspend",
+            "findAndReplaceInModels",
+            "loadProfile",
+            "guiExitAnki",
+            "exportPackage",
+        ] {
+            assert!(is_mutating(action), "{}", action);
+        }
+        assert!(!is_mutating("findNotes"));
+        assert!(!is_mutating("areSuspended"));
+        assert!(!is_mutating("settings"));
+
+        let plan = DryRunPlan::default();
+        let read = serde_json::json!({ "action": "findNotes", "params": { "query": "" } });
+        assert!(plan.intercept(&read).is_none());
+
+        let data = "A".repeat(500);
+        let store = serde_json::json!({
+            "action": "storeMediaFile",
+            "params": { "filename": "a.mp3", "data": data },
+        });
+        assert!(plan.intercept(&store).is_some());
+        let multi = serde_json::json!({
+            "action": "multi",
+            "params": { "actions": [read, { "action": "deleteNotes", "params": { "notes": [1] } }] },
+        });
+        let body = plan.intercept(&multi).unwrap();
+        assert_eq!(body["result"].as_array().unwrap().len(), 2);
+
+        let actions = plan.actions();
+        assert_eq!(actions.len(), 2);
+        assert!(actions[0].params["data"]
+            .as_str()
+            .unwrap()
+            .ends_with("(500 chars)"));
+        assert_eq!(actions[1].action, "deleteNotes");
+    }
+
+    #[tokio::test]
+    async fn record_and_replay_fixtures() {
+        use crate::fixture::{RecordingTransport, ReplayTransport};
+        use crate::transport::{AnkiTransport, TransportFuture};
+
+        struct Canned;
+        impl AnkiTransport for Canned {
+            fn call<'a>(&'a self, request: &'a serde_json::Value) -> TransportFuture<'a> {
+                let query = request["params"]["query"].as_str().unwrap_or_default();
+                let ids = if query == "deck:Mining" {
+                    vec![7, 8]
+                } else {
+                    vec![9]
+                };
+                Box::pin(async move { Ok(serde_json::json!({ "result": ids, "error": null })) })
+            }
+        }
+
+        let path = std::env::temp_dir().join("anki_direct_fixture_test.json");
+        let recording = AnkiClient::default()
+            .with_transport(Canned)
+            .record_to(&path);
+        let mut client = recording.clone();
+        client.api_key = Some("secret".to_string());
+        NoteAction::find_note_ids(&client, "deck:Mining")
+            .await
+            .unwrap();
+        NoteAction::find_note_ids(&client, "deck:Other")
+            .await
+            .unwrap();
+        let fixture = std::fs::read_to_string(&path).unwrap();
+        assert!(!fixture.contains("secret"));
+
+        let replay = ReplayTransport::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let client = AnkiClient::default().with_transport(replay);
+        let ids = NoteAction::find_note_ids(&client, "deck:Mining")
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![NoteId(7), NoteId(8)]);
+        assert!(matches!(
+            NoteAction::find_note_ids(&client, "deck:Mining").await,
+            Err(AnkiError::RequestError(_))
+        ));
+
+        let direct =
+            RecordingTransport::new(std::env::temp_dir().join("anki_direct_unused.json"), Canned);
+        assert!(direct.exchanges().is_empty());
+    }
+
+    #[tokio::test]
+    async fn mock_backend_answers_expected_actions() {
+        use crate::mock::MockBackend;
+
+        let mock = MockBackend::new();
+        mock.expect("addNotes").respond(vec![Some(123), None]);
+        mock.expect("findNotes")
+            .with_params(serde_json::json!({ "query": "deck:Mining" }))
+            .respond(vec![1, 2]);
+        mock.expect("notesInfo").fail("collection is not available");
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut batch = client.batch();
+        let added = batch.action::<Vec<Option<u128>>>("addNotes", None);
+        let found = batch.find_notes("deck:Mining");
+        let mut results = batch.send().await.unwrap();
+        assert_eq!(results.take(added).unwrap(), vec![Some(123), None]);
+        assert_eq!(results.take(found).unwrap(), vec![NoteId(1), NoteId(2)]);
+
+        assert!(matches!(
+            NoteAction::get_notes_infos(&client, vec![NoteId(1)]).await,
+            Err(AnkiError::CollectionUnavailable)
+        ));
+        assert!(NoteAction::find_note_ids(&client, "deck:Mining")
+            .await
+            .is_err());
+        assert_eq!(mock.requests().len(), 4);
+        mock.assert_done();
+
+        let mock = MockBackend::new();
+        mock.expect("findNotes").respond(vec![1]);
+        mock.expect("findNotes").respond(vec![2]);
+        let client = AnkiClient::new("8765", 5).with_transport(mock.clone());
+        NoteAction::find_note_ids(&client, "").await.unwrap();
+        NoteAction::find_note_ids(&client.at_version(6), "")
+            .await
+            .unwrap();
+        let versions: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r["version"].clone())
+            .collect();
+        assert_eq!(versions, vec![5, 6]);
+        assert_eq!(client.version, 5);
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn cache_expires_and_persists() {
+        use crate::cache::{Cache, CacheError, CacheFormat, CacheSection};
+        use crate::mock::MockBackend;
+        use std::time::Duration;
+
+        let mock = MockBackend::new();
+        mock.expect("modelNames").respond(vec!["Basic"]);
+        mock.expect("findModelsByName").respond(serde_json::json!([{
+            "id": 1, "name": "Basic", "flds": [], "tmpls": [], "css": "", "type": 0, "sortf": 0
+        }]));
+        mock.expect("deckNamesAndIds")
+            .respond(serde_json::json!({ "Default": 1, "Mining": 2 }));
+        mock.expect("getTags")
+            .respond(vec!["Mining::Anime", "mining::book", "Leech"]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let path = std::env::temp_dir().join("anki_direct_cache_test.json");
+        let mut cache = Cache::new()
+            .with_ttl(CacheSection::Decks, Duration::ZERO)
+            .auto_save(&path, CacheFormat::PrettyJson);
+        assert_eq!(
+            cache.decks.contains("Mining").unwrap_err(),
+            CacheError::Dehydrated(CacheSection::Decks)
+        );
+        let refreshed = cache.refresh_expired(&client).await.unwrap();
+        assert_eq!(refreshed, CacheSection::ALL);
+        assert!(cache.tags.contains("leech").unwrap());
+        assert_eq!(
+            cache.tags.normalize("MINING::ANIME").unwrap(),
+            Some("Mining::Anime")
+        );
+        assert_eq!(
+            cache.tags.with_prefix("mining::").unwrap(),
+            vec!["Mining::Anime", "mining::book"]
+        );
+        assert!(cache.decks.contains("Mining").unwrap());
+        assert!(!cache.is_expired(CacheSection::Models));
+        assert!(cache.is_expired(CacheSection::Decks));
+        drop(cache);
+
+        let cache = Cache::load(&path, CacheFormat::Json).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            cache.models.get("Basic").unwrap().unwrap().id,
+            crate::id::ModelId(1)
+        );
+        assert!(cache.is_expired(CacheSection::Decks));
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn note_cache_refreshes_incrementally() {
+        use crate::cache::{NoteCache, NoteChanges};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let note =
+            |id: u128| json!({ "noteId": id, "modelName": "Basic", "tags": [], "fields": {} });
+        let mod_time = |id: u128, modified: u64| json!({ "noteId": id, "mod": modified });
+        let mock = MockBackend::new();
+        mock.expect("findNotes").respond(vec![1, 2]);
+        mock.expect("notesModTime")
+            .respond(vec![mod_time(1, 10), mod_time(2, 10)]);
+        mock.expect("notesInfo").respond(vec![note(1), note(2)]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut cache = NoteCache::new("deck:Mining");
+        cache.hydrate(&client).await.unwrap();
+        assert_eq!(cache.ids().collect::<Vec<_>>(), vec![NoteId(1), NoteId(2)]);
+
+        mock.expect("findNotes")
+            .with_params(json!({ "query": "deck:Mining" }))
+            .respond(vec![1, 3]);
+        mock.expect("findNotes")
+            .with_params(json!({ "query": "(deck:Mining) edited:1" }))
+            .respond(vec![1]);
+        mock.expect("notesModTime")
+            .with_params(json!({ "notes": [1, 3] }))
+            .respond(vec![mod_time(1, 20), mod_time(3, 5)]);
+        mock.expect("notesModTime")
+            .respond(vec![mod_time(1, 20), mod_time(3, 5)]);
+        mock.expect("notesInfo")
+            .with_params(json!({ "notes": [1, 3] }))
+            .respond(vec![note(1), note(3)]);
+        let changes = cache.refresh(&client).await.unwrap();
+        assert_eq!(
+            changes,
+            NoteChanges {
+                updated: vec![NoteId(1), NoteId(3)],
+                removed: vec![NoteId(2)],
+            }
+        );
+        assert_eq!(cache.ids().collect::<Vec<_>>(), vec![NoteId(1), NoteId(3)]);
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn card_cache_tracks_stale_cards() {
+        use crate::cache::CardCache;
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let card = |id: u128, lapses: u32, modified: u64| {
+            json!({
+                "cardId": id, "note": 1, "deckName": "Mining", "modelName": "Basic",
+                "question": "", "answer": "", "fields": {}, "fieldOrder": 0, "css": "",
+                "ord": 0, "type": 2, "queue": 2, "due": 0, "interval": 3, "factor": 2500,
+                "reps": 5, "lapses": lapses, "left": 0, "mod": modified,
+            })
+        };
+        let mock = MockBackend::new();
+        mock.expect("findCards").respond(vec![1, 2, 3]);
+        mock.expect("cardsInfo")
+            .respond(vec![card(1, 0, 10), card(2, 0, 10), card(3, 0, 10)]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut cache = CardCache::new();
+        cache.hydrate(&client, "tag:leech").await.unwrap();
+        assert_eq!(cache.len(), 3);
+
+        mock.expect("cardsModTime")
+            .respond(json!([{ "cardId": 1, "mod": 10 }, { "cardId": 2, "mod": 20 }]));
+        mock.expect("cardsInfo")
+            .with_params(json!({ "cards": [2] }))
+            .respond(vec![card(2, 1, 20)]);
+        assert_eq!(
+            cache.refresh_stale(&client).await.unwrap(),
+            vec![CardId(2), CardId(3)]
+        );
+        assert_eq!(cache.get(CardId(2)).unwrap().lapses, 1);
+        assert!(cache.get(CardId(3)).is_none());
+        assert_eq!(cache.query(), Some("tag:leech"));
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn deck_cache_hydrates_configs() {
+        use crate::cache::DeckCache;
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Default": 1, "Mining": 2 }));
+        mock.expect("getDeckConfig")
+            .with_params(json!({ "deck": "Default" }))
+            .respond(json!({ "id": 1, "name": "Default", "maxTaken": 60 }));
+        mock.expect("getDeckConfig")
+            .with_params(json!({ "deck": "Mining" }))
+            .respond(json!({ "id": 2, "name": "Sentences" }));
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut decks = DeckCache::default();
+        decks.hydrate_full(&client).await.unwrap();
+        assert_eq!(decks.config("Mining").unwrap().unwrap().name, "Sentences");
+        assert_eq!(
+            decks.config("Default").unwrap().unwrap().extra["maxTaken"],
+            60
+        );
+        assert_eq!(mock.requests()[1]["action"], "getDeckConfig");
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn cache_hydrates_all_sections() {
+        use crate::cache::{Cache, CacheError, CacheSection};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let error = CacheError::Dehydrated(CacheSection::Models).to_string();
+        assert!(error.contains("Cache::hydrate_all"));
+
+        for concurrent in [false, true] {
+            let mock = MockBackend::new();
+            mock.expect("modelNames").respond(Vec::<String>::new());
+            mock.expect("findModelsByName")
+                .respond(Vec::<serde_json::Value>::new());
+            mock.expect("deckNamesAndIds")
+                .respond(json!({ "Default": 1 }));
+            mock.expect("getDeckConfig")
+                .respond(json!({ "id": 1, "name": "Default" }));
+            mock.expect("getTags").respond(vec!["leech"]);
+            let client = AnkiClient::default().with_transport(mock.clone());
+
+            let mut cache = Cache::new();
+            cache.hydrate_all(&client, concurrent).await.unwrap();
+            assert!(CacheSection::ALL.iter().all(|s| !cache.is_expired(*s)));
+            assert!(cache.decks.config("Default").unwrap().is_some());
+            if concurrent {
+                mock.expect("modelNames").respond(Vec::<String>::new());
+                mock.expect("findModelsByName")
+                    .respond(Vec::<serde_json::Value>::new());
+                mock.expect("deckNamesAndIds").respond(json!({}));
+                mock.expect("getTags").respond(Vec::<String>::new());
+                let report = cache.warm_up(&client).await.unwrap();
+                assert_eq!(report.sections.len(), CacheSection::ALL.len());
+                assert!(report.sections.values().all(|took| *took <= report.total));
+                assert!(!cache.tags.contains("leech").unwrap());
+            }
+            mock.assert_done();
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn mutations_expire_cache_sections() {
+        use crate::cache::{sections_changed_by, Cache, CacheSection};
+        use crate::mock::MockBackend;
+
+        assert_eq!(
+            sections_changed_by("modelFieldRename"),
+            [CacheSection::Models]
+        );
+        assert!(sections_changed_by("modelFieldNames").is_empty());
+        for read_only in [
+            "modelFieldFonts",
+            "modelFieldsOnTemplates",
+            "modelTemplates",
+        ] {
+            assert!(sections_changed_by(read_only).is_empty(), "{}", read_only);
+        }
+        assert_eq!(
+            sections_changed_by("modelTemplateRename"),
+            [CacheSection::Models]
+        );
+        assert!(sections_changed_by("findNotes").is_empty());
+        assert_eq!(sections_changed_by("cardReviews"), [CacheSection::Decks]);
+        assert_eq!(sections_changed_by("importPackage"), CacheSection::ALL);
+
+        let mock = MockBackend::new();
+        mock.expect("getTags").respond(vec!["leech"]);
+        mock.expect("deckNamesAndIds")
+            .respond(serde_json::json!({ "Default": 1 }));
+        mock.expect("addTags").respond(());
+        mock.expect("createDeck").respond(2);
+        let mut cache = Cache::new();
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_cache_invalidation(cache.invalidation());
+        cache.hydrate(&client, CacheSection::Tags).await.unwrap();
+        cache.hydrate(&client, CacheSection::Decks).await.unwrap();
+        assert!(!cache.is_expired(CacheSection::Tags));
+
+        let mut batch = client.batch();
+        batch.action::<()>("addTags", None);
+        batch.action::<u128>("createDeck", None);
+        batch.send().await.unwrap();
+        assert!(cache.is_expired(CacheSection::Tags));
+        assert!(cache.is_expired(CacheSection::Decks));
+
+        mock.expect("deckNamesAndIds")
+            .respond(serde_json::json!({ "Default": 1 }));
+        mock.expect("changeDeck").respond(());
+        cache.hydrate(&client, CacheSection::Decks).await.unwrap();
+        assert!(!cache.is_expired(CacheSection::Decks));
+        let mut batch = client.batch();
+        batch.action::<()>("changeDeck", None);
+        batch.send().await.unwrap();
+        assert!(cache.is_expired(CacheSection::Decks));
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn cache_detects_stale_sections() {
+        use crate::cache::{Cache, CacheSection};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Mining": 2, "Default": 1 }));
+        mock.expect("getTags").respond(vec!["leech"]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+        let mut cache = Cache::new();
+        cache.hydrate(&client, CacheSection::Decks).await.unwrap();
+        cache.hydrate(&client, CacheSection::Tags).await.unwrap();
+
+        mock.expect("modelNames").respond(vec!["Basic"]);
+        mock.expect("deckNames").respond(vec!["Default", "Mining"]);
+        mock.expect("findNotes").respond(vec![1, 2]);
+        mock.expect("notesModTime")
+            .respond(json!([{ "noteId": 1, "mod": 0 }, { "noteId": 2, "mod": u32::MAX }]));
+        let stale = cache.is_stale(&client).await.unwrap();
+        assert_eq!(stale, vec![CacheSection::Models, CacheSection::Tags]);
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn cache_hydrate_reports_diff() {
+        use crate::cache::{Cache, CacheSection};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let model = |name: &str, fields: &[&str]| {
+            let flds: Vec<_> = fields
+                .iter()
+                .enumerate()
+                .map(|(ord, f)| json!({ "name": f, "ord": ord }))
+                .collect();
+            json!({ "id": 1, "name": name, "flds": flds, "tmpls": [], "css": "", "type": 0, "sortf": 0 })
+        };
+        let mock = MockBackend::new();
+        mock.expect("modelNames").respond(vec!["Basic", "Cloze"]);
+        mock.expect("findModelsByName").respond(json!([
+            model("Basic", &["Front", "Back"]),
+            model("Cloze", &["Text"])
+        ]));
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Default": 1, "Mining": 2 }));
+        let client = AnkiClient::default().with_transport(mock.clone());
+        let mut cache = Cache::new();
+        let diff = cache.hydrate(&client, CacheSection::Models).await.unwrap();
+        assert_eq!(diff.models.added, ["Basic", "Cloze"]);
+        cache.hydrate(&client, CacheSection::Decks).await.unwrap();
+
+        mock.expect("modelNames").respond(vec!["Basic", "Sentence"]);
+        mock.expect("findModelsByName").respond(json!([
+            model("Basic", &["Front"]),
+            model("Sentence", &["Text"])
+        ]));
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Default": 1, "Mining": 3 }));
+        mock.expect("getTags").respond(vec!["leech"]);
+        let diff = cache.update_all(&client).await.unwrap();
+        assert_eq!(diff.models.added, ["Sentence"]);
+        assert_eq!(diff.models.removed, ["Cloze"]);
+        assert_eq!(diff.models.changed, ["Basic"]);
+        assert_eq!(diff.removed_fields["Basic"], ["Back"]);
+        assert_eq!(diff.decks.changed, ["Mining"]);
+        assert_eq!(diff.tags.added, ["leech"]);
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn bounded_note_cache_evicts_least_recently_used() {
+        use crate::cache::{CacheCapacity, NoteCache};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let note =
+            |id: u128| json!({ "noteId": id, "modelName": "Basic", "tags": [], "fields": {} });
+        let mod_time = |id: u128| json!({ "noteId": id, "mod": 10 });
+        let mock = MockBackend::new();
+        mock.expect("findNotes").respond(vec![1, 2, 3]);
+        mock.expect("notesModTime")
+            .respond(vec![mod_time(1), mod_time(2), mod_time(3)]);
+        mock.expect("notesInfo")
+            .respond(vec![note(1), note(2), note(3)]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut cache = NoteCache::new("deck:Mining").with_capacity(CacheCapacity::Entries(2));
+        cache.hydrate(&client).await.unwrap();
+        assert_eq!(cache.ids().collect::<Vec<_>>(), vec![NoteId(2), NoteId(3)]);
+
+        assert!(cache.get(NoteId(2)).is_some());
+        mock.expect("notesModTime").respond(vec![mod_time(1)]);
+        mock.expect("notesInfo").respond(vec![note(1)]);
+        cache.get_or_fetch(&client, NoteId(1)).await.unwrap();
+        assert_eq!(cache.ids().collect::<Vec<_>>(), vec![NoteId(1), NoteId(2)]);
+
+        // Evicted notes that still match aren't fetched again on refresh.
+        mock.expect("findNotes")
+            .with_params(json!({ "query": "deck:Mining" }))
+            .respond(vec![1, 2, 3]);
+        mock.expect("findNotes").respond(vec![3]);
+        let changes = cache.refresh(&client).await.unwrap();
+        assert!(changes.is_empty());
+        mock.assert_done();
+
+        let cache = NoteCache::new("").with_capacity(CacheCapacity::Bytes(0));
+        assert!(cache.is_empty());
+    }
+
+    #[cfg(feature = "cache-cbor")]
+    #[tokio::test]
+    async fn cache_round_trips_as_cbor() {
+        use crate::cache::{Cache, CacheFormat, CacheSection};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        mock.expect("modelNames").respond(vec!["Basic"]);
+        mock.expect("findModelsByName").respond(json!([{
+            "id": 1_700_000_000_000u64, "name": "Basic", "flds": [], "tmpls": [], "css": "",
+            "type": 0, "sortf": 0, "tags": ["x"], "req": [[0, "any", [0]]]
+        }]));
+        let client = AnkiClient::default().with_transport(mock.clone());
+        let mut cache = Cache::new();
+        cache.hydrate(&client, CacheSection::Models).await.unwrap();
+
+        let path = std::env::temp_dir().join("anki_direct_cache_test.cbor");
+        cache.save(&path, CacheFormat::Cbor).unwrap();
+        let json_len = serde_json::to_vec(&cache).unwrap().len();
+        assert!(std::fs::metadata(&path).unwrap().len() < json_len as u64);
+        let loaded = Cache::load(&path, CacheFormat::Cbor).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let model = loaded.models.get("Basic").unwrap().unwrap();
+        assert_eq!(model.id, crate::id::ModelId(1_700_000_000_000));
+        assert_eq!(model.extra["req"], json!([[0, "any", [0]]]));
+    }
+
+    #[test]
+    fn queries_render_escaped_terms() {
+        use crate::query::{AnkiQuery, CardState};
+
+        assert_eq!(
+            AnkiQuery::Is(CardState::Suspended).to_string(),
+            "is:suspended"
+        );
+        assert_eq!(AnkiQuery::Deck("Mining".into()).to_string(), "deck:Mining");
+        assert_eq!(
+            AnkiQuery::Deck("Core 2k (Kana)".into()).to_string(),
+            r#""deck:Core 2k (Kana)""#
+        );
+        assert_eq!(
+            AnkiQuery::Tag("jlpt_n5".into()).to_string(),
+            r"tag:jlpt\_n5"
+        );
+        assert_eq!(
+            AnkiQuery::Note("Basic (and reversed card)".into()).to_string(),
+            r#""note:Basic (and reversed card)""#
+        );
+        assert_eq!(
+            AnkiQuery::Field {
+                name: "Sentence".into(),
+                value: r#"He said "hi""#.into()
+            }
+            .to_string(),
+            r#""Sentence:He said \"hi\"""#
+        );
+        assert_eq!(
+            AnkiQuery::Field {
+                name: "a:b".into(),
+                value: "-1".into()
+            }
+            .to_string(),
+            r"a\:b:-1"
+        );
+        assert_eq!(
+            AnkiQuery::Raw("is:due -is:new".into()).to_string(),
+            "is:due -is:new"
+        );
+    }
+
+    #[test]
+    fn queries_combine_with_parentheses() {
+        use crate::query::{AnkiQuery, CardState};
+
+        let tag = |name: &str| AnkiQuery::Tag(name.into());
+        assert_eq!(
+            (tag("a") | tag("b") | tag("c")).to_string(),
+            "tag:a or tag:b or tag:c"
+        );
+        assert_eq!(
+            ((tag("a") & tag("b")) | !(tag("c") | tag("d"))).to_string(),
+            "(tag:a tag:b) or -(tag:c or tag:d)"
+        );
+        assert_eq!(
+            (AnkiQuery::Raw("a or b".into()) & tag("c")).to_string(),
+            "(a or b) tag:c"
+        );
+        assert_eq!(
+            AnkiQuery::Group(Box::new(!AnkiQuery::Is(CardState::New))).to_string(),
+            "(-is:new)"
+        );
+        assert_eq!(AnkiQuery::And(vec![tag("a")]).to_string(), "tag:a");
+        assert_eq!(AnkiQuery::And(Vec::new()).to_string(), "deck:*");
+        assert_eq!((!AnkiQuery::And(Vec::new())).to_string(), "-deck:*");
+        assert_eq!(AnkiQuery::Or(Vec::new()).to_string(), "-deck:*");
+        assert_eq!((!AnkiQuery::Or(Vec::new())).to_string(), "-(-deck:*)");
+        assert_eq!(
+            (tag("a") | AnkiQuery::Or(Vec::new())).to_string(),
+            "tag:a or (-deck:*)"
+        );
+        assert_eq!(
+            (!AnkiQuery::And(vec![tag("a") | tag("b")])).to_string(),
+            "-(tag:a or tag:b)"
+        );
+        assert_eq!(
+            (!AnkiQuery::Or(vec![AnkiQuery::Raw("a or b".into())])).to_string(),
+            "-(a or b)"
+        );
+    }
+
+    #[test]
+    fn queries_render_date_windows() {
+        use crate::gui::Ease;
+        use crate::query::AnkiQuery;
+
+        assert_eq!(AnkiQuery::Added(7).to_string(), "added:7");
+        assert_eq!(AnkiQuery::Edited(1).to_string(), "edited:1");
+        assert_eq!(AnkiQuery::Introduced(30).to_string(), "introduced:30");
+        assert_eq!(
+            AnkiQuery::Rated {
+                days: 3,
+                ease: None
+            }
+            .to_string(),
+            "rated:3"
+        );
+        assert_eq!(
+            (AnkiQuery::Rated {
+                days: 3,
+                ease: Some(Ease::Again)
+            } & AnkiQuery::Added(7))
+            .to_string(),
+            "rated:3:1 added:7"
+        );
+    }
+
+    #[test]
+    fn queries_render_regex_and_no_combining() {
+        use crate::query::AnkiQuery;
+
+        assert_eq!(AnkiQuery::Regex(r"^\d+$".into()).to_string(), r"re:^\d+$");
+        assert_eq!(
+            AnkiQuery::Regex(r#"(?i)say "hi""#.into()).to_string(),
+            r#""re:(?i)say \"hi\"""#
+        );
+        assert_eq!(
+            AnkiQuery::FieldRegex {
+                name: "Reading".into(),
+                pattern: "^[ぁ-ん]+$".into()
+            }
+            .to_string(),
+            "Reading:re:^[ぁ-ん]+$"
+        );
+        assert_eq!(
+            AnkiQuery::NoCombining("ばば*".into()).to_string(),
+            r"nc:ばば\*"
+        );
+    }
+
+    #[test]
+    fn id_queries_split_under_the_length_limit() {
+        use crate::query::AnkiQuery;
+
+        assert_eq!(
+            AnkiQuery::NoteIds(vec![NoteId(1), NoteId(22)]).to_string(),
+            "nid:1,22"
+        );
+        assert_eq!(AnkiQuery::CardIds(vec![CardId(3)]).to_string(), "cid:3");
+
+        let queries = AnkiQuery::CardIds(vec![CardId(1), CardId(22), CardId(333), CardId(4444)])
+            .split_ids(10);
+        let rendered: Vec<String> = queries.iter().map(ToString::to_string).collect();
+        assert_eq!(rendered, ["cid:1,22", "cid:333", "cid:4444"]);
+        assert_eq!(AnkiQuery::Added(1).split_ids(1), [AnkiQuery::Added(1)]);
+    }
+
+    #[test]
+    fn text_queries_escape_user_input() {
+        use crate::query::AnkiQuery;
+
+        let text = |t: &str| AnkiQuery::Text(t.into()).to_string();
+        assert_eq!(text("食べる"), "食べる");
+        assert_eq!(text("note:Basic"), r"note\:Basic");
+        assert_eq!(text("a_b*"), r"a\_b\*");
+        assert_eq!(text("(aside)"), r#""\(aside\)""#);
+        assert_eq!(text(r#"He said "no" -"#), r#""He said \"no\" -""#);
+        assert_eq!(text("-1"), r#""-1""#);
+        assert_eq!(text("OR"), r#""OR""#);
+    }
+
+    #[test]
+    fn raw_queries_are_linted() {
+        use crate::query::{AnkiQuery, QueryWarning};
+
+        let lint = |q: &str| AnkiQuery::parse(q).1;
+        assert!(lint(r#"deck:X (tag:a or "tag:b c") -is:suspended Front:re:\d"#).is_empty());
+        assert!(lint(r"10\:30 is:Buried-Sibling").is_empty());
+        assert_eq!(
+            lint(r#"(deck:X "tag:a"#),
+            [
+                QueryWarning::UnbalancedParens { at: 0 },
+                QueryWarning::UnbalancedQuotes { at: 8 }
+            ]
+        );
+        assert_eq!(lint("a)"), [QueryWarning::UnbalancedParens { at: 1 }]);
+        assert_eq!(
+            lint("-is:nw"),
+            [QueryWarning::UnknownIsValue {
+                value: "nw".into(),
+                at: 4
+            }]
+        );
+        assert_eq!(
+            lint("at 10:30 https://example.com"),
+            [
+                QueryWarning::UnescapedColon {
+                    term: "10:30".into(),
+                    at: 5
+                },
+                QueryWarning::UnescapedColon {
+                    term: "https://example.com".into(),
+                    at: 14
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn query_builder_chains_terms() {
+        use crate::gui::Ease;
+        use crate::query::{AnkiQuery, CardState};
+
+        let query = AnkiQuery::builder()
+            .note("Mining")
+            .any_of([AnkiQuery::Tag("a".into()), AnkiQuery::Tag("b".into())])
+            .not(AnkiQuery::Is(CardState::Suspended))
+            .rated_within_days(2, Some(Ease::Hard))
+            .field("Word", "猫")
+            .build();
+        assert_eq!(
+            query.to_string(),
+            "note:Mining (tag:a or tag:b) -is:suspended rated:2:2 Word:猫"
+        );
+        assert_eq!(
+            AnkiQuery::builder().is_due().build(),
+            AnkiQuery::Is(CardState::Due)
+        );
+        assert_eq!(AnkiQuery::builder().build().to_string(), "deck:*");
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn query_presets_persist_with_the_cache() {
+        use crate::cache::{Cache, CacheFormat};
+        use crate::gui::Ease;
+        use crate::query::{AnkiQuery, CardState};
+
+        let cache = Cache::new();
+        let client = AnkiClient::default().with_queries(cache.queries.clone());
+        let leeches = AnkiQuery::Tag("leech".into()) & !AnkiQuery::Is(CardState::Suspended);
+        client.queries().save("leeches", leeches.clone());
+        client.queries().save(
+            "failed today",
+            AnkiQuery::Rated {
+                days: 1,
+                ease: Some(Ease::Again),
+            },
+        );
+        assert_eq!(
+            client.clone().queries().names(),
+            ["failed today", "leeches"]
+        );
+
+        let path = std::env::temp_dir().join("anki_direct_query_presets_test.json");
+        cache.save(&path, CacheFormat::Json).unwrap();
+        let loaded = Cache::load(&path, CacheFormat::Json).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.queries.get("leeches"), Some(leeches));
+        assert_eq!(
+            loaded.queries.remove("failed today").unwrap().to_string(),
+            "rated:1:1"
+        );
+        assert!(client.queries().get("missing").is_none());
+    }
+
+    #[test]
+    fn extreme_durations_do_not_panic() {
+        use crate::metrics::ActionMetrics;
+        use std::time::Duration;
+
+        let metrics = ActionMetrics {
+            requests: u64::from(u32::MAX) + 1,
+            total_latency: Duration::from_secs(u64::from(u32::MAX) + 1),
+            ..ActionMetrics::default()
+        };
+        assert_eq!(metrics.average_latency(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn ids_convert_and_serialize_as_numbers() {
+        use crate::id::DeckId;
+
+        let id: NoteId = "1717752795958".parse().unwrap();
+        assert_eq!(id, NoteId(1_717_752_795_958));
+        assert_eq!(id.to_string(), "1717752795958");
+        assert_eq!(serde_json::to_string(&id).unwrap(), "1717752795958");
+        assert_eq!(
+            serde_json::from_str::<CardId>("1485369733217").unwrap(),
+            CardId(1_485_369_733_217)
+        );
+        assert_eq!(DeckId::from(1u32), DeckId(1));
+        assert!(NoteId::try_from(-1i64).is_err());
+        assert!(CardId::try_from(u128::MAX).is_err());
+        assert!("abc".parse::<DeckId>().is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn info_timestamps_come_from_ids_and_mod() {
+        use crate::result::{CardModTime, NotesInfoData};
+
+        let note: NotesInfoData = serde_json::from_value(serde_json::json!({
+            "noteId": 1717752795958u64, "modelName": "Basic", "tags": [], "fields": {},
+            "mod": 1717752800
+        }))
+        .unwrap();
+        let created = note.created_at().unwrap();
+        assert_eq!(created.to_rfc3339(), "2024-06-07T09:33:15.958+00:00");
+        assert_eq!(note.modified_at().unwrap().timestamp(), 1_717_752_800);
+        let modified = CardModTime {
+            cardId: CardId(1),
+            modified: 0,
+        };
+        assert_eq!(modified.modified_at().unwrap().timestamp(), 0);
+        assert!(NoteId(u64::MAX).created_at().is_none());
+    }
+
+    #[tokio::test]
+    async fn plain_clients_parse_responses_in_one_pass() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let _ = socket.read(&mut request).await.unwrap();
+            let body = r#"{"result": [1717752795958, 1717752795959], "error": null}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = AnkiClient::new(&port.to_string(), 6);
+        let ids = NoteAction::find_note_ids(&client, "deck:Mining")
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![NoteId(1717752795958), NoteId(1717752795959)]);
+    }
+
+    #[tokio::test]
+    async fn oversized_add_notes_are_split() {
+        use crate::mock::MockBackend;
+        use crate::payload::split_add_notes;
+        use serde_json::json;
+
+        let note = |word: &str| json!({ "deckName": "Mining", "fields": { "Front": word } });
+        let payload = json!({
+            "action": "addNotes",
+            "version": 6,
+            "params": { "notes": [note("犬"), note("猫"), note("鳥")] }
+        });
+        let limit = payload.to_string().len() - 1;
+        let chunks = split_add_notes(payload.clone(), limit).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0]["params"]["notes"].as_array().unwrap().len(), 2);
+        assert_eq!(chunks[1]["params"]["notes"][0], note("鳥"));
+        assert!(chunks.iter().all(|c| c.to_string().len() <= limit));
+        assert!(matches!(
+            split_add_notes(payload.clone(), 80),
+            Err(AnkiError::ValidationError(_))
+        ));
+
+        let mock = MockBackend::new();
+        mock.expect("addNotes").respond(vec![Some(1), None]);
+        mock.expect("addNotes").respond(vec![Some(3)]);
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_max_payload_bytes(Some(limit));
+        let ids: Vec<Option<NoteId>> = crate::post_generic_request(&payload, &client)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(ids, vec![Some(NoteId(1)), None, Some(NoteId(3))]);
+        mock.assert_done();
+
+        let mock = MockBackend::new();
+        mock.expect("addNotes").respond(vec![1, 2, 3]);
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_max_payload_bytes(None);
+        crate::post_generic_request::<Vec<NoteId>>(&payload, &client)
+            .await
+            .unwrap();
+        mock.assert_done();
+
+        let mock = MockBackend::new();
+        mock.expect("addNotes").respond(vec![Some(1), None]);
+        mock.expect("addNotes").fail("collection is not available");
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_max_payload_bytes(Some(limit));
+        let res = crate::post_generic_request::<Vec<Option<NoteId>>>(&payload, &client).await;
+        match res {
+            Err(AnkiError::PartiallyAdded { added, error }) => {
+                assert_eq!(added, vec![Some(NoteId(1)), None]);
+                assert!(matches!(*error, AnkiError::CollectionUnavailable));
+            }
+            other => panic!("expected PartiallyAdded, got {:?}", other),
+        }
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_bodies_decode_to_the_request() {
+        use crate::payload::{encode_body, Compression};
+        use std::io::Read;
+
+        let request = serde_json::json!({ "action": "deckNames", "version": 6 });
+        let (body, encoding) = encode_body(&request, Compression::Gzip).unwrap();
+        assert_eq!(encoding, Some("gzip"));
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(body.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, request.to_string());
+        let (plain, encoding) = encode_body(&request, Compression::None).unwrap();
+        assert_eq!((plain, encoding), (request.to_string().into_bytes(), None));
+    }
+
+    #[tokio::test]
+    async fn long_id_lists_are_chunked() {
+        use crate::chunk::ChunkPolicy;
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        for ids in [[1, 2], [3, 4]] {
+            mock.expect("notesModTime")
+                .with_params(json!({ "notes": ids }))
+                .respond(ids.map(|id| json!({ "noteId": id, "mod": id * 10 })));
+        }
+        mock.expect("notesModTime")
+            .with_params(json!({ "notes": [5] }))
+            .respond(json!([{ "noteId": 5, "mod": 50 }]));
+        mock.expect("suspend")
+            .with_params(json!({ "cards": [1, 2] }))
+            .respond(false);
+        mock.expect("suspend")
+            .with_params(json!({ "cards": [3] }))
+            .respond(true);
+        mock.expect("deleteNotes").respond(());
+        mock.expect("deleteNotes")
+            .fail("collection is not available");
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_chunking(Some(ChunkPolicy {
+                size: 2,
+                parallelism: 2,
+            }));
+
+        let ids: Vec<NoteId> = (1..=5).map(NoteId).collect();
+        let times = NoteAction::notes_mod_time(&client, ids).await.unwrap();
+        let modified: Vec<_> = times.iter().map(|t| (t.noteId.0, t.modified)).collect();
+        assert_eq!(modified, [(1, 10), (2, 20), (3, 30), (4, 40), (5, 50)]);
+
+        let payload =
+            json!({ "action": "suspend", "version": 6, "params": { "cards": [1, 2, 3] } });
+        let changed: bool = crate::post_generic_request(&payload, &client)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert!(changed);
+
+        let payload =
+            json!({ "action": "deleteNotes", "version": 6, "params": { "notes": [1, 2, 3] } });
+        let res = crate::post_generic_request::<serde_json::Value>(&payload, &client).await;
+        assert!(matches!(
+            res.unwrap().into_unit(),
+            Err(AnkiError::CollectionUnavailable)
+        ));
+        mock.assert_done();
+
+        let mock = MockBackend::new();
+        mock.expect("notesModTime").respond(Vec::<()>::new());
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_chunking(None);
+        let ids: Vec<NoteId> = (1..=5).map(NoteId).collect();
+        NoteAction::notes_mod_time(&client, ids).await.unwrap();
+        let sent = mock.requests().pop().unwrap();
+        assert_eq!(sent["params"]["notes"].as_array().unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn store_many_reports_failures_by_index() {
+        use crate::media::{MediaAction, MediaBuilder, MediaSource};
+        use crate::mock::MockBackend;
+
+        let mock = MockBackend::new();
+        mock.expect("storeMediaFile").respond("a.mp3");
+        mock.expect("storeMediaFile").respond("c.mp3");
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let items = vec![
+            MediaBuilder::new(MediaSource::Bytes(vec![1])).filename("a.mp3"),
+            MediaBuilder::new(MediaSource::Bytes(vec![2])),
+            MediaBuilder::new(MediaSource::Bytes(vec![3])).filename("c.mp3"),
+        ];
+        let outcome = MediaAction::store_many(&client, items, 1).await;
+        assert!(!outcome.is_complete());
+        assert_eq!(outcome.len(), 3);
+        assert_eq!(
+            outcome.succeeded,
+            vec![(0, "a.mp3".to_string()), (2, "c.mp3".to_string())]
+        );
+        assert_eq!(outcome.failed.len(), 1);
+        assert_eq!(outcome.failed[0].index, 1);
+        let results = outcome.into_results();
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_deref().unwrap(), "c.mp3");
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn borrowed_infos_parse_from_the_response() {
+        use crate::chunk::ChunkPolicy;
+        use crate::mock::MockBackend;
+        use serde_json::json;
+        use std::borrow::Cow;
+
+        let note = |id: u64, front: &str| {
+            json!([{
+                "noteId": id,
+                "modelName": "Basic",
+                "tags": ["mining"],
+                "fields": { "Front": { "value": front, "order": 0 } },
+                "mod": 1717752800
+            }])
+        };
+        let mock = MockBackend::new();
+        mock.expect("notesInfo").respond(note(1, "犬"));
+        mock.expect("notesInfo").respond(note(2, "say \"hi\""));
+        let client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_chunking(Some(ChunkPolicy {
+                size: 1,
+                parallelism: 1,
+            }));
+
+        let buffer = NoteAction::notes_infos_borrowed(&client, vec![NoteId(1), NoteId(2)])
+            .await
+            .unwrap();
+        let notes = buffer.notes().unwrap();
+        assert_eq!(notes.len(), 2);
+        assert!(matches!(
+            notes[0].fields["Front"].value,
+            Cow::Borrowed("犬")
+        ));
+        assert!(matches!(notes[0].modelName, Cow::Borrowed("Basic")));
+        // Escaped strings have to be copied.
+        assert_eq!(notes[1].fields["Front"].value, "say \"hi\"");
+        assert!(matches!(notes[1].fields["Front"].value, Cow::Owned(_)));
+        assert_eq!(notes[1].modified, Some(1717752800));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn base64_bodies_match_the_json_payload() {
+        use crate::payload::write_base64_body;
+        use base64::Engine;
+        use serde_json::json;
+
+        let data = vec![0xff, 0x00, b'"', 0x7f, 1, 2, 3];
+        let envelope = json!({ "action": "storeMediaFile", "version": 6, "key": "k\"ey" });
+        let params = json!({ "filename": "a \"b\".mp3", "deleteExisting": true });
+        let body = write_base64_body(&envelope, &params, "data", &data).unwrap();
+
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let mut expected = envelope;
+        expected["params"] = params;
+        expected["params"]["data"] = base64::engine::general_purpose::STANDARD
+            .encode(&data)
+            .into();
+        assert_eq!(parsed, expected);
+
+        let body = write_base64_body(&json!({}), &json!({}), "data", b"").unwrap();
+        assert_eq!(body, br#"{"params":{"data":""}}"#);
+    }
+
+    #[tokio::test]
+    async fn media_bytes_are_encoded_into_the_body() {
+        use crate::media::MediaAction;
+        use crate::mock::MockBackend;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let body_start = loop {
+                let mut buf = [0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                if let Some(at) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break at + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+            let len: usize = head
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length: "))
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap();
+            while request.len() < body_start + len {
+                let mut buf = [0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let body = r#"{"result": "clip.mp4", "error": null}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&request[body_start..]).unwrap()
+        });
+
+        let data: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let client = AnkiClient::new(&port.to_string(), 6);
+        let stored = MediaAction::store_media_bytes(&client, "clip.mp4", &data, true)
+            .await
+            .unwrap();
+        assert_eq!(stored, "clip.mp4");
+        let sent = server.await.unwrap();
+        let expected = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &data);
+        assert_eq!(sent["params"]["data"], expected);
+        assert_eq!(sent["params"]["filename"], "clip.mp4");
+
+        let mock = MockBackend::new();
+        mock.expect("storeMediaFile")
+            .with_params(serde_json::json!({
+                "filename": "a.mp3", "deleteExisting": false, "data": "AQID"
+            }))
+            .respond("a.mp3");
+        let client = AnkiClient::default().with_transport(mock.clone());
+        MediaAction::store_media_bytes(&client, "a.mp3", &[1, 2, 3], false)
+            .await
+            .unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn raw_actions_round_trip() {
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        mock.expect("areDue")
+            .with_params(json!({ "cards": [1, 2] }))
+            .respond(vec![true, false]);
+        mock.expect("myAddonAction").respond(json!({ "ok": 1 }));
+        mock.expect("guiUndo").respond(());
+        mock.expect("getDeckStats").respond(());
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let due: Vec<bool> = client
+            .raw_typed("areDue", json!({ "cards": [1, 2] }))
+            .await
+            .unwrap();
+        assert_eq!(due, [true, false]);
+        let value = client.raw("myAddonAction", json!(null)).await.unwrap();
+        assert_eq!(value, json!({ "ok": 1 }));
+        assert!(mock.requests()[1].get("params").is_none());
+        client
+            .raw_typed::<()>("guiUndo", json!(null))
+            .await
+            .unwrap();
+        assert!(matches!(
+            client
+                .raw_typed::<Vec<u64>>("getDeckStats", json!(null))
+                .await,
+            Err(AnkiError::NoDataFound)
+        ));
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_collection_reads_notes_and_decks() {
+        use crate::query::AnkiQuery;
+        use crate::sqlite::SqliteCollection;
+        use rusqlite::Connection;
+
+        let path = std::env::temp_dir().join("anki_direct_sqlite_test.anki2");
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.create_collation("unicase", |a, b| a.cmp(b)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notetypes (id integer primary key, name text not null collate unicase);
+             CREATE TABLE fields (ntid integer, ord integer, name text collate unicase);
+             CREATE TABLE decks (id integer primary key, name text not null collate unicase);
+             CREATE TABLE notes (id integer primary key, mid integer, tags text, flds text, mod integer);
+             CREATE TABLE cards (id integer primary key, nid integer, did integer, odid integer);
+             INSERT INTO notetypes VALUES (10, 'Basic');
+             INSERT INTO fields VALUES (10, 1, 'Back'), (10, 0, 'Front');
+             INSERT INTO decks VALUES (1, 'Default'), (2, 'Japanese\x1fMining');
+             INSERT INTO notes VALUES
+                 (100, 10, ' mining anime::bleach ', '犬\x1fdog', 1717752800),
+                 (101, 10, '', '猫\x1fcat', 1717752801),
+                 (102, 10, ' Leech ', '鳥\x1fbird', 1717752802);
+             INSERT INTO cards VALUES (1000, 100, 2, 0), (1001, 101, 3, 2), (1002, 102, 1, 0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let collection = SqliteCollection::open(&path).unwrap();
+        let decks = collection.deck_names_and_ids();
+        assert_eq!(decks["Japanese::Mining"], crate::DeckId(2));
+        assert_eq!(collection.model_names(), ["Basic"]);
+        assert_eq!(
+            collection.get_tags().unwrap(),
+            ["Leech", "anime::bleach", "mining"]
+        );
+
+        let find = |query: AnkiQuery| collection.find_note_ids(&query).unwrap();
+        // 101 is in a filtered deck whose home deck is Japanese::Mining.
+        assert_eq!(
+            find(AnkiQuery::Deck("japanese".into())),
+            [NoteId(100), NoteId(101)]
+        );
+        assert_eq!(find(AnkiQuery::Tag("anime".into())), [NoteId(100)]);
+        // Terms are literal; wildcards only work in search strings.
+        assert!(find(AnkiQuery::Tag("lee*".into())).is_empty());
+        assert_eq!(find(AnkiQuery::Raw("tag:lee*".into())), [NoteId(102)]);
+        assert_eq!(
+            find(AnkiQuery::Raw("deck:Japanese*".into()) & !AnkiQuery::Text("ca".into())),
+            [NoteId(100)]
+        );
+        assert!(find(AnkiQuery::Field {
+            name: "back".into(),
+            value: "b_rd".into()
+        })
+        .is_empty());
+        assert_eq!(
+            find(AnkiQuery::Raw(
+                "back:b_rd or (-deck:Default nid:100,101 \"front:猫\")".into()
+            )),
+            [NoteId(101), NoteId(102)]
+        );
+        assert!(matches!(
+            collection.find_note_ids(&AnkiQuery::Added(1)),
+            Err(AnkiError::ValidationError(_))
+        ));
+        assert!(matches!(
+            collection.find_note_ids(&AnkiQuery::Raw("is:due".into())),
+            Err(AnkiError::ValidationError(_))
+        ));
+        assert!(matches!(
+            collection.find_note_ids(&AnkiQuery::Raw("(deck:Default".into())),
+            Err(AnkiError::ValidationError(_))
+        ));
+
+        let notes = collection.notes_infos(&[NoteId(101), NoteId(999)]).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].modelName, "Basic");
+        assert_eq!(notes[0].fields["Front"].value, "猫");
+        assert_eq!(notes[0].fields["Back"].order, 1);
+        assert_eq!(notes[0].modified, Some(1717752801));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_transport_answers_read_actions() {
+        use crate::decks::DeckAction;
+        use crate::notes::NoteAction;
+        use crate::sqlite::SqliteTransport;
+        use rusqlite::Connection;
+
+        let path = std::env::temp_dir().join("anki_direct_sqlite_transport_test.anki2");
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.create_collation("unicase", |a, b| a.cmp(b)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notetypes (id integer primary key, name text not null collate unicase);
+             CREATE TABLE fields (ntid integer, ord integer, name text collate unicase);
+             CREATE TABLE decks (id integer primary key, name text not null collate unicase);
+             CREATE TABLE notes (id integer primary key, mid integer, tags text, flds text, mod integer);
+             CREATE TABLE cards (id integer primary key, nid integer, did integer, odid integer);
+             INSERT INTO notetypes VALUES (10, 'Basic');
+             INSERT INTO fields VALUES (10, 0, 'Front'), (10, 1, 'Back');
+             INSERT INTO decks VALUES (1, 'Default'), (2, 'Mining');
+             INSERT INTO notes VALUES
+                 (100, 10, ' leech ', '犬\x1fdog', 1717752800),
+                 (101, 10, '', '猫\x1fcat', 1717752801);
+             INSERT INTO cards VALUES (1000, 100, 2, 0), (1001, 101, 1, 0);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let client = AnkiClient::default().with_transport(SqliteTransport::open(&path).unwrap());
+        assert_eq!(
+            DeckAction::deck_names(&client).await.unwrap(),
+            ["Default", "Mining"]
+        );
+        let ids = NoteAction::find_note_ids(&client, "deck:Mining tag:leech")
+            .await
+            .unwrap();
+        assert_eq!(ids, [NoteId(100)]);
+        let notes = NoteAction::get_notes_infos(&client, ids).await.unwrap();
+        assert_eq!(notes[0].fields["Back"].value, "dog");
+        assert!(NoteAction::find_note_ids(&client, "rated:1").await.is_err());
+        assert!(NoteAction::add_note(&client, Default::default())
+            .await
+            .is_err());
+
+        // Unsupported actions fail the same way alone and inside a `multi`.
+        let alone = client
+            .raw_typed::<serde_json::Value>("guiBrowse", serde_json::json!({ "query": "" }))
+            .await
+            .unwrap_err();
+        let mut batch = client.batch();
+        let item = batch.action::<serde_json::Value>("guiBrowse", None);
+        let batched = batch.send().await.unwrap().take(item).unwrap_err();
+        assert_eq!(format!("{:?}", alone), format!("{:?}", batched));
+        assert!(matches!(alone, AnkiError::RequestError(_)));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn yomitan_terms_map_onto_model_fields() {
+        use crate::mock::MockBackend;
+        use crate::yomitan::{parse_terms, TermField, YomitanProfile};
+
+        let terms = parse_terms(
+            r#"[
+                {
+                    "term": "食べる",
+                    "reading": "たべる",
+                    "definitions": ["to eat", { "type": "text", "text": "to live on <sth>" }],
+                    "audioUrl": "https://example.com/audio/taberu.ogg?x=1",
+                    "termTags": "common v1"
+                },
+                ["猫", "ねこ", "n", "", 0, [{
+                    "type": "structured-content",
+                    "content": [
+                        { "tag": "ruby", "content": ["猫", { "tag": "rt", "content": "ねこ" }] },
+                        { "tag": "div", "content": "cat" },
+                        "feline"
+                    ]
+                }], 1, ""],
+                { "expression": "", "glossary": "nothing" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(terms[0].glossary, ["to eat", "to live on <sth>"]);
+        assert_eq!(terms[0].tags, ["common", "v1"]);
+        assert_eq!(terms[1].glossary, ["猫\ncat\nfeline"]);
+        assert_eq!(terms[1].audio, None);
+
+        let profile = YomitanProfile::builder()
+            .deck("Mining")
+            .model("Japanese")
+            .field("Word", TermField::Expression)
+            .field("Reading", TermField::Furigana)
+            .field("Meaning", TermField::Glossary)
+            .audio_field("Audio")
+            .tag("yomitan")
+            .keep_term_tags(true)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            YomitanProfile::builder().model("Japanese").build(),
+            Err(AnkiError::ValidationError(_))
+        ));
+
+        let mock = MockBackend::new();
+        mock.expect("modelFieldNames")
+            .with_params(serde_json::json!({ "modelName": "Japanese" }))
+            .respond(["Word", "Reading", "Meaning", "Audio"]);
+        mock.expect("addNotes").respond(vec![Some(1), Some(2)]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let outcome = profile.prepare(&client, terms).await.unwrap();
+        assert_eq!(outcome.failed.len(), 1);
+        assert_eq!(outcome.failed[0].index, 2);
+        let notes: Vec<_> = outcome.succeeded.into_iter().map(|(_, n)| n).collect();
+        assert_eq!(notes[0].fields["Reading"], "食べる[たべる]");
+        assert_eq!(
+            notes[0].fields["Meaning"],
+            "<ol><li>to eat</li><li>to live on &lt;sth&gt;</li></ol>"
+        );
+        assert_eq!(notes[0].tags, ["yomitan", "common", "v1"]);
+        assert_eq!(notes[0].audio[0].fields, ["Audio"]);
+        assert!(notes[0].audio[0].filename.starts_with("yomitan-"));
+        assert!(notes[0].audio[0].filename.ends_with(".ogg"));
+        assert_eq!(notes[1].fields["Meaning"], "猫<br>cat<br>feline");
+        assert!(notes[1].audio.is_empty());
+
+        let ids = NoteAction::add_notes(&client, notes).await.unwrap();
+        assert_eq!(ids, vec![Some(NoteId(1)), Some(NoteId(2))]);
+        mock.assert_done();
+
+        assert!(matches!(
+            profile.to_notes(&["Front".to_string()], Vec::new()),
+            Err(AnkiError::ValidationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn folder_import_reports_each_row() {
+        use crate::folder_import::FolderImport;
+        use crate::mock::MockBackend;
+
+        let dir = std::env::temp_dir().join("anki_direct_folder_import");
+        let media = dir.join("media");
+        std::fs::create_dir_all(&media).unwrap();
+        std::fs::write(media.join("clip1.mp3"), b"ID3 one").unwrap();
+        std::fs::write(media.join("shot1.jpg"), b"\xff\xd8\xff jpeg").unwrap();
+        std::fs::write(media.join("clip2.mp3"), b"ID3 two").unwrap();
+        let tsv = dir.join("episode.tsv");
+        std::fs::write(
+            &tsv,
+            "ep01\t1\t[sound:clip1.mp3]\t<img src=\"shot1.jpg\">\t犬がいる\tThere's a dog\n\
+             \n\
+             ep01\t2\tclip2.mp3\t\t猫だ\tIt's a cat\r\n\
+             ep01\t3\t[sound:missing.mp3]\t\t鳥\tA bird\n\
+             ep01\t4\n",
+        )
+        .unwrap();
+
+        let mock = MockBackend::new();
+        mock.expect("storeMediaFile").respond("clip1.mp3");
+        mock.expect("storeMediaFile").respond("shot1.jpg");
+        mock.expect("storeMediaFile").respond("clip2.mp3");
+        mock.expect("addNote").respond(11);
+        mock.expect("addNote")
+            .fail("cannot create note because it is a duplicate");
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let outcome = FolderImport::new(&tsv, &media, "Mining", "Sentence")
+            .tag_column(0)
+            .tag("subs2srs")
+            .audio(2, "Audio")
+            .image(3, "Picture")
+            .text(4, "Sentence")
+            .text(5, "Meaning")
+            .concurrency(1)
+            .import(&client)
+            .await
+            .unwrap();
+        assert_eq!(outcome.succeeded, vec![(0, NoteId(11))]);
+        let failed: Vec<usize> = outcome.failed.iter().map(|f| f.index).collect();
+        assert_eq!(failed, [1, 2, 3]);
+        assert!(matches!(outcome.failed[0].error, AnkiError::DuplicateNote));
+        assert!(matches!(
+            outcome.failed[1].error,
+            AnkiError::RequestError(_)
+        ));
+        assert!(matches!(
+            outcome.failed[2].error,
+            AnkiError::ValidationError(_)
+        ));
+
+        let added: Vec<_> = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r["action"] == "addNote")
+            .collect();
+        let note = &added[0]["params"]["note"];
+        assert_eq!(note["fields"]["Audio"], "[sound:clip1.mp3]");
+        assert_eq!(note["fields"]["Picture"], "<img src=\"shot1.jpg\">");
+        assert_eq!(note["fields"]["Meaning"], "There's a dog");
+        assert_eq!(note["tags"], serde_json::json!(["subs2srs", "ep01"]));
+        let note = &added[1]["params"]["note"];
+        assert_eq!(note["fields"]["Picture"], "");
+        assert_eq!(note["fields"]["Meaning"], "It's a cat");
+        mock.assert_done();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn image_occlusion_builds_both_notetypes() {
+        use crate::mock::MockBackend;
+        use crate::occlusion::{ImageOcclusion, OcclusionMode, OcclusionRect};
+
+        let occlusion = || {
+            ImageOcclusion::new(MediaSource::Bytes(b"\x89PNG".to_vec()), 800, 600)
+                .filename("heart.png")
+                .deck("Anatomy")
+                .header("Chambers")
+                .occlusion(OcclusionRect::new(200.0, 150.0, 100.0, 60.0))
+                .occlusion_group(vec![
+                    OcclusionRect::new(0.0, 0.0, 80.0, 30.0),
+                    OcclusionRect::new(720.0, 570.0, 80.0, 30.0),
+                ])
+        };
+
+        let note = occlusion().builtin_note("heart.png").unwrap();
+        assert_eq!(note.modelName, "Image Occlusion");
+        assert_eq!(
+            note.fields["Occlusion"],
+            "{{c1::image-occlusion:rect:left=.25:top=.25:width=.125:height=.1:oi=1}}<br>\
+             {{c2::image-occlusion:rect:left=0:top=0:width=.1:height=.05:oi=1}}<br>\
+             {{c2::image-occlusion:rect:left=.9:top=.95:width=.1:height=.05:oi=1}}"
+        );
+        assert_eq!(note.fields["Image"], "<img src=\"heart.png\">");
+
+        let (notes, masks) = occlusion()
+            .mode(OcclusionMode::HideOneGuessOne)
+            .enhanced_notes("heart.png")
+            .unwrap();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(masks.len(), 5);
+        let id = &notes[1].fields["ID (hidden)"];
+        assert!(id.ends_with("-2"));
+        assert_eq!(
+            notes[1].fields["Question Mask"],
+            format!("<img src=\"{}-Q.svg\" />", id)
+        );
+        let question = &masks
+            .iter()
+            .find(|mask| mask.filename == format!("{}-Q.svg", id))
+            .unwrap()
+            .svg;
+        assert_eq!(question.matches("<rect").count(), 2);
+        assert_eq!(question.matches("qshape").count(), 2);
+        assert!(question.starts_with("<svg width=\"800\" height=\"600\""));
+
+        assert!(matches!(
+            occlusion()
+                .occlusion(OcclusionRect::new(750.0, 0.0, 100.0, 10.0))
+                .builtin_note("heart.png"),
+            Err(AnkiError::ValidationError(_))
+        ));
+
+        let mock = MockBackend::new();
+        mock.expect("storeMediaFile").respond("heart.png");
+        mock.expect("addNote").respond(42);
+        let client = AnkiClient::default().with_transport(mock.clone());
+        let id = occlusion().add_builtin(&client).await.unwrap();
+        assert_eq!(id, NoteId(42));
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "stats")]
+    #[tokio::test]
+    async fn heatmap_reads_reviews_deck_by_deck() {
+        use crate::mock::MockBackend;
+        use crate::stats::StatsAction;
+        use chrono::{Local, NaiveDate, TimeZone};
+
+        let day = NaiveDate::from_ymd_opt(2024, 6, 7).unwrap();
+        let local_millis = |hour| {
+            Local
+                .with_ymd_and_hms(2024, 6, 7, hour, 15, 0)
+                .earliest()
+                .unwrap()
+                .timestamp_millis()
+        };
+        let at_ten = local_millis(10);
+        let mock = MockBackend::new();
+        mock.expect("getNumCardsReviewedByDay")
+            .respond(serde_json::json!([["2024-06-07", 2]]));
+        mock.expect("deckNames").respond(["Default", "Mining"]);
+        mock.expect("cardReviews")
+            .with_params(
+                serde_json::json!({ "deck": "Default", "startID": local_millis(0) - 15 * 60_000 }),
+            )
+            .respond(serde_json::json!([[
+                at_ten + 1,
+                2,
+                -1,
+                3,
+                4,
+                1,
+                2500,
+                6000,
+                1
+            ]]));
+        mock.expect("cardReviews")
+            .respond(serde_json::json!([[at_ten, 1, -1, 3, 4, 1, 2500, 6000, 1]]));
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let heatmap = StatsAction::heatmap(&client, day..=day).await.unwrap();
+        assert_eq!(heatmap.days[&day], 2);
+        assert_eq!(heatmap.hours[&day][10], 2);
+        mock.assert_done();
+        assert!(mock.requests().iter().all(|r| r["params"]["deck"] != "*"));
+    }
+
+    #[cfg(feature = "stats")]
+    #[tokio::test]
+    async fn export_reviews_covers_every_deck() {
+        use crate::mock::MockBackend;
+        use crate::stats::{ReviewExportFormat, ReviewScope, StatsAction};
+
+        let mock = MockBackend::new();
+        mock.expect("deckNames").respond(["Default", "Mining"]);
+        mock.expect("cardReviews")
+            .with_params(serde_json::json!({ "deck": "Default", "startID": 0 }))
+            .respond(serde_json::json!([[20, 2, -1, 3, 4, 1, 2500, 6000, 1]]));
+        mock.expect("cardReviews")
+            .with_params(serde_json::json!({ "deck": "Mining", "startID": 0 }))
+            .respond(serde_json::json!([[10, 1, -1, 1, -600, 0, 2500, 9000, 0]]));
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut csv = Vec::new();
+        let written = StatsAction::export_reviews(
+            &client,
+            &ReviewScope::all(),
+            ReviewExportFormat::Csv,
+            &mut csv,
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, 2);
+        let csv = String::from_utf8(csv).unwrap();
+        let ids: Vec<&str> = csv.lines().skip(1).map(|l| &l[..2]).collect();
+        assert_eq!(ids, ["10", "20"]);
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn rehydrating_decks_keeps_configs() {
+        use crate::cache::{Cache, CacheSection};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Default": 1 }));
+        mock.expect("getDeckConfig")
+            .respond(json!({ "id": 1, "name": "Default" }));
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Default": 1 }));
+        mock.expect("getDeckConfig")
+            .respond(json!({ "id": 1, "name": "Default" }));
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut cache = Cache::new();
+        cache.decks.hydrate_full(&client).await.unwrap();
+        assert!(cache.decks.has_configs());
+        cache.hydrate(&client, CacheSection::Decks).await.unwrap();
+        assert!(cache.decks.config("Default").unwrap().is_some());
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn load_profile_keeps_version_and_expires_cache() {
+        use crate::cache::{Cache, CacheSection};
+        use crate::misc::MiscAction;
+        use crate::mock::MockBackend;
+        use std::sync::atomic::Ordering;
+
+        let mock = MockBackend::new();
+        mock.expect("getTags").respond(vec!["leech"]);
+        mock.expect("loadProfile")
+            .with_params(serde_json::json!({ "name": "Other" }))
+            .respond(true);
+        let mut cache = Cache::new();
+        let mut client = AnkiClient::default()
+            .with_transport(mock.clone())
+            .with_cache_invalidation(cache.invalidation());
+        client.version = 5;
+
+        cache.tags.hydrate(&client).await.unwrap();
+        assert!(!cache.is_expired(CacheSection::Tags));
+        assert!(MiscAction::load_profile(&client, "Other").await.unwrap());
+        // Detected once for the version check and again after the switch.
+        let probes = mock.requests();
+        let probes = probes.iter().filter(|r| r["action"] == "version");
+        assert_eq!(probes.count(), 2);
+        assert_eq!(client.version, 5);
+        assert_eq!(client.detected_version.load(Ordering::Relaxed), 6);
+        assert!(CacheSection::ALL.iter().all(|s| cache.is_expired(*s)));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn params_serialize_under_their_own_action() {
+        use crate::action::ActionRequest;
+        use crate::decks::GetDeckConfigParams;
+        use crate::gui::GuiDeckReviewParams;
+        use crate::media::DeleteMediaFileParams;
+        use crate::models::ModelFieldNamesParams;
+        use serde_json::json;
+
+        let request = |value: serde_json::Value| (value["action"].clone(), value["params"].clone());
+        let deck = ActionRequest::new(
+            6,
+            GetDeckConfigParams {
+                deck: "Mining".into(),
+            },
+        );
+        assert_eq!(
+            request(serde_json::to_value(&deck).unwrap()),
+            (json!("getDeckConfig"), json!({ "deck": "Mining" }))
+        );
+        let review = ActionRequest::new(
+            6,
+            GuiDeckReviewParams {
+                name: "Mining".into(),
+            },
+        );
+        assert_eq!(
+            request(serde_json::to_value(&review).unwrap()),
+            (json!("guiDeckReview"), json!({ "name": "Mining" }))
+        );
+        let delete = ActionRequest::new(
+            6,
+            DeleteMediaFileParams {
+                filename: "a.mp3".into(),
+            },
+        );
+        assert_eq!(
+            request(serde_json::to_value(&delete).unwrap()).0,
+            json!("deleteMediaFile")
+        );
+        let fields = ActionRequest::new(
+            6,
+            ModelFieldNamesParams {
+                modelName: "Basic".into(),
+            },
+        );
+        assert_eq!(
+            request(serde_json::to_value(&fields).unwrap()).0,
+            json!("modelFieldNames")
+        );
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn caches_keyed_by_id_survive_renames() {
+        use crate::cache::{DeckCache, ModelCache};
+        use crate::id::{DeckId, ModelId};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let model = |name: &str| json!({ "id": 7, "name": name, "flds": [], "tmpls": [], "css": "", "type": 0, "sortf": 0 });
+        let mock = MockBackend::new();
+        mock.expect("modelNames").respond(vec!["Basic"]);
+        mock.expect("findModelsByName")
+            .respond(json!([model("Basic")]));
+        mock.expect("modelNames").respond(vec!["Vocab"]);
+        mock.expect("findModelsByName")
+            .respond(json!([model("Vocab")]));
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Default": 1 }));
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Mining": 1 }));
+        mock.expect("deckNamesAndIds")
+            .respond(json!({ "Sentences": 1 }));
+        mock.expect("getDeckConfig")
+            .respond(json!({ "id": 1, "name": "Default" }));
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut models = ModelCache::<ModelId>::default();
+        models.hydrate_by(&client, |m| m.id).await.unwrap();
+        assert_eq!(models.get(&ModelId(7)).unwrap().unwrap().name, "Basic");
+        models.hydrate_by(&client, |m| m.id).await.unwrap();
+        assert_eq!(models.get(&ModelId(7)).unwrap().unwrap().name, "Vocab");
+        assert_eq!(models.keys().unwrap().count(), 1);
+
+        let mut decks = DeckCache::<DeckId>::default();
+        decks.hydrate_names_by(&client, |d| d.id).await.unwrap();
+        assert_eq!(decks.get(&DeckId(1)).unwrap().unwrap().name, "Default");
+        decks.hydrate_names_by(&client, |d| d.id).await.unwrap();
+        assert_eq!(decks.get(&DeckId(1)).unwrap().unwrap().name, "Mining");
+        decks.hydrate_full_by(&client, |d| d.id).await.unwrap();
+        assert_eq!(decks.get(&DeckId(1)).unwrap().unwrap().name, "Sentences");
+        assert!(decks.config(&DeckId(1)).unwrap().is_some());
+        assert_eq!(decks.keys().unwrap().count(), 1);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn media_list_fetches_one_partition_at_a_time() {
+        use crate::media::MediaAction;
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        let listing = |pattern: &str, names: &[&str]| {
+            mock.expect("getMediaFilesNames")
+                .with_params(json!({ "pattern": pattern }))
+                .respond(names.to_vec());
+        };
+        listing("a*", &["aa.mp3", "ab.mp3"]);
+        listing("[0-9]*a*", &["1a.mp3"]);
+        listing("[aA]*a*", &["aa.mp3"]);
+        listing("[bB]*a*", &["ba.mp3"]);
+        for class in 'c'..='z' {
+            listing(
+                &format!("[{}{}]*a*", class, class.to_ascii_uppercase()),
+                &[],
+            );
+        }
+        listing("[!0-9a-zA-Z]*a*", &[]);
+        listing("img_*", &["img_1.png"]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut list = MediaAction::list(&client, "**a*", 2);
+        assert!(mock.requests().is_empty());
+        let first = list.next_batch().await.unwrap().unwrap();
+        assert_eq!(first, ["aa.mp3", "ab.mp3"]);
+        let second = list.next_batch().await.unwrap().unwrap();
+        assert_eq!(second, ["1a.mp3", "ba.mp3"]);
+        assert!(list.next_batch().await.unwrap().is_none());
+
+        let mut list = MediaAction::list(&client, "img_*", 2);
+        assert_eq!(list.next_batch().await.unwrap().unwrap(), ["img_1.png"]);
+        assert!(list.next_batch().await.unwrap().is_none());
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_proxies_forward_to_async_actions() {
+        use crate::blocking::BlockingClient;
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let mock = MockBackend::new();
+        mock.expect("findNotes")
+            .with_params(json!({ "query": "is:new" }))
+            .respond(vec![1, 2]);
+        mock.expect("modelFieldNames")
+            .with_params(json!({ "modelName": "Basic" }))
+            .respond(vec!["Front", "Back"]);
+        let client =
+            BlockingClient::from_client(AnkiClient::default().with_transport(mock.clone()))
+                .unwrap();
+
+        let ids = client.notes().find_note_ids("is:new").unwrap();
+        assert_eq!(ids, [NoteId(1), NoteId(2)]);
+        let fields = client.models().field_names("Basic").unwrap();
+        assert_eq!(fields, ["Front", "Back"]);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn review_session_keeps_the_card_until_answered() {
+        use crate::gui::Ease;
+        use crate::mock::MockBackend;
+        use crate::review::ReviewSession;
+        use serde_json::json;
+
+        let card = |id: u64| {
+            json!({
+                "cardId": id, "note": 1, "deckName": "Mining", "modelName": "Basic",
+                "question": "", "answer": "", "fields": {}, "fieldOrder": 0, "css": "",
+                "ord": 0, "type": 2, "queue": 2, "due": 0, "interval": 3, "factor": 2500,
+                "reps": 5, "lapses": 0, "left": 0, "mod": 0,
+            })
+        };
+        let answer = |id: u64| json!({ "answers": [{ "cardId": id, "ease": 3 }] });
+        let due = json!({ "query": "\"deck:Mining\" is:due" });
+        let mock = MockBackend::new();
+        mock.expect("findCards")
+            .with_params(due.clone())
+            .respond(vec![1]);
+        mock.expect("cardsInfo")
+            .with_params(json!({ "cards": [1] }))
+            .respond(vec![card(1)]);
+        mock.expect("answerCards")
+            .with_params(answer(1))
+            .respond(vec![false]);
+        mock.expect("answerCards")
+            .with_params(answer(1))
+            .fail("busy");
+        mock.expect("answerCards")
+            .with_params(answer(1))
+            .respond(vec![true]);
+        mock.expect("findCards")
+            .with_params(due.clone())
+            .respond(vec![2]);
+        mock.expect("cardsInfo")
+            .with_params(json!({ "cards": [2] }))
+            .respond(vec![card(2)]);
+        mock.expect("answerCards")
+            .with_params(answer(2))
+            .respond(vec![true]);
+        mock.expect("findCards")
+            .with_params(due)
+            .respond(Vec::<u64>::new());
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let mut session = ReviewSession::start(&client, "Mining").await.unwrap();
+        assert_eq!(session.remaining(), 1);
+        let first = session.next_card().await.unwrap().unwrap();
+        assert_eq!(first.cardId, CardId(1));
+        assert!(session.answer(Ease::Good).await.is_err());
+        assert!(session.answer(Ease::Good).await.is_err());
+        assert_eq!(session.current().unwrap().cardId, CardId(1));
+        session.answer(Ease::Good).await.unwrap();
+        assert!(session.current().is_none());
+
+        let second = session.next_card().await.unwrap().unwrap();
+        assert_eq!(second.cardId, CardId(2));
+        session.answer(Ease::Good).await.unwrap();
+        assert!(session.next_card().await.unwrap().is_none());
+        assert_eq!(session.reviewed(), 2);
+        mock.assert_done();
+    }
+
+    #[cfg(feature = "stats")]
+    #[tokio::test]
+    async fn collection_watcher_reports_changes_after_the_first_poll() {
+        use crate::mock::MockBackend;
+        use crate::watcher::{CollectionEvent, CollectionWatcher};
+        use serde_json::json;
+        use std::time::Duration;
+
+        let mock = MockBackend::new();
+        let poll = |latest: u64, mods: &[(u64, u64)]| {
+            mock.expect("getLatestReviewID")
+                .with_params(json!({ "deck": "Mining" }))
+                .respond(latest);
+            let ids: Vec<u64> = mods.iter().map(|(id, _)| *id).collect();
+            mock.expect("findNotes")
+                .with_params(json!({ "query": "edited:1" }))
+                .respond(ids);
+            let times: Vec<_> = mods
+                .iter()
+                .map(|(id, m)| json!({ "noteId": id, "mod": m }))
+                .collect();
+            mock.expect("notesModTime").respond(times);
+        };
+        poll(10, &[(1, 100), (2, 100)]);
+        poll(10, &[(1, 100), (2, 150)]);
+        poll(12, &[(1, 100), (2, 150), (3, 200)]);
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let (mut events, handle) =
+            CollectionWatcher::new(&client, "Mining", Duration::from_millis(1))
+                .watch_notes(true)
+                .spawn();
+        assert_eq!(
+            events.recv().await.unwrap().unwrap(),
+            CollectionEvent::NotesChanged {
+                note_ids: vec![NoteId(2)]
+            }
+        );
+        assert_eq!(
+            events.recv().await.unwrap().unwrap(),
+            CollectionEvent::ReviewsLogged {
+                deck: "Mining".to_string(),
+                previous_review_id: 10,
+                latest_review_id: 12,
+            }
+        );
+        assert_eq!(
+            events.recv().await.unwrap().unwrap(),
+            CollectionEvent::NotesChanged {
+                note_ids: vec![NoteId(3)]
+            }
+        );
+        handle.abort();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn overwrite_policies_pick_delete_existing() {
+        use crate::media::{MediaBuilder, OverwritePolicy};
+        use crate::mock::MockBackend;
+        use serde_json::json;
+
+        let store = |filename: &str, delete_existing: bool| json!({ "filename": filename, "deleteExisting": delete_existing, "data": "AQID" });
+        let builder = |filename: &str, policy: OverwritePolicy| {
+            MediaBuilder::new(MediaSource::Bytes(vec![1, 2, 3]))
+                .filename(filename)
+                .overwrite(policy)
+        };
+        let mock = MockBackend::new();
+        mock.expect("storeMediaFile")
+            .with_params(store("a.mp3", true))
+            .respond("a.mp3");
+        mock.expect("storeMediaFile")
+            .with_params(store("a.mp3", false))
+            .respond("a_1.mp3");
+        mock.expect("getMediaFilesNames")
+            .with_params(json!({ "pattern": "a.mp3" }))
+            .respond(vec!["a.mp3"]);
+        mock.expect("getMediaFilesNames")
+            .with_params(json!({ "pattern": "b.mp3" }))
+            .respond(Vec::<String>::new());
+        mock.expect("storeMediaFile")
+            .with_params(store("b.mp3", true))
+            .respond("b.mp3");
+        let client = AnkiClient::default().with_transport(mock.clone());
+
+        let stored = builder("a.mp3", OverwritePolicy::Overwrite);
+        assert_eq!(stored.store(&client).await.unwrap(), "a.mp3");
+        let stored = builder("a.mp3", OverwritePolicy::KeepExisting);
+        assert_eq!(stored.store(&client).await.unwrap(), "a_1.mp3");
+        let refused = builder("a.mp3", OverwritePolicy::ErrorIfExists);
+        assert!(matches!(
+            refused.store(&client).await,
+            Err(AnkiError::ValidationError(_))
+        ));
+        assert_eq!(
+            mock.requests().last().unwrap()["action"],
+            "getMediaFilesNames"
+        );
+        let stored = builder("b.mp3", OverwritePolicy::ErrorIfExists);
+        assert_eq!(stored.store(&client).await.unwrap(), "b.mp3");
+        mock.assert_done();
+
+        // Copied straight into a local media folder, the file on disk is checked instead.
+        let dir = std::env::temp_dir().join("anki_direct_overwrite_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.mp3"), [9]).unwrap();
+        let direct = |policy| {
+            builder("a.mp3", policy)
+                .direct_copy()
+                .media_dir(dir.clone())
+        };
+        let sent = mock.requests().len();
+        assert!(matches!(
+            direct(OverwritePolicy::ErrorIfExists).store(&client).await,
+            Err(AnkiError::ValidationError(_))
+        ));
+        assert_eq!(mock.requests().len(), sent);
+        mock.expect("storeMediaFile")
+            .with_params(store("a.mp3", false))
+            .respond("a_1.mp3");
+        let stored = direct(OverwritePolicy::KeepExisting).store(&client).await;
+        assert_eq!(stored.unwrap(), "a_1.mp3");
+        let stored = direct(OverwritePolicy::Overwrite).store(&client).await;
+        assert_eq!(stored.unwrap(), "a.mp3");
+        assert_eq!(std::fs::read(dir.join("a.mp3")).unwrap(), [1, 2, 3]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        mock.assert_done();
+    }
 }
@@ -0,0 +1,169 @@
+use crate::error::AnkiError;
+use crate::payload::{encode_body, Compression};
+use crate::result::GenericResult;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+/// The future returned by `AnkiTransport::call`.
+#[cfg(not(target_arch = "wasm32"))]
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Value, TransportError>> + Send + 'a>>;
+
+/// The future returned by `AnkiTransport::call`. Browser fetches aren't `Send`.
+#[cfg(target_arch = "wasm32")]
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Value, TransportError>> + 'a>>;
+
+/// Why a transport couldn't deliver a request.
+#[derive(Debug)]
+pub enum TransportError {
+    /// AnkiConnect couldn't be reached, so the request never arrived.
+    /// The client re-probes the endpoint and retries once.
+    Unreachable(String),
+    Failed(AnkiError),
+}
+
+impl From<TransportError> for AnkiError {
+    fn from(value: TransportError) -> Self {
+        match value {
+            TransportError::Unreachable(e) => AnkiError::RequestError(e),
+            TransportError::Failed(e) => e,
+        }
+    }
+}
+
+/// Delivers request envelopes to AnkiConnect, so other HTTP stacks or test doubles
+/// can stand in for `reqwest` without changing any action.
+///
+/// The request is the full JSON envelope, with `action`, `version`, `params` and `key`
+/// when set; the response is AnkiConnect's `{ result, error }` envelope.
+///
+/// # Example
+///
+/// ```
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::transport::{AnkiTransport, TransportFuture};
+/// # use serde_json::{json, Value};
+/// struct Canned;
+///
+/// impl AnkiTransport for Canned {
+///     fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a> {
+///         Box::pin(async move { Ok(json!({ "result": 6, "error": null })) })
+///     }
+/// }
+///
+/// let client = AnkiClient::default().with_transport(Canned);
+/// ```
+pub trait AnkiTransport: Send + Sync {
+    fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a>;
+}
+
+impl fmt::Debug for dyn AnkiTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AnkiTransport")
+    }
+}
+
+/// The default transport: JSON over HTTP with `reqwest`.
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    pub endpoint: String,
+    pub client: Client,
+    pub compression: Compression,
+}
+
+impl AnkiTransport for ReqwestTransport {
+    fn call<'a>(&'a self, request: &'a Value) -> TransportFuture<'a> {
+        Box::pin(reqwest_call(
+            &self.client,
+            &self.endpoint,
+            request,
+            self.compression,
+        ))
+    }
+}
+
+/// A `POST` of `request` to `endpoint`, with its body encoded by `compression`.
+fn post(
+    client: &Client,
+    endpoint: &str,
+    request: &Value,
+    compression: Compression,
+) -> Result<RequestBuilder, TransportError> {
+    match encode_body(request, compression) {
+        Ok((body, encoding)) => Ok(post_body(client, endpoint, body, encoding)),
+        Err(e) => Err(TransportError::Failed(e)),
+    }
+}
+
+/// A `POST` of an already serialized, and possibly compressed, JSON body.
+pub(crate) fn post_body(
+    client: &Client,
+    endpoint: &str,
+    body: Vec<u8>,
+    encoding: Option<&str>,
+) -> RequestBuilder {
+    let builder = client
+        .post(endpoint)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body);
+    match encoding {
+        Some(encoding) => builder.header(CONTENT_ENCODING, encoding),
+        None => builder,
+    }
+}
+
+pub(crate) async fn reqwest_call(
+    client: &Client,
+    endpoint: &str,
+    request: &Value,
+    compression: Compression,
+) -> Result<Value, TransportError> {
+    let req = post(client, endpoint, request, compression)?;
+    let res = async { req.send().await?.json().await };
+    match res.await {
+        Ok(body) => Ok(body),
+        Err(e) => Err(transport_error(e)),
+    }
+}
+
+/// Like `reqwest_call`, but parses the response bytes straight into the envelope,
+/// without building a `Value` first.
+pub(crate) async fn reqwest_call_typed<T: DeserializeOwned>(
+    client: &Client,
+    endpoint: &str,
+    request: &Value,
+    compression: Compression,
+) -> Result<GenericResult<T>, TransportError> {
+    send_typed(post(client, endpoint, request, compression)?).await
+}
+
+/// Sends a request and parses the response bytes into the envelope.
+pub(crate) async fn send_typed<T: DeserializeOwned>(
+    req: RequestBuilder,
+) -> Result<GenericResult<T>, TransportError> {
+    let res = async { req.send().await?.bytes().await };
+    let bytes = match res.await {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(transport_error(e)),
+    };
+    match serde_json::from_slice(&bytes) {
+        Ok(envelope) => Ok(envelope),
+        Err(e) => Err(TransportError::Failed(AnkiError::ParseError(e.to_string()))),
+    }
+}
+
+fn transport_error(e: reqwest::Error) -> TransportError {
+    #[cfg(not(target_arch = "wasm32"))]
+    if e.is_connect() {
+        return TransportError::Unreachable(e.to_string());
+    }
+    match e.is_decode() {
+        true => TransportError::Failed(AnkiError::ParseError(e.to_string())),
+        false => TransportError::Failed(AnkiError::RequestError(e.to_string())),
+    }
+}
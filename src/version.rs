@@ -0,0 +1,37 @@
+#![allow(non_snake_case)]
+use crate::action::Action;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Returns the oldest AnkiConnect version `action` is known to work with, if it is tracked.
+/// See `Action::min_version`.
+pub fn min_version(action: &str) -> Option<u8> {
+    Action::from(action).min_version()
+}
+
+/// The body AnkiConnect answers a plain `GET` with, e.g. `{"apiVersion": "AnkiConnect v.6"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ApiVersionInfo {
+    #[serde(default, alias = "api_version", alias = "version")]
+    pub apiVersion: Option<Value>,
+}
+
+impl ApiVersionInfo {
+    /// The version number, whether it was sent as a number or as text like `AnkiConnect v.6`.
+    pub fn version(&self) -> Option<u8> {
+        match self.apiVersion.as_ref()? {
+            Value::Number(n) => n.as_u64().and_then(|n| u8::try_from(n).ok()),
+            Value::String(s) => parse_api_version(s),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the version number out of an `apiVersion` string: its last run of digits.
+pub fn parse_api_version(text: &str) -> Option<u8> {
+    let end = text.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = text[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    text[start..end].parse().ok()
+}
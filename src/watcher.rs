@@ -0,0 +1,163 @@
+use crate::error::AnkiError;
+use crate::id::NoteId;
+use crate::notes::NoteAction;
+use crate::stats::StatsAction;
+use crate::AnkiClient;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How many events a `CollectionWatcher` buffers before polling waits for the receiver.
+const WATCHER_CHANNEL_CAPACITY: usize = 64;
+
+/// Study activity noticed by a `CollectionWatcher`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollectionEvent {
+    /// New reviews were logged in the watched deck since the last poll.
+    ReviewsLogged {
+        deck: String,
+        previous_review_id: u64,
+        latest_review_id: u64,
+    },
+    /// Notes were added or edited since the last poll.
+    NotesChanged { note_ids: Vec<NoteId> },
+}
+
+/// Polls AnkiConnect on an interval and reports study activity through a channel,
+/// so companion apps can react to reviews and edits without their own polling loop.
+///
+/// The first poll only records the current state; events describe changes after it.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::watcher::CollectionWatcher;
+/// # use std::time::Duration;
+/// # async fn run() {
+/// let client = AnkiClient::default();
+/// let (mut events, _handle) = CollectionWatcher::new(&client, "Mining", Duration::from_secs(5))
+///     .watch_notes(true)
+///     .spawn();
+/// while let Some(event) = events.recv().await {
+///     println!("{:?}", event);
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CollectionWatcher {
+    anki_client: AnkiClient,
+    deck: String,
+    interval: Duration,
+    watch_notes: bool,
+}
+
+impl CollectionWatcher {
+    /// Watches the reviews of `deck`, polling every `interval`.
+    pub fn new(anki_client: &AnkiClient, deck: &str, interval: Duration) -> Self {
+        Self {
+            anki_client: anki_client.clone(),
+            deck: deck.to_string(),
+            interval,
+            watch_notes: false,
+        }
+    }
+
+    /// Also reports notes added or edited in the last day. Off by default,
+    /// since it costs two more requests per poll.
+    pub fn watch_notes(mut self, watch_notes: bool) -> Self {
+        self.watch_notes = watch_notes;
+        self
+    }
+
+    /// Starts polling in a background task.
+    ///
+    /// Polling errors are sent through the channel and polling carries on.
+    /// The task stops once the receiver is dropped.
+    pub fn spawn(
+        self,
+    ) -> (
+        mpsc::Receiver<Result<CollectionEvent, AnkiError>>,
+        JoinHandle<()>,
+    ) {
+        let (tx, rx) = mpsc::channel(WATCHER_CHANNEL_CAPACITY);
+        let handle = tokio::spawn(self.run(tx));
+        (rx, handle)
+    }
+
+    async fn run(self, tx: mpsc::Sender<Result<CollectionEvent, AnkiError>>) {
+        let mut state = WatchState::default();
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if tx.is_closed() {
+                return;
+            }
+            let events = match self.poll(&mut state).await {
+                Ok(events) => events.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            for event in events {
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn poll(&self, state: &mut WatchState) -> Result<Vec<CollectionEvent>, AnkiError> {
+        let mut events = Vec::new();
+
+        let latest = StatsAction::latest_review_id(&self.anki_client, &self.deck).await?;
+        if let Some(previous) = state.latest_review_id {
+            if latest > previous {
+                events.push(CollectionEvent::ReviewsLogged {
+                    deck: self.deck.clone(),
+                    previous_review_id: previous,
+                    latest_review_id: latest,
+                });
+            }
+        }
+        state.latest_review_id = Some(latest);
+
+        if self.watch_notes {
+            let ids = match NoteAction::find_note_ids(&self.anki_client, "edited:1").await {
+                Ok(ids) => ids,
+                Err(AnkiError::NoDataFound) => Vec::new(),
+                Err(e) => return Err(e),
+            };
+            let times = match ids.is_empty() {
+                true => Vec::new(),
+                false => NoteAction::notes_mod_time(&self.anki_client, ids).await?,
+            };
+
+            // Rebuilt every poll so notes falling out of the one-day window are forgotten.
+            let mut mods = HashMap::with_capacity(times.len());
+            let mut changed = Vec::new();
+            for t in times {
+                let previous = state.note_mods.get(&t.noteId);
+                if state.notes_seeded && previous.is_none_or(|p| t.modified > *p) {
+                    changed.push(t.noteId);
+                }
+                mods.insert(t.noteId, t.modified);
+            }
+            state.note_mods = mods;
+            state.notes_seeded = true;
+            if !changed.is_empty() {
+                events.push(CollectionEvent::NotesChanged { note_ids: changed });
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+#[derive(Default)]
+struct WatchState {
+    latest_review_id: Option<u64>,
+    note_mods: HashMap<NoteId, u64>,
+    notes_seeded: bool,
+}
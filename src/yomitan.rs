@@ -0,0 +1,434 @@
+use crate::batch::BatchOutcome;
+use crate::error::AnkiError;
+use crate::media::{infer_filename, with_hash_suffix, MediaSource};
+use crate::models::ModelAction;
+use crate::notes::{Media, NewNote};
+use crate::{AnkiClient, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Extensions kept when naming downloaded term audio; anything else is stored as `.mp3`.
+const AUDIO_EXTENSIONS: [&str; 7] = ["mp3", "ogg", "opus", "m4a", "aac", "wav", "flac"];
+
+/// A dictionary term exported from Yomitan (or Yomichan).
+///
+/// Deserializes from an exported term object, with either Yomitan's or Yomichan's key
+/// names, or from a row of a `term_bank_*.json` file. Structured-content glossaries are
+/// flattened to plain text, one line per block; images are left out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(try_from = "RawTerm")]
+pub struct YomitanTerm {
+    pub expression: String,
+    pub reading: String,
+    /// One entry per definition, as plain text.
+    pub glossary: Vec<String>,
+    /// Where the term's audio can be downloaded from.
+    pub audio: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTerm {
+    Object(TermObject),
+    Row(Vec<Value>),
+}
+
+#[derive(Deserialize)]
+struct TermObject {
+    #[serde(alias = "term")]
+    expression: String,
+    #[serde(default)]
+    reading: String,
+    #[serde(default, alias = "definitions", alias = "glossaries")]
+    glossary: Value,
+    #[serde(default, alias = "audioUrl", alias = "audio_url")]
+    audio: Option<String>,
+    #[serde(default, alias = "termTags")]
+    tags: Value,
+}
+
+impl TryFrom<RawTerm> for YomitanTerm {
+    type Error = String;
+
+    fn try_from(raw: RawTerm) -> Result<Self, Self::Error> {
+        match raw {
+            RawTerm::Object(term) => Ok(YomitanTerm {
+                expression: term.expression,
+                reading: term.reading,
+                glossary: glossary_entries(&term.glossary),
+                audio: term.audio.filter(|url| !url.is_empty()),
+                tags: tag_list(&term.tags),
+            }),
+            // [expression, reading, definitionTags, rules, score, glossary, sequence, termTags]
+            RawTerm::Row(row) => {
+                let Some(expression) = row.first().and_then(Value::as_str) else {
+                    return Err("a term bank row must start with the expression".to_string());
+                };
+                Ok(YomitanTerm {
+                    expression: expression.to_string(),
+                    reading: row.get(1).and_then(Value::as_str).unwrap_or("").to_string(),
+                    glossary: row.get(5).map(glossary_entries).unwrap_or_default(),
+                    audio: None,
+                    tags: row.get(7).map(tag_list).unwrap_or_default(),
+                })
+            }
+        }
+    }
+}
+
+/// Parses exported terms: a single term, a list of terms, or a whole term bank.
+pub fn parse_terms(json: &str) -> Result<Vec<YomitanTerm>, AnkiError> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Terms {
+        Many(Vec<YomitanTerm>),
+        One(YomitanTerm),
+    }
+
+    match serde_json::from_str(json) {
+        Ok(Terms::Many(terms)) => Ok(terms),
+        Ok(Terms::One(term)) => Ok(vec![term]),
+        Err(e) => Err(AnkiError::ParseError(e.to_string())),
+    }
+}
+
+/// What part of a term a model field is filled with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TermField {
+    Expression,
+    Reading,
+    /// The expression with its reading in Anki's furigana syntax, `漢字[かんじ]`.
+    Furigana,
+    /// The definitions, as a numbered list when there are several.
+    Glossary,
+    /// The same text for every note.
+    Fixed(String),
+}
+
+/// How exported terms map onto the fields of a model, built with `YomitanProfile::builder`.
+///
+/// # Example
+///
+/// ```no_run
+/// # use anki_direct::AnkiClient;
+/// # use anki_direct::notes::NoteAction;
+/// # use anki_direct::yomitan::{parse_terms, TermField, YomitanProfile};
+/// # async fn run(json: &str) -> Result<(), anki_direct::error::AnkiError> {
+/// let client = AnkiClient::default();
+/// let profile = YomitanProfile::builder()
+///     .deck("Mining")
+///     .model("Japanese")
+///     .field("Word", TermField::Expression)
+///     .field("Reading", TermField::Furigana)
+///     .field("Meaning", TermField::Glossary)
+///     .audio_field("Audio")
+///     .tag("yomitan")
+///     .build()?;
+/// let prepared = profile.prepare(&client, parse_terms(json)?).await?;
+/// let notes = prepared.succeeded.into_iter().map(|(_, note)| note).collect();
+/// let ids = NoteAction::add_notes(&client, notes).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct YomitanProfile {
+    deck: String,
+    model: String,
+    fields: Vec<(String, TermField)>,
+    audio_field: Option<String>,
+    tags: Vec<String>,
+    keep_term_tags: bool,
+}
+
+/// Builds a `YomitanProfile`. The deck, the model and at least one field are required.
+#[derive(Debug, Default)]
+pub struct YomitanProfileBuilder {
+    deck: Option<String>,
+    model: Option<String>,
+    fields: Vec<(String, TermField)>,
+    audio_field: Option<String>,
+    tags: Vec<String>,
+    keep_term_tags: bool,
+}
+
+impl YomitanProfileBuilder {
+    /// The deck the notes are added to.
+    pub fn deck(mut self, deck: &str) -> Self {
+        self.deck = Some(deck.to_string());
+        self
+    }
+
+    /// The model of the notes.
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+
+    /// Fills the model field `name` with `value`. Setting a field again replaces it.
+    pub fn field(mut self, name: &str, value: TermField) -> Self {
+        self.fields.retain(|(field, _)| field != name);
+        self.fields.push((name.to_string(), value));
+        self
+    }
+
+    /// The field the term's audio is appended to, as `[sound:...]`. Terms without
+    /// an audio URL leave it as it is.
+    pub fn audio_field(mut self, name: &str) -> Self {
+        self.audio_field = Some(name.to_string());
+        self
+    }
+
+    /// A tag added to every note.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Also tags each note with its term's own tags, e.g. `common`. Off by default.
+    pub fn keep_term_tags(mut self, keep: bool) -> Self {
+        self.keep_term_tags = keep;
+        self
+    }
+
+    pub fn build(self) -> Result<YomitanProfile, AnkiError> {
+        let (Some(deck), Some(model)) = (self.deck, self.model) else {
+            return Err(AnkiError::ValidationError(
+                "a yomitan profile needs a deck and a model".to_string(),
+            ));
+        };
+        if self.fields.is_empty() {
+            return Err(AnkiError::ValidationError(
+                "a yomitan profile needs at least one field".to_string(),
+            ));
+        }
+        Ok(YomitanProfile {
+            deck,
+            model,
+            fields: self.fields,
+            audio_field: self.audio_field,
+            tags: self.tags,
+            keep_term_tags: self.keep_term_tags,
+        })
+    }
+}
+
+impl YomitanProfile {
+    pub fn builder() -> YomitanProfileBuilder {
+        YomitanProfileBuilder::default()
+    }
+
+    /// Looks up the model's fields and maps `terms` with `to_notes`.
+    pub async fn prepare(
+        &self,
+        anki_client: &AnkiClient,
+        terms: Vec<YomitanTerm>,
+    ) -> Result<BatchOutcome<NewNote>, AnkiError> {
+        let model_fields = ModelAction::field_names(anki_client, &self.model).await?;
+        self.to_notes(&model_fields, terms)
+    }
+
+    /// Maps `terms` onto notes of a model with `model_fields`, in order.
+    ///
+    /// Fails as a whole with `ValidationError` if the profile names a field the model
+    /// doesn't have. A term fails on its own if it has no expression or would leave
+    /// the model's first field empty, which Anki refuses.
+    pub fn to_notes(
+        &self,
+        model_fields: &[String],
+        terms: Vec<YomitanTerm>,
+    ) -> Result<BatchOutcome<NewNote>, AnkiError> {
+        let start = Instant::now();
+        let mapped = self.fields.iter().map(|(name, _)| name);
+        for name in mapped.chain(&self.audio_field) {
+            if !model_fields.contains(name) {
+                return Err(AnkiError::ValidationError(format!(
+                    "model `{}` has no field `{}`",
+                    self.model, name
+                )));
+            }
+        }
+        let first_field = model_fields.first();
+
+        let results = terms.iter().map(|term| {
+            let note = self.to_note(term)?;
+            let first_empty = first_field
+                .map(|name| note.fields.get(name).is_none_or(|v| v.trim().is_empty()))
+                .unwrap_or(true);
+            // Audio is appended to its field by AnkiConnect, so it counts as content.
+            let has_audio = !note.audio.is_empty() && self.audio_field.as_ref() == first_field;
+            if first_empty && !has_audio {
+                return Err(AnkiError::ValidationError(format!(
+                    "`{}` would leave the first field empty",
+                    term.expression
+                )));
+            }
+            Ok(note)
+        });
+        Ok(BatchOutcome::from_results(
+            results.collect::<Vec<_>>(),
+            start.elapsed(),
+        ))
+    }
+
+    /// Maps a single term onto a note, without checking it against the model.
+    pub fn to_note(&self, term: &YomitanTerm) -> Result<NewNote, AnkiError> {
+        if term.expression.trim().is_empty() {
+            return Err(AnkiError::ValidationError(
+                "the term has no expression".to_string(),
+            ));
+        }
+        let fields: HashMap<String, String> = self
+            .fields
+            .iter()
+            .map(|(name, value)| (name.clone(), render_field(term, value)))
+            .collect();
+
+        let mut tags = self.tags.clone();
+        if self.keep_term_tags {
+            for tag in &term.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+
+        let audio = match (&self.audio_field, &term.audio) {
+            (Some(field), Some(url)) => vec![Media {
+                url: url.clone(),
+                filename: audio_filename(url),
+                skipHash: None,
+                fields: vec![field.clone()],
+            }],
+            _ => Vec::new(),
+        };
+
+        Ok(NewNote {
+            deckName: self.deck.clone(),
+            modelName: self.model.clone(),
+            fields,
+            tags,
+            audio,
+            picture: Vec::new(),
+        })
+    }
+}
+
+fn render_field(term: &YomitanTerm, value: &TermField) -> String {
+    match value {
+        TermField::Expression => escape_html(&term.expression),
+        TermField::Reading => escape_html(&term.reading),
+        TermField::Furigana if term.reading.is_empty() || term.reading == term.expression => {
+            escape_html(&term.expression)
+        }
+        TermField::Furigana => format!(
+            "{}[{}]",
+            escape_html(&term.expression),
+            escape_html(&term.reading)
+        ),
+        TermField::Glossary => match term.glossary.as_slice() {
+            [] => String::new(),
+            [entry] => glossary_html(entry),
+            entries => {
+                let items: String = entries
+                    .iter()
+                    .map(|entry| format!("<li>{}</li>", glossary_html(entry)))
+                    .collect();
+                format!("<ol>{}</ol>", items)
+            }
+        },
+        TermField::Fixed(text) => text.clone(),
+    }
+}
+
+/// Names downloaded audio after a hash of its URL, so the same clip is stored once.
+fn audio_filename(url: &str) -> String {
+    let ext = MediaSource::url(url)
+        .and_then(|source| infer_filename(&source))
+        .ok()
+        .and_then(|name| Some(name.rsplit_once('.')?.1.to_ascii_lowercase()))
+        .filter(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str()))
+        .unwrap_or_else(|| "mp3".to_string());
+    with_hash_suffix(&format!("yomitan.{}", ext), url.as_bytes())
+}
+
+fn glossary_html(entry: &str) -> String {
+    escape_html(entry).replace('\n', "<br>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn tag_list(tags: &Value) -> Vec<String> {
+    match tags {
+        Value::String(tags) => tags.split_whitespace().map(str::to_string).collect(),
+        Value::Array(tags) => tags
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn glossary_entries(glossary: &Value) -> Vec<String> {
+    let entries = match glossary {
+        Value::Array(entries) => entries.as_slice(),
+        entry => std::slice::from_ref(entry),
+    };
+    entries
+        .iter()
+        .filter_map(glossary_text)
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+/// The text of a glossary entry. `None` for images and deinflection entries.
+fn glossary_text(entry: &Value) -> Option<String> {
+    match entry {
+        Value::String(text) => Some(text.trim().to_string()),
+        Value::Object(entry) => match entry.get("type").and_then(Value::as_str) {
+            Some("text") => Some(entry.get("text")?.as_str()?.trim().to_string()),
+            Some("structured-content") => {
+                let mut text = String::new();
+                push_content(entry.get("content")?, &mut text);
+                Some(text.trim().to_string())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Appends the text of structured content, breaking lines at `<br>` and around blocks.
+fn push_content(content: &Value, out: &mut String) {
+    match content {
+        Value::String(text) => out.push_str(text),
+        Value::Array(items) => items.iter().for_each(|item| push_content(item, out)),
+        Value::Object(node) => {
+            let tag = node.get("tag").and_then(Value::as_str).unwrap_or("");
+            if tag == "br" {
+                out.push('\n');
+                return;
+            }
+            if matches!(tag, "img" | "rt" | "rp") {
+                return;
+            }
+            let block = matches!(tag, "div" | "li" | "ol" | "ul" | "p" | "tr" | "details");
+            if block && !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            if let Some(content) = node.get("content") {
+                push_content(content, out);
+            }
+            if block && !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        _ => {}
+    }
+}